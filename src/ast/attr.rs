@@ -0,0 +1,203 @@
+use super::*;
+use std::str::FromStr;
+
+impl Attr {
+    /// Whether `class` is among the classes.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.classes.iter().any(|c| c.as_ref() == class)
+    }
+
+    /// Add `class` unless it is already present. Returns whether it was added.
+    pub fn add_class(&mut self, class: &str) -> bool {
+        if self.has_class(class) {
+            false
+        } else {
+            self.classes.push_back(InternedString::from(class));
+            true
+        }
+    }
+
+    /// Remove every occurrence of `class`. Returns whether any were removed.
+    pub fn remove_class(&mut self, class: &str) -> bool {
+        let len = self.classes.len();
+        self.classes.retain(|c| c.as_ref() != class);
+        self.classes.len() != len
+    }
+
+    /// The value of the first attribute named `key`.
+    pub fn get(&self, key: &str) -> Option<&Rope> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k.as_ref() == key)
+            .map(|(_, v)| v)
+    }
+
+    /// The value of the first attribute named `key`, as a `String`.
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        self.get(key).map(|v| v.chars().collect())
+    }
+
+    /// Set the attribute `key` to `value`, returning its previous value.
+    ///
+    /// An existing attribute keeps its position; any later duplicates of it
+    /// are removed. A new attribute is appended.
+    pub fn set(&mut self, key: &str, value: &str) -> Option<Rope> {
+        self.remove_duplicates(key);
+        match self.attrs.iter_mut().find(|(k, _)| k.as_ref() == key) {
+            Some((_, v)) => Some(std::mem::replace(v, Rope::from(value))),
+            None => {
+                self.attrs
+                    .push_back((InternedString::from(key), Rope::from(value)));
+                None
+            }
+        }
+    }
+
+    /// Remove every attribute named `key`, returning the value of the first.
+    pub fn remove(&mut self, key: &str) -> Option<Rope> {
+        let index = self.attrs.iter().position(|(k, _)| k.as_ref() == key)?;
+        self.remove_duplicates(key);
+        Some(self.attrs.remove(index).1)
+    }
+
+    /// Remove all but the first attribute named `key`.
+    fn remove_duplicates(&mut self, key: &str) {
+        let mut seen = false;
+        self.attrs.retain(|(k, _)| {
+            if k.as_ref() != key {
+                true
+            } else {
+                !std::mem::replace(&mut seen, true)
+            }
+        });
+    }
+
+    /// The value of the attribute `key`, interpreted as a boolean.
+    ///
+    /// `true`, `yes`, `on` and `1` are true; `false`, `no`, `off` and `0` are
+    /// false, all case-insensitively. Other values, and a missing attribute,
+    /// give `None`.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        let value = self.get_str(key)?;
+        match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Some(true),
+            "false" | "no" | "off" | "0" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// The value of the attribute `key`, parsed with [`FromStr`].
+    ///
+    /// For example, `attr.get_parsed::<i32>("startFrom")`. Surrounding
+    /// whitespace is ignored.
+    pub fn get_parsed<T>(&self, key: &str) -> Option<Result<T, T::Err>>
+    where
+        T: FromStr,
+    {
+        self.get_str(key).map(|v| v.trim().parse())
+    }
+
+    /// The value of the attribute `key`, parsed as a [`Dimension`].
+    pub fn get_dimension(&self, key: &str) -> Option<Result<Dimension, ParseDimensionError>> {
+        self.get_parsed(key)
+    }
+}
+
+/// A length, as given by a `width` or `height` attribute.
+///
+/// This corresponds to Pandoc's `Dimension` type, and is parsed the same way
+/// as Pandoc parses these attributes: a number with no unit is a number of
+/// pixels, and points and picas are converted to inches.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Dimension {
+    Pixel(i64),
+    Centimeter(f64),
+    Millimeter(f64),
+    Inch(f64),
+    Percent(f64),
+    Em(f64),
+}
+
+/// Error returned when a string cannot be parsed as a [`Dimension`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDimensionError(String);
+
+impl Display for ParseDimensionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid dimension: {:?}", self.0)
+    }
+}
+
+impl Error for ParseDimensionError {}
+
+impl FromStr for Dimension {
+    type Err = ParseDimensionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseDimensionError(s.to_string());
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let a: f64 = number.parse().map_err(|_| err())?;
+
+        match unit.trim() {
+            "" | "px" => Ok(Dimension::Pixel(a.floor() as i64)),
+            "cm" => Ok(Dimension::Centimeter(a)),
+            "mm" => Ok(Dimension::Millimeter(a)),
+            "in" | "inch" => Ok(Dimension::Inch(a)),
+            "%" => Ok(Dimension::Percent(a)),
+            "pt" => Ok(Dimension::Inch(a / 72.0)),
+            "pc" => Ok(Dimension::Inch(a / 6.0)),
+            "em" => Ok(Dimension::Em(a)),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl Display for Dimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dimension::Pixel(a) => write!(f, "{}px", a),
+            Dimension::Centimeter(a) => write!(f, "{}cm", a),
+            Dimension::Millimeter(a) => write!(f, "{}mm", a),
+            Dimension::Inch(a) => write!(f, "{}in", a),
+            Dimension::Percent(a) => write!(f, "{}%", a),
+            Dimension::Em(a) => write!(f, "{}em", a),
+        }
+    }
+}
+
+#[test]
+fn test_attr_helpers() {
+    let mut attr = Attr::builder()
+        .class("a")
+        .attr("width", "50%")
+        .attr("height", "3in")
+        .attr("startFrom", "4")
+        .attr("numbers", "Yes")
+        .attr("width", "10")
+        .build();
+
+    ::std::assert!(attr.has_class("a"));
+    ::std::assert!(!attr.add_class("a"));
+    ::std::assert!(attr.add_class("b"));
+    ::std::assert!(attr.remove_class("a"));
+    ::std::assert_eq!(attr.classes, Vector::unit(InternedString::from("b")));
+
+    ::std::assert_eq!(
+        attr.get_dimension("width"),
+        Some(Ok(Dimension::Percent(50.0)))
+    );
+    ::std::assert_eq!(attr.get_dimension("height"), Some(Ok(Dimension::Inch(3.0))));
+    ::std::assert_eq!(attr.get_parsed::<i32>("startFrom"), Some(Ok(4)));
+    ::std::assert_eq!(attr.get_bool("numbers"), Some(true));
+    ::std::assert_eq!(attr.get_bool("missing"), None);
+
+    ::std::assert_eq!(attr.set("width", "2cm"), Some(Rope::from("50%")));
+    ::std::assert_eq!(attr.attrs.len(), 4);
+    ::std::assert_eq!(attr.get_str("width").as_deref(), Some("2cm"));
+    ::std::assert_eq!(attr.remove("height"), Some(Rope::from("3in")));
+    ::std::assert_eq!(attr.get("height"), None);
+}
@@ -0,0 +1,455 @@
+use super::*;
+
+/// Construct [`Blocks`] from a list of expressions, each of which may be
+/// anything convertible into a [`Block`].
+///
+/// ```
+/// # use pandoc_filter::*;
+/// let blocks: Blocks<NullScheme> = blocks![
+///     Block::header(1, Attr::default(), "Introduction"),
+///     Block::para("Hello, world!"),
+/// ];
+/// ```
+#[macro_export]
+macro_rules! blocks {
+    ($($block:expr),* $(,)?) => {
+        <$crate::Blocks<_> as ::core::iter::FromIterator<$crate::Block<_>>>::from_iter([
+            $(<$crate::Block<_> as ::core::convert::From<_>>::from($block)),*
+        ])
+    };
+}
+
+/// Construct [`Inlines`] from a list of expressions, each of which may be
+/// anything convertible into [`Inlines`]. The results are concatenated.
+///
+/// String literals are broken into words by [`Inlines::from_text`].
+///
+/// ```
+/// # use pandoc_filter::*;
+/// let inlines: Inlines<NullScheme> = inlines!["Hello, ", Inline::emph("world"), "!"];
+/// ```
+#[macro_export]
+macro_rules! inlines {
+    ($($inline:expr),* $(,)?) => {
+        <$crate::Inlines<_> as ::core::iter::FromIterator<$crate::Inlines<_>>>::from_iter([
+            $(<$crate::Inlines<_> as ::core::convert::From<_>>::from($inline)),*
+        ])
+    };
+}
+
+impl<A> Block<A>
+where
+    A: DecorationScheme,
+{
+    pub fn plain(inlines: impl Into<Inlines<A>>) -> Self {
+        BlockContent::Plain(inlines.into()).into()
+    }
+
+    pub fn para(inlines: impl Into<Inlines<A>>) -> Self {
+        BlockContent::Para(inlines.into()).into()
+    }
+
+    pub fn line_block(lines: impl IntoIterator<Item = Inlines<A>>) -> Self {
+        BlockContent::LineBlock(Vector::from_iter(lines)).into()
+    }
+
+    pub fn code_block(attr: Attr, code: &str) -> Self {
+        BlockContent::CodeBlock(attr, Rope::from(code)).into()
+    }
+
+    pub fn raw_block(format: &str, content: &str) -> Self {
+        BlockContent::RawBlock(InternedString::from(format), Rope::from(content)).into()
+    }
+
+    pub fn block_quote(blocks: impl Into<Blocks<A>>) -> Self {
+        BlockContent::BlockQuote(blocks.into()).into()
+    }
+
+    pub fn ordered_list(attrs: ListAttributes, items: impl IntoIterator<Item = Blocks<A>>) -> Self {
+        BlockContent::OrderedList(attrs, Vector::from_iter(items)).into()
+    }
+
+    pub fn bullet_list(items: impl IntoIterator<Item = Blocks<A>>) -> Self {
+        BlockContent::BulletList(Vector::from_iter(items)).into()
+    }
+
+    pub fn definition_list(
+        items: impl IntoIterator<Item = (Inlines<A>, Vector<Blocks<A>>)>,
+    ) -> Self {
+        BlockContent::DefinitionList(Vector::from_iter(items)).into()
+    }
+
+    pub fn header(level: i32, attr: Attr, inlines: impl Into<Inlines<A>>) -> Self {
+        BlockContent::Header(level, attr, inlines.into()).into()
+    }
+
+    pub fn horizontal_rule() -> Self {
+        BlockContent::HorizontalRule.into()
+    }
+
+    pub fn table(table: Table<A>) -> Self {
+        BlockContent::Table(table).into()
+    }
+
+    pub fn figure(attr: Attr, caption: Caption<A>, blocks: impl Into<Blocks<A>>) -> Self {
+        BlockContent::Figure(attr, caption, blocks.into()).into()
+    }
+
+    pub fn div(attr: Attr, blocks: impl Into<Blocks<A>>) -> Self {
+        BlockContent::Div(attr, blocks.into()).into()
+    }
+}
+
+impl<A> Inline<A, ExpandedLeaf>
+where
+    A: DecorationScheme,
+{
+    /// A single `Str`. Unlike [`Inlines::from_text`], this does not break the
+    /// text into words.
+    pub fn str(text: &str) -> Self {
+        InlineContent::Leaf(ExpandedLeaf::Str(Rope::from(text))).into()
+    }
+
+    pub fn space() -> Self {
+        InlineContent::Leaf(ExpandedLeaf::Space).into()
+    }
+
+    pub fn soft_break() -> Self {
+        InlineContent::Leaf(ExpandedLeaf::SoftBreak).into()
+    }
+
+    pub fn line_break() -> Self {
+        InlineContent::Leaf(ExpandedLeaf::LineBreak).into()
+    }
+
+    pub fn emph(inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::Emph(inlines.into()).into()
+    }
+
+    pub fn underline(inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::Underline(inlines.into()).into()
+    }
+
+    pub fn strong(inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::Strong(inlines.into()).into()
+    }
+
+    pub fn strikeout(inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::Strikeout(inlines.into()).into()
+    }
+
+    pub fn superscript(inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::Superscript(inlines.into()).into()
+    }
+
+    pub fn subscript(inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::Subscript(inlines.into()).into()
+    }
+
+    pub fn small_caps(inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::SmallCaps(inlines.into()).into()
+    }
+
+    pub fn quoted(quote_type: QuoteType, inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::Quoted(quote_type, inlines.into()).into()
+    }
+
+    pub fn cite(
+        citations: impl IntoIterator<Item = Citation<A>>,
+        inlines: impl Into<Inlines<A>>,
+    ) -> Self {
+        InlineContent::Cite(Vector::from_iter(citations), inlines.into()).into()
+    }
+
+    pub fn code(attr: Attr, code: &str) -> Self {
+        InlineContent::Code(attr, Rope::from(code)).into()
+    }
+
+    pub fn math(math_type: MathType, tex: &str) -> Self {
+        InlineContent::Math(math_type, Rope::from(tex)).into()
+    }
+
+    pub fn raw_inline(format: &str, content: &str) -> Self {
+        InlineContent::RawInline(InternedString::from(format), Rope::from(content)).into()
+    }
+
+    pub fn link(attr: Attr, inlines: impl Into<Inlines<A>>, url: &str, title: &str) -> Self {
+        InlineContent::Link(attr, inlines.into(), Target::new(url, title)).into()
+    }
+
+    pub fn image(attr: Attr, alt: impl Into<Inlines<A>>, url: &str, title: &str) -> Self {
+        InlineContent::Image(attr, alt.into(), Target::new(url, title)).into()
+    }
+
+    pub fn note(blocks: impl Into<Blocks<A>>) -> Self {
+        InlineContent::Note(blocks.into()).into()
+    }
+
+    pub fn span(attr: Attr, inlines: impl Into<Inlines<A>>) -> Self {
+        InlineContent::Span(attr, inlines.into()).into()
+    }
+}
+
+impl Target {
+    pub fn new(url: &str, title: &str) -> Self {
+        Target {
+            url: Rope::from(url),
+            title: Rope::from(title),
+        }
+    }
+}
+
+impl<A> Caption<A>
+where
+    A: DecorationScheme,
+{
+    pub fn new(short: Option<Inlines<A>>, full: impl Into<Blocks<A>>) -> Self {
+        Caption {
+            decoration: None,
+            short,
+            full: full.into(),
+        }
+    }
+}
+
+impl<A> Citation<A>
+where
+    A: DecorationScheme,
+{
+    /// A normal citation of `id`, with no prefix or suffix.
+    pub fn new(id: &str) -> Self {
+        Citation {
+            decoration: None,
+            id: InternedString::from(id),
+            prefix: Inlines::default(),
+            suffix: Inlines::default(),
+            mode: CitationMode::Normal,
+            num: 0,
+            hash: 0,
+        }
+    }
+}
+
+impl Attr {
+    /// Start building an `Attr`.
+    pub fn builder() -> AttrBuilder {
+        AttrBuilder::default()
+    }
+}
+
+/// Builder for [`Attr`].
+///
+/// ```
+/// # use pandoc_filter::*;
+/// let attr = Attr::builder()
+///     .id("fig:plot")
+///     .class("wide")
+///     .attr("width", "50%")
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttrBuilder {
+    attr: Attr,
+}
+
+impl AttrBuilder {
+    pub fn id(mut self, identifier: &str) -> Self {
+        self.attr.identifier = InternedString::from(identifier);
+        self
+    }
+
+    pub fn class(mut self, class: &str) -> Self {
+        self.attr.classes.push_back(InternedString::from(class));
+        self
+    }
+
+    pub fn attr(mut self, key: &str, value: &str) -> Self {
+        self.attr
+            .attrs
+            .push_back((InternedString::from(key), Rope::from(value)));
+        self
+    }
+
+    pub fn build(self) -> Attr {
+        self.attr
+    }
+}
+
+impl From<AttrBuilder> for Attr {
+    fn from(builder: AttrBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl<A> From<BlockContent<A>> for Block<A>
+where
+    A: DecorationScheme,
+{
+    fn from(value: BlockContent<A>) -> Self {
+        Block {
+            decoration: None,
+            content: value,
+        }
+    }
+}
+
+impl<A, L> From<InlineContent<A, L>> for Inline<A, L>
+where
+    A: DecorationScheme,
+{
+    fn from(value: InlineContent<A, L>) -> Self {
+        Inline {
+            decoration: None,
+            content: value,
+        }
+    }
+}
+
+impl<A> From<Vector<Block<A>>> for Blocks<A>
+where
+    A: DecorationScheme,
+{
+    fn from(value: Vector<Block<A>>) -> Self {
+        Blocks {
+            decoration: None,
+            content: value,
+        }
+    }
+}
+
+impl<A> From<Block<A>> for Blocks<A>
+where
+    A: DecorationScheme,
+{
+    fn from(value: Block<A>) -> Self {
+        Vector::unit(value).into()
+    }
+}
+
+impl<A> FromIterator<Block<A>> for Blocks<A>
+where
+    A: DecorationScheme,
+{
+    fn from_iter<T: IntoIterator<Item = Block<A>>>(iter: T) -> Self {
+        Vector::from_iter(iter).into()
+    }
+}
+
+impl<A> Default for Blocks<A>
+where
+    A: DecorationScheme,
+{
+    fn default() -> Self {
+        Vector::new().into()
+    }
+}
+
+impl<A> From<InlinesContent<A>> for Inlines<A>
+where
+    A: DecorationScheme,
+{
+    fn from(value: InlinesContent<A>) -> Self {
+        Inlines {
+            decoration: None,
+            content: value,
+        }
+    }
+}
+
+impl<A> From<Inline<A, ExpandedLeaf>> for Inlines<A>
+where
+    A: DecorationScheme,
+{
+    fn from(value: Inline<A, ExpandedLeaf>) -> Self {
+        InlinesContent::Expanded(Vector::unit(value)).into()
+    }
+}
+
+impl<A> From<&str> for Inlines<A>
+where
+    A: DecorationScheme,
+{
+    fn from(value: &str) -> Self {
+        Inlines::from_text(value)
+    }
+}
+
+impl<A> From<String> for Inlines<A>
+where
+    A: DecorationScheme,
+{
+    fn from(value: String) -> Self {
+        Inlines::from_text(&value)
+    }
+}
+
+impl<A> FromIterator<Inline<A, ExpandedLeaf>> for Inlines<A>
+where
+    A: DecorationScheme,
+{
+    fn from_iter<T: IntoIterator<Item = Inline<A, ExpandedLeaf>>>(iter: T) -> Self {
+        InlinesContent::from_iter(iter).into()
+    }
+}
+
+/// Concatenation. The result is condensed if every part is, and expanded
+/// otherwise.
+impl<A> FromIterator<Inlines<A>> for Inlines<A>
+where
+    A: DecorationScheme,
+{
+    fn from_iter<T: IntoIterator<Item = Inlines<A>>>(iter: T) -> Self {
+        let parts = Vec::from_iter(iter);
+        if parts
+            .iter()
+            .all(|p| matches!(p.content, InlinesContent::Condensed(_)))
+        {
+            let mut content: Vector<Inline<A, CondensedLeaf>> = Vector::new();
+            for inline in parts.into_iter().flat_map(Inlines::into_condensed) {
+                match (content.back_mut(), inline.content) {
+                    (
+                        Some(Inline {
+                            content: InlineContent::Leaf(last),
+                            ..
+                        }),
+                        InlineContent::Leaf(leaf),
+                    ) => {
+                        for ch in leaf.chars() {
+                            last.push_back(ch);
+                        }
+                    }
+                    (_, content_) => content.push_back(Inline {
+                        decoration: inline.decoration,
+                        content: content_,
+                    }),
+                }
+            }
+            InlinesContent::Condensed(content).into()
+        } else {
+            InlinesContent::from_iter(parts.into_iter().flat_map(Inlines::into_expanded)).into()
+        }
+    }
+}
+
+impl<A> Default for Inlines<A>
+where
+    A: DecorationScheme,
+{
+    fn default() -> Self {
+        InlinesContent::Condensed(Vector::new()).into()
+    }
+}
+
+#[test]
+fn test_build() {
+    let built: Blocks<NullScheme> = blocks![
+        Block::header(1, Attr::builder().id("intro").build(), "Intro"),
+        Block::para(inlines!["Hello ", Inline::emph("big"), " world"]),
+    ];
+    let input = r#"[
+        {"t":"Header","c":[1,["intro",[],[]],[{"t":"Str","c":"Intro"}]]},
+        {"t":"Para","c":[{"t":"Str","c":"Hello"},{"t":"Space"},{"t":"Emph","c":[{"t":"Str","c":"big"}]},{"t":"Space"},{"t":"Str","c":"world"}]}
+    ]"#;
+    let input_value: ::serde_json::Value = ::serde_json::from_str(input).unwrap();
+    ::std::assert_eq!(::serde_json::to_value(&built).unwrap(), input_value);
+}
@@ -15,7 +15,13 @@ use std::vec::Vec;
 
 use crate::interned::InternedString;
 
+mod attr;
+mod build;
 mod serde;
+mod text;
+
+pub use attr::{Dimension, ParseDimensionError};
+pub use build::AttrBuilder;
 
 /// This crate's supported API version.
 ///
@@ -587,7 +593,9 @@ pub enum ExpandedLeaf {
 }
 
 /// Attributes.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
 #[serde(from = "serde::AttrSerde", into = "serde::AttrSerde")]
 pub struct Attr {
     pub identifier: InternedString,
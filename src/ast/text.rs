@@ -0,0 +1,111 @@
+use super::*;
+
+impl<A> Inlines<A>
+where
+    A: DecorationScheme,
+{
+    /// Build inlines from plain text, breaking it into words the way Pandoc's
+    /// `text` builder does.
+    ///
+    /// Each run of spaces, tabs, carriage returns and newlines becomes a single
+    /// `Space`, or a `SoftBreak` if the run contains a line ending. Every other
+    /// character, including a non-breaking space, is kept within a `Str`.
+    ///
+    /// The result is condensed. Use [`Inlines::expand`] to obtain the expanded
+    /// representation.
+    pub fn from_text(text: &str) -> Self {
+        Self::from_leaf(condense_text(text, false))
+    }
+
+    /// Build inlines from plain text, preserving its line structure.
+    ///
+    /// This is like [`Inlines::from_text`], except that each line ending
+    /// becomes a `LineBreak`, and spaces and tabs adjacent to a line ending
+    /// are dropped.
+    pub fn from_text_with_breaks(text: &str) -> Self {
+        Self::from_leaf(condense_text(text, true))
+    }
+
+    fn from_leaf(leaf: Rope) -> Self {
+        let mut content = Vector::new();
+        if !leaf.is_empty() {
+            content.push_back(Inline {
+                decoration: None,
+                content: InlineContent::Leaf(leaf),
+            });
+        }
+
+        Inlines {
+            decoration: None,
+            content: InlinesContent::Condensed(content),
+        }
+    }
+}
+
+fn condense_text(text: &str, breaks: bool) -> Rope {
+    let mut leaf = Rope::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ' ' | '\t' | '\r' | '\n' => {
+                let mut prev = ch;
+                let mut line_endings = usize::from(ch == '\r' || ch == '\n');
+                while let Some(&next) = chars.peek() {
+                    match next {
+                        ' ' | '\t' => {}
+                        // Count "\r\n" as a single line ending.
+                        '\n' if prev == '\r' => {}
+                        '\r' | '\n' => line_endings += 1,
+                        _ => break,
+                    }
+                    prev = next;
+                    chars.next();
+                }
+
+                if line_endings == 0 {
+                    leaf.push_back(' ');
+                } else if breaks {
+                    for _ in 0..line_endings {
+                        leaf.push_back('\n');
+                    }
+                } else {
+                    leaf.push_back('\r');
+                }
+            }
+            CONDENSED_LITERAL_SPACE | CONDENSED_LITERAL_CR | CONDENSED_LITERAL_NEWLINE => {
+                leaf.push_back(char::REPLACEMENT_CHARACTER);
+            }
+            _ => leaf.push_back(ch),
+        }
+    }
+
+    leaf
+}
+
+#[test]
+fn test_from_text() {
+    let mut inlines: Inlines<NullScheme> = Inlines::from_text("Hello  wide\u{a0}\n world ");
+    ::std::assert_eq!(
+        Vec::from_iter(inlines.expand().iter().map(|i| &i.content)),
+        vec![
+            &InlineContent::Leaf(ExpandedLeaf::Str(Rope::from("Hello"))),
+            &InlineContent::Leaf(ExpandedLeaf::Space),
+            &InlineContent::Leaf(ExpandedLeaf::Str(Rope::from("wide\u{a0}"))),
+            &InlineContent::Leaf(ExpandedLeaf::SoftBreak),
+            &InlineContent::Leaf(ExpandedLeaf::Str(Rope::from("world"))),
+            &InlineContent::Leaf(ExpandedLeaf::Space),
+        ]
+    );
+
+    let mut inlines: Inlines<NullScheme> = Inlines::from_text_with_breaks("one \r\n\r\ntwo");
+    ::std::assert_eq!(
+        Vec::from_iter(inlines.expand().iter().map(|i| &i.content)),
+        vec![
+            &InlineContent::Leaf(ExpandedLeaf::Str(Rope::from("one"))),
+            &InlineContent::Leaf(ExpandedLeaf::LineBreak),
+            &InlineContent::Leaf(ExpandedLeaf::LineBreak),
+            &InlineContent::Leaf(ExpandedLeaf::Str(Rope::from("two"))),
+        ]
+    );
+}
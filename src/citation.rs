@@ -0,0 +1,368 @@
+//! Inventory and rewriting of citations
+
+use imbl::Vector;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+
+use crate::ast::*;
+use crate::interned::InternedString;
+use crate::stringify::Stringify;
+use crate::visit::{Map, Query};
+
+/// A citation found by [`Pandoc::citations`].
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone
+)]
+pub struct CitationRef<'a, A>
+where
+    A: DecorationScheme,
+{
+    /// The citation, whose `mode`, `prefix`, `suffix` and `num` describe how
+    /// it is cited.
+    pub citation: &'a Citation<A>,
+    /// The position of the enclosing `Cite` among the document's `Cite`
+    /// inlines, counting from 0 in document order.
+    pub cite_index: usize,
+    /// The position of the citation within the enclosing `Cite`.
+    pub index: usize,
+    /// The text of the enclosing `Cite`, as written in the source document.
+    pub cite_text: &'a Inlines<A>,
+    /// The blocks enclosing the citation, outermost first.
+    pub ancestors: Vec<&'a Block<A>>,
+}
+
+/// Rules for [`Pandoc::rewrite_citations`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CitationRewrite {
+    /// New keys for old ones. Several old keys may map to the same new key.
+    /// Renames are applied once, so they are not chained.
+    pub renames: HashMap<String, String>,
+    /// If set, citations whose key, after renaming, is not in this set are
+    /// removed, with a warning.
+    pub known: Option<HashSet<String>>,
+    /// New modes for old ones.
+    pub modes: HashMap<CitationMode, CitationMode>,
+}
+
+/// A problem found by [`Pandoc::rewrite_citations`] or [`Pandoc::citeproc`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CitationWarning {
+    /// A citation refers to an unknown key. [`Pandoc::rewrite_citations`]
+    /// removes such citations.
+    UnknownKey(InternedString),
+}
+
+impl Display for CitationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CitationWarning::UnknownKey(id) => write!(f, "unknown citation key '{}'", id),
+        }
+    }
+}
+
+impl Error for CitationWarning {}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    /// Every citation in the document, in document order. This includes
+    /// citations within notes and within the prefixes and suffixes of other
+    /// citations.
+    pub fn citations(&self) -> Vec<CitationRef<'_, A>> {
+        let mut collector = CitationCollector {
+            cites: 0,
+            citations: Vec::new(),
+            ancestors: Vec::new(),
+        };
+        self.blocks.query(&mut collector);
+        collector.citations
+    }
+
+    /// Apply `f` to every citation in the document, removing those for which
+    /// it returns `false`. A `Cite` whose citations are all removed is itself
+    /// removed, along with its text. A `Cite` whose citations are otherwise
+    /// changed has its text replaced by their Pandoc Markdown, as in
+    /// `[see @doe, p. 4; -@roe]`.
+    pub fn map_citations<F>(&mut self, f: F)
+    where
+        F: FnMut(&mut Citation<A>) -> bool,
+    {
+        let blocks = std::mem::take(&mut self.blocks);
+        self.blocks = blocks.walk(&mut CitationMapper(f));
+    }
+
+    /// Rename citation keys, remove citations of unknown keys and change
+    /// citation modes according to `rules`. See [`Pandoc::map_citations`].
+    ///
+    /// Returns a warning for each citation removed.
+    pub fn rewrite_citations(&mut self, rules: &CitationRewrite) -> Vec<CitationWarning> {
+        let mut warnings = Vec::new();
+
+        self.map_citations(|citation| {
+            if let Some(id) = rules.renames.get(citation.id.as_ref()) {
+                citation.id = InternedString::from(id.as_str());
+            }
+            if let Some(mode) = rules.modes.get(&citation.mode) {
+                citation.mode = mode.clone();
+            }
+
+            let known = rules
+                .known
+                .as_ref()
+                .is_none_or(|known| known.contains(citation.id.as_ref()));
+            if !known {
+                warnings.push(CitationWarning::UnknownKey(citation.id.clone()));
+            }
+            known
+        });
+
+        warnings
+    }
+}
+
+struct CitationCollector<'a, A>
+where
+    A: DecorationScheme,
+{
+    cites: usize,
+    citations: Vec<CitationRef<'a, A>>,
+    ancestors: Vec<&'a Block<A>>,
+}
+
+impl<'a, A> CitationCollector<'a, A>
+where
+    A: DecorationScheme,
+{
+    fn add<L>(&mut self, inline: &'a InlineContent<A, L>) {
+        if let InlineContent::Cite(citations, text) = inline {
+            for (index, citation) in citations.iter().enumerate() {
+                self.citations.push(CitationRef {
+                    citation,
+                    cite_index: self.cites,
+                    index,
+                    cite_text: text,
+                    ancestors: self.ancestors.clone(),
+                });
+            }
+            self.cites += 1;
+        }
+    }
+}
+
+impl<'a, A> Query<'a, A> for CitationCollector<'a, A>
+where
+    A: DecorationScheme,
+{
+    fn enter_block(&mut self, block: &'a Block<A>) {
+        self.ancestors.push(block);
+    }
+
+    fn query_block(&mut self, _block: &'a Block<A>) {
+        self.ancestors.pop();
+    }
+
+    fn enter_inline_condensed(&mut self, inline: &'a Inline<A, CondensedLeaf>) {
+        self.add(&inline.content);
+    }
+
+    fn enter_inline_expanded(&mut self, inline: &'a Inline<A, ExpandedLeaf>) {
+        self.add(&inline.content);
+    }
+}
+
+struct CitationMapper<F>(F);
+
+impl<F> CitationMapper<F> {
+    /// Map the citations of a `Cite`, returning whether any remain.
+    fn map<A, L>(&mut self, inline: &mut InlineContent<A, L>) -> bool
+    where
+        A: DecorationScheme,
+        F: FnMut(&mut Citation<A>) -> bool,
+    {
+        match inline {
+            InlineContent::Cite(citations, text) => {
+                let before = Vec::from_iter(citations.iter().map(citation_key));
+                *citations = std::mem::take(citations)
+                    .into_iter()
+                    .filter_map(|mut c| (self.0)(&mut c).then_some(c))
+                    .collect();
+                if before != Vec::from_iter(citations.iter().map(citation_key)) {
+                    *text = cite_text(citations);
+                }
+                !citations.is_empty()
+            }
+            _ => true,
+        }
+    }
+}
+
+impl<A, F> Map<A> for CitationMapper<F>
+where
+    A: DecorationScheme,
+    F: FnMut(&mut Citation<A>) -> bool,
+{
+    fn map_inlines(&mut self, mut inlines: Inlines<A>) -> Inlines<A> {
+        let mut emptied = false;
+        match &mut inlines.content {
+            InlinesContent::Condensed(v) => {
+                for inline in v.iter_mut() {
+                    emptied |= !self.map(&mut inline.content);
+                }
+            }
+            InlinesContent::Expanded(v) => {
+                for inline in v.iter_mut() {
+                    emptied |= !self.map(&mut inline.content);
+                }
+            }
+        }
+
+        if emptied {
+            inlines
+                .expand()
+                .retain(|i| !matches!(&i.content, InlineContent::Cite(c, _) if c.is_empty()));
+        }
+        inlines
+    }
+}
+
+/// What a citation's Markdown depends on.
+fn citation_key<A>(citation: &Citation<A>) -> (InternedString, CitationMode, String, String)
+where
+    A: DecorationScheme,
+{
+    (
+        citation.id.clone(),
+        citation.mode.clone(),
+        citation.prefix.stringify(),
+        citation.suffix.stringify(),
+    )
+}
+
+/// The Pandoc Markdown for `citations`, as in `@doe [p. 4]` or
+/// `[see @doe, p. 4; -@roe]`. Author-in-text citations come first.
+fn cite_text<A>(citations: &Vector<Citation<A>>) -> Inlines<A>
+where
+    A: DecorationScheme,
+{
+    let mut parts = Vec::new();
+    for citation in citations
+        .iter()
+        .filter(|c| c.mode == CitationMode::AuthorInText)
+    {
+        if !parts.is_empty() {
+            parts.push(Inline::space().into());
+        }
+        parts.push(Inlines::from_text(&format!("@{}", citation.id)));
+        if !citation.suffix.stringify().is_empty() {
+            parts.extend([
+                Inlines::from_text(" ["),
+                citation.suffix.clone(),
+                Inlines::from_text("]"),
+            ]);
+        }
+    }
+
+    let bracketed = Vec::from_iter(
+        citations
+            .iter()
+            .filter(|c| c.mode != CitationMode::AuthorInText),
+    );
+    if !bracketed.is_empty() {
+        if !parts.is_empty() {
+            parts.push(Inline::space().into());
+        }
+        parts.push(Inlines::from_text("["));
+        for (i, citation) in bracketed.into_iter().enumerate() {
+            if i > 0 {
+                parts.push(Inlines::from_text("; "));
+            }
+            if !citation.prefix.stringify().is_empty() {
+                parts.extend([citation.prefix.clone(), Inline::space().into()]);
+            }
+            let suppress = match citation.mode {
+                CitationMode::SuppressAuthor => "-",
+                _ => "",
+            };
+            parts.push(Inlines::from_text(&format!("{}@{}", suppress, citation.id)));
+            parts.push(citation.suffix.clone());
+        }
+        parts.push(Inlines::from_text("]"));
+    }
+    Inlines::from_iter(parts)
+}
+
+#[test]
+fn test_citations() {
+    let cite = |id: &str, mode: &str| {
+        ::serde_json::json!({"citationId":id,"citationPrefix":[],"citationSuffix":[],
+            "citationMode":{"t":mode},"citationNoteNum":1,"citationHash":0})
+    };
+    let input = ::serde_json::json!({"pandoc-api-version":[1,23,1],"meta":{},"blocks":[
+        {"t":"Para","c":[
+            {"t":"Cite","c":[[cite("old-a","AuthorInText"),cite("b","NormalCitation")],[{"t":"Str","c":"@old-a"}]]},
+            {"t":"Note","c":[{"t":"Para","c":[
+                {"t":"Cite","c":[[cite("gone","NormalCitation")],[{"t":"Str","c":"[@gone]"}]]}
+            ]}]}
+        ]}
+    ]});
+    let mut doc: Pandoc<NullScheme> = ::serde_json::from_str(&input.to_string()).unwrap();
+
+    let citations = doc.citations();
+    ::std::assert_eq!(
+        Vec::from_iter(citations.iter().map(|c| (
+            c.citation.id.to_string(),
+            c.cite_index,
+            c.index,
+            c.ancestors.len()
+        ))),
+        vec![
+            ("old-a".to_string(), 0, 0, 1),
+            ("b".to_string(), 0, 1, 1),
+            ("gone".to_string(), 1, 0, 2),
+        ]
+    );
+
+    let warnings = doc.rewrite_citations(&CitationRewrite {
+        renames: HashMap::from([("old-a".to_string(), "a".to_string())]),
+        known: Some(HashSet::from(["a".to_string(), "b".to_string()])),
+        modes: HashMap::from([(CitationMode::AuthorInText, CitationMode::Normal)]),
+    });
+    ::std::assert_eq!(
+        warnings,
+        vec![CitationWarning::UnknownKey(InternedString::from("gone"))]
+    );
+    ::std::assert_eq!(
+        Vec::from_iter(
+            doc.citations()
+                .iter()
+                .map(|c| (c.citation.id.to_string(), c.citation.mode.clone()))
+        ),
+        vec![
+            ("a".to_string(), CitationMode::Normal),
+            ("b".to_string(), CitationMode::Normal),
+        ]
+    );
+    ::std::assert_eq!(doc.citations()[0].cite_text.stringify(), "[@a; @b]");
+    let citations: Vector<Citation<NullScheme>> = ::serde_json::from_value(::serde_json::json!([
+        {"citationId":"doe","citationPrefix":[{"t":"Str","c":"see"}],
+            "citationSuffix":[{"t":"Str","c":","},{"t":"Space"},{"t":"Str","c":"p."},{"t":"Space"},{"t":"Str","c":"4"}],
+            "citationMode":{"t":"NormalCitation"},"citationNoteNum":0,"citationHash":0},
+        cite("roe", "SuppressAuthor"),
+        cite("poe", "AuthorInText")
+    ]))
+    .unwrap();
+    ::std::assert_eq!(
+        cite_text(&citations).stringify(),
+        "@poe [see @doe, p. 4; -@roe]"
+    );
+    ::std::assert_eq!(
+        ::serde_json::to_value(&doc.blocks.content[0]).unwrap()["c"][1],
+        ::serde_json::json!({"t":"Note","c":[{"t":"Para","c":[]}]})
+    );
+}
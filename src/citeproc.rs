@@ -0,0 +1,577 @@
+//! A minimal citation processor
+
+use imbl::Vector;
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::citation::CitationWarning;
+use crate::interned::InternedString;
+use crate::meta::{meta_inlines, meta_text};
+use crate::stringify::Stringify;
+use crate::visit::Map;
+
+/// A built-in citation style for [`Pandoc::citeproc`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CitationStyle {
+    /// Citations like "(Doe 2020)", and a bibliography sorted by author, in
+    /// the manner of the Chicago author-date style.
+    #[default]
+    AuthorDate,
+    /// Citations like "[1]", and a bibliography numbered in order of first
+    /// citation, in the manner of the IEEE style.
+    Numeric,
+}
+
+/// Options for [`Pandoc::citeproc`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CiteprocOptions {
+    pub style: CitationStyle,
+    /// Link each citation to its entry in the bibliography, like Pandoc's
+    /// `link-citations` variable.
+    pub link_citations: bool,
+    /// Do not add a bibliography, like Pandoc's `suppress-bibliography`
+    /// variable.
+    pub suppress_bibliography: bool,
+}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    /// Render the document's citations from the CSL-JSON entries in its
+    /// `references` metadata field, as a lightweight stand-in for Pandoc's
+    /// `--citeproc`.
+    ///
+    /// The text of each `Cite` is replaced by its rendering in the given
+    /// style, according to the mode of its first citation:
+    ///
+    /// | Mode             | Author-date       | Numeric    |
+    /// |------------------|-------------------|------------|
+    /// | `Normal`         | (Doe 2020, 4)     | [1, 4]     |
+    /// | `AuthorInText`   | Doe (2020, 4)     | Doe [1, 4] |
+    /// | `SuppressAuthor` | (2020, 4)         | [1, 4]     |
+    ///
+    /// The bibliography lists the cited references in a `Div` with
+    /// identifier `refs` and class `references`, whose entries are `Div`s
+    /// with identifiers like `ref-doe` and class `csl-entry`. It replaces the
+    /// content of an existing `Div#refs`, or else is appended to the
+    /// document.
+    ///
+    /// Only the common fields of books, chapters and articles are rendered,
+    /// and works by the same author in the same year are not disambiguated.
+    /// Returns a warning for each citation of an unknown key; these are
+    /// rendered as "???" in bold.
+    pub fn citeproc(&mut self, options: &CiteprocOptions) -> Vec<CitationWarning> {
+        let references = self
+            .meta
+            .get(&InternedString::from("references"))
+            .map(read_references)
+            .unwrap_or_default();
+
+        let mut processor = Processor {
+            options,
+            references: HashMap::from_iter(references.iter().map(|r| (r.id.clone(), r))),
+            cited: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let blocks = std::mem::take(&mut self.blocks);
+        self.blocks = blocks.walk(&mut processor);
+
+        if !options.suppress_bibliography {
+            let mut placeholder = RefsPlaceholder {
+                bibliography: processor.bibliography(),
+                found: false,
+            };
+            let blocks = std::mem::take(&mut self.blocks);
+            self.blocks = blocks.walk(&mut placeholder);
+            if !placeholder.found && !placeholder.bibliography.is_empty() {
+                let attr = Attr::builder().id("refs").class("references").build();
+                self.blocks
+                    .content
+                    .push_back(Block::div(attr, placeholder.bibliography));
+            }
+        }
+
+        processor.warnings
+    }
+}
+
+struct Name {
+    family: String,
+    given: String,
+}
+
+struct Reference<A>
+where
+    A: DecorationScheme,
+{
+    id: String,
+    authors: Vec<Name>,
+    title: Option<Inlines<A>>,
+    container: Option<Inlines<A>>,
+    publisher: Option<String>,
+    volume: Option<String>,
+    issue: Option<String>,
+    page: Option<String>,
+    year: String,
+    url: Option<String>,
+    doi: Option<String>,
+}
+
+fn read_references<A>(value: &MetaValue<A>) -> Vec<Reference<A>>
+where
+    A: DecorationScheme,
+{
+    match &value.content {
+        MetaValueContent::List(items) => items.iter().filter_map(read_reference).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn read_reference<A>(value: &MetaValue<A>) -> Option<Reference<A>>
+where
+    A: DecorationScheme,
+{
+    let MetaValueContent::Map(fields) = &value.content else {
+        return None;
+    };
+    let get = |key: &str| fields.get(&InternedString::from(key));
+    let text = |key: &str| get(key).and_then(meta_text).filter(|t| !t.is_empty());
+
+    let authors = match get("author").or_else(|| get("editor")) {
+        Some(value) => match &value.content {
+            MetaValueContent::List(names) => names.iter().filter_map(read_name).collect(),
+            _ => read_name(value).into_iter().collect(),
+        },
+        None => Vec::new(),
+    };
+
+    Some(Reference {
+        id: text("id")?,
+        authors,
+        title: get("title").and_then(meta_inlines),
+        container: get("container-title").and_then(meta_inlines),
+        publisher: text("publisher"),
+        volume: text("volume"),
+        issue: text("issue"),
+        page: text("page"),
+        year: get("issued")
+            .and_then(read_year)
+            .unwrap_or_else(|| "n.d.".to_string()),
+        url: text("URL"),
+        doi: text("DOI"),
+    })
+}
+
+fn read_name<A>(value: &MetaValue<A>) -> Option<Name>
+where
+    A: DecorationScheme,
+{
+    match &value.content {
+        MetaValueContent::Map(fields) => {
+            let text = |key: &str| fields.get(&InternedString::from(key)).and_then(meta_text);
+            match text("family") {
+                Some(family) => Some(Name {
+                    family,
+                    given: text("given").unwrap_or_default(),
+                }),
+                None => text("literal").map(|family| Name {
+                    family,
+                    given: String::new(),
+                }),
+            }
+        }
+        _ => meta_text(value).map(|family| Name {
+            family,
+            given: String::new(),
+        }),
+    }
+}
+
+/// The year of a CSL date, given either as `date-parts` or as a string.
+fn read_year<A>(value: &MetaValue<A>) -> Option<String>
+where
+    A: DecorationScheme,
+{
+    let text = match &value.content {
+        MetaValueContent::Map(fields) => {
+            let mut parts = fields.get(&InternedString::from("date-parts"))?;
+            while let MetaValueContent::List(items) = &parts.content {
+                parts = items.front()?;
+            }
+            meta_text(parts)?
+        }
+        _ => meta_text(value)?,
+    };
+
+    let year: String = text
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    if year.is_empty() {
+        None
+    } else {
+        Some(year)
+    }
+}
+
+impl<A> Reference<A>
+where
+    A: DecorationScheme,
+{
+    /// The authors as cited: "Doe", "Doe and Roe" or "Doe et al.".
+    fn short_authors(&self) -> String {
+        match self.authors.as_slice() {
+            [] => self
+                .title
+                .as_ref()
+                .map(|t| t.stringify())
+                .unwrap_or_default(),
+            [a] => a.family.clone(),
+            [a, b] => format!("{} and {}", a.family, b.family),
+            [a, ..] => format!("{} et al.", a.family),
+        }
+    }
+
+    /// The authors as listed in the bibliography: "Doe, Jane, and John Roe".
+    fn full_authors(&self) -> String {
+        let names = Vec::from_iter(self.authors.iter().enumerate().map(|(i, n)| {
+            match (i, n.given.is_empty()) {
+                (_, true) => n.family.clone(),
+                (0, false) => format!("{}, {}", n.family, n.given),
+                (_, false) => format!("{} {}", n.given, n.family),
+            }
+        }));
+
+        match names.as_slice() {
+            [] => String::new(),
+            [a] => a.clone(),
+            [a, b] if !a.contains(',') => format!("{} and {}", a, b),
+            [init @ .., last] => format!("{}, and {}", init.join(", "), last),
+        }
+    }
+
+    fn sort_key(&self) -> (String, String, String) {
+        (
+            self.short_authors().to_lowercase(),
+            self.year.clone(),
+            self.title
+                .as_ref()
+                .map(|t| t.stringify())
+                .unwrap_or_default(),
+        )
+    }
+
+    /// The bibliography entry, in the manner of the Chicago author-date style:
+    ///
+    /// Doe, Jane. 2020. "Title." *Container* 1 (2): 3–4. Publisher. URL.
+    fn entry(&self, style: CitationStyle) -> Inlines<A> {
+        let mut out = Vec::new();
+        let sentence = |inlines: Inlines<A>, out: &mut Vec<Inlines<A>>| {
+            if !out.is_empty() {
+                out.push(Inline::space().into());
+            }
+            out.push(inlines);
+        };
+
+        let authors = self.full_authors();
+        if !authors.is_empty() {
+            sentence(Inlines::from_text(&terminate(&authors)), &mut out);
+        }
+        if style == CitationStyle::AuthorDate {
+            sentence(Inlines::from_text(&format!("{}.", self.year)), &mut out);
+        }
+        if let Some(title) = &self.title {
+            let title = Inlines::from_iter([title.clone(), Inlines::from_text(".")]);
+            match self.container {
+                Some(_) => sentence(Inline::quoted(QuoteType::Double, title).into(), &mut out),
+                None => sentence(Inline::emph(title).into(), &mut out),
+            }
+        }
+        if let Some(container) = &self.container {
+            let mut text = String::new();
+            if let Some(volume) = &self.volume {
+                text.push_str(&format!(" {}", volume));
+            }
+            if let Some(issue) = &self.issue {
+                text.push_str(&format!(" ({})", issue));
+            }
+            if let Some(page) = &self.page {
+                text.push_str(&format!(": {}", page.replace('-', "\u{2013}")));
+            }
+            text.push('.');
+            let container = Inlines::from_iter([
+                Inline::emph(container.clone()).into(),
+                Inlines::from_text(&text),
+            ]);
+            sentence(container, &mut out);
+        }
+        if let Some(publisher) = &self.publisher {
+            sentence(Inlines::from_text(&terminate(publisher)), &mut out);
+        }
+        if style == CitationStyle::Numeric {
+            sentence(Inlines::from_text(&format!("{}.", self.year)), &mut out);
+        }
+        let link = match (&self.doi, &self.url) {
+            (Some(doi), _) => Some(format!("https://doi.org/{}", doi)),
+            (None, Some(url)) => Some(url.clone()),
+            (None, None) => None,
+        };
+        if let Some(link) = link {
+            let link = Inlines::from_iter([
+                Inline::link(Attr::default(), Inline::str(&link), &link, "").into(),
+                Inlines::from_text("."),
+            ]);
+            sentence(link, &mut out);
+        }
+
+        Inlines::from_iter(out)
+    }
+}
+
+/// End a sentence with a period, unless it already has one.
+fn terminate(text: &str) -> String {
+    if text.ends_with(['.', '?', '!']) {
+        text.to_string()
+    } else {
+        format!("{}.", text)
+    }
+}
+
+struct Processor<'a, A>
+where
+    A: DecorationScheme,
+{
+    options: &'a CiteprocOptions,
+    references: HashMap<String, &'a Reference<A>>,
+    /// The keys of the cited references, in order of first citation.
+    cited: Vec<String>,
+    warnings: Vec<CitationWarning>,
+}
+
+impl<A> Processor<'_, A>
+where
+    A: DecorationScheme,
+{
+    fn render(&mut self, citations: &Vector<Citation<A>>) -> Inlines<A> {
+        let numeric = self.options.style == CitationStyle::Numeric;
+        let in_text = citations
+            .front()
+            .is_some_and(|c| c.mode == CitationMode::AuthorInText);
+
+        let mut items = Vec::new();
+        let mut author = None;
+        for (i, citation) in citations.iter().enumerate() {
+            let id = citation.id.to_string();
+            let Some(reference) = self.references.get(&id).copied() else {
+                self.warnings
+                    .push(CitationWarning::UnknownKey(citation.id.clone()));
+                items.push(Inline::strong(Inline::str("???")).into());
+                continue;
+            };
+
+            let number = match self.cited.iter().position(|c| *c == id) {
+                Some(n) => n + 1,
+                None => {
+                    self.cited.push(id.clone());
+                    self.cited.len()
+                }
+            };
+
+            let show_author = !numeric && citation.mode == CitationMode::Normal;
+            let text = match (numeric, show_author) {
+                (true, _) => number.to_string(),
+                (false, true) => format!("{} {}", reference.short_authors(), reference.year),
+                (false, false) => reference.year.clone(),
+            };
+            let mut text = Inlines::from_text(&text);
+            if self.options.link_citations {
+                let url = format!("#ref-{}", id);
+                text = Inline::link(Attr::default(), text, &url, "").into();
+            }
+            if i == 0 && in_text {
+                author = Some(Inlines::from_text(&reference.short_authors()));
+            }
+
+            let mut item = Vec::new();
+            if !citation.prefix.clone().expand().is_empty() {
+                item.extend([citation.prefix.clone(), Inline::space().into()]);
+            }
+            item.push(text);
+            item.push(citation.suffix.clone());
+            items.push(Inlines::from_iter(item));
+        }
+
+        let separator = match numeric {
+            true => Inlines::from_text(", "),
+            false => Inlines::from_text("; "),
+        };
+        let (open, close) = match numeric {
+            true => ("[", "]"),
+            false => ("(", ")"),
+        };
+
+        let mut out = Vec::new();
+        if let Some(author) = author {
+            out.extend([author, Inline::space().into()]);
+        }
+        out.push(Inlines::from_text(open));
+        for (i, item) in items.into_iter().enumerate() {
+            if i > 0 {
+                out.push(separator.clone());
+            }
+            out.push(item);
+        }
+        out.push(Inlines::from_text(close));
+        Inlines::from_iter(out)
+    }
+
+    fn bibliography(&self) -> Vector<Block<A>> {
+        let mut cited = Vec::from_iter(
+            self.cited
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (i + 1, self.references[id])),
+        );
+        if self.options.style == CitationStyle::AuthorDate {
+            cited.sort_by_key(|(_, r)| r.sort_key());
+        }
+
+        Vector::from_iter(cited.into_iter().map(|(number, reference)| {
+            let mut entry = reference.entry(self.options.style);
+            if self.options.style == CitationStyle::Numeric {
+                let label = Inlines::from_text(&format!("[{}]", number));
+                entry = Inlines::from_iter([label, Inline::space().into(), entry]);
+            }
+            let attr = Attr::builder()
+                .id(&format!("ref-{}", reference.id))
+                .class("csl-entry")
+                .build();
+            Block::div(attr, Block::para(entry))
+        }))
+    }
+}
+
+impl<A> Map<A> for Processor<'_, A>
+where
+    A: DecorationScheme,
+{
+    fn map_inline_condensed(
+        &mut self,
+        mut inline: Inline<A, CondensedLeaf>,
+    ) -> Inline<A, CondensedLeaf> {
+        if let InlineContent::Cite(citations, text) = &mut inline.content {
+            *text = self.render(citations);
+        }
+        inline
+    }
+
+    fn map_inline_expanded(
+        &mut self,
+        mut inline: Inline<A, ExpandedLeaf>,
+    ) -> Inline<A, ExpandedLeaf> {
+        if let InlineContent::Cite(citations, text) = &mut inline.content {
+            *text = self.render(citations);
+        }
+        inline
+    }
+}
+
+/// Fills the first `Div#refs` with the bibliography.
+struct RefsPlaceholder<A>
+where
+    A: DecorationScheme,
+{
+    bibliography: Vector<Block<A>>,
+    found: bool,
+}
+
+impl<A> Map<A> for RefsPlaceholder<A>
+where
+    A: DecorationScheme,
+{
+    fn map_block(&mut self, mut block: Block<A>) -> Block<A> {
+        if let BlockContent::Div(attr, content) = &mut block.content {
+            if !self.found && attr.identifier.as_ref() == "refs" {
+                attr.add_class("references");
+                *content = std::mem::take(&mut self.bibliography).into();
+                self.found = true;
+            }
+        }
+        block
+    }
+}
+
+#[test]
+fn test_citeproc() {
+    let text = |s: &str| ::serde_json::json!({"t":"MetaInlines","c":[{"t":"Str","c":s}]});
+    let name = |family: &str, given: &str| ::serde_json::json!({"t":"MetaMap","c":{"family":text(family),"given":text(given)}});
+    let cite = |id: &str, mode: &str, suffix: ::serde_json::Value| {
+        ::serde_json::json!({"citationId":id,"citationPrefix":[],"citationSuffix":suffix,
+            "citationMode":{"t":mode},"citationNoteNum":0,"citationHash":0})
+    };
+    let page = ::serde_json::json!([{"t":"Str","c":","},{"t":"Space"},{"t":"Str","c":"4"}]);
+    let input = ::serde_json::json!({"pandoc-api-version":[1,23,1],
+        "meta":{"references":{"t":"MetaList","c":[
+            {"t":"MetaMap","c":{
+                "id":{"t":"MetaString","c":"roe"},
+                "author":{"t":"MetaList","c":[name("Roe","Jane"),name("Poe","Edgar")]},
+                "title":text("Rust"),
+                "container-title":text("Journal"),
+                "volume":text("3"),
+                "page":text("10-20"),
+                "issued":{"t":"MetaMap","c":{"date-parts":{"t":"MetaList","c":[
+                    {"t":"MetaList","c":[{"t":"MetaString","c":"2019"}]}
+                ]}}}
+            }},
+            {"t":"MetaMap","c":{
+                "id":{"t":"MetaString","c":"doe"},
+                "author":{"t":"MetaList","c":[name("Doe","John")]},
+                "title":text("Pandoc"),
+                "publisher":text("Press"),
+                "issued":text("2020")
+            }}
+        ]}},
+        "blocks":[
+            {"t":"Para","c":[
+                {"t":"Cite","c":[[cite("roe","AuthorInText",page.clone())],[]]},
+                {"t":"Space"},
+                {"t":"Cite","c":[[cite("doe","NormalCitation",::serde_json::json!([])),
+                    cite("missing","NormalCitation",::serde_json::json!([]))],[]]}
+            ]}
+        ]
+    });
+    let doc: Pandoc<NullScheme> = ::serde_json::from_str(&input.to_string()).unwrap();
+
+    let mut author_date = doc.clone();
+    let warnings = author_date.citeproc(&CiteprocOptions::default());
+    ::std::assert_eq!(
+        warnings,
+        vec![CitationWarning::UnknownKey(InternedString::from("missing"))]
+    );
+    let text = Vec::from_iter(author_date.blocks.content.iter().map(|b| b.stringify()));
+    ::std::assert_eq!(
+        text,
+        vec![
+            "Roe and Poe (2019, 4) (Doe 2020; ???)",
+            "Doe, John. 2020. Pandoc. Press.\n\n\
+             Roe, Jane, and Edgar Poe. 2019. \u{201c}Rust.\u{201d} Journal 3: 10\u{2013}20.",
+        ]
+    );
+
+    let mut numeric = doc;
+    numeric.citeproc(&CiteprocOptions {
+        style: CitationStyle::Numeric,
+        ..CiteprocOptions::default()
+    });
+    let text = Vec::from_iter(numeric.blocks.content.iter().map(|b| b.stringify()));
+    ::std::assert_eq!(
+        text,
+        vec![
+            "Roe and Poe [1, 4] [2, ???]",
+            "[1] Roe, Jane, and Edgar Poe. \u{201c}Rust.\u{201d} Journal 3: 10\u{2013}20. 2019.\n\n\
+             [2] Doe, John. Pandoc. Press. 2020.",
+        ]
+    );
+}
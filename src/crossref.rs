@@ -0,0 +1,818 @@
+//! Cross-references to figures, tables, equations and sections
+
+use im_rope::Rope;
+use imbl::Vector;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use crate::ast::*;
+use crate::interned::InternedString;
+use crate::number::{advance_counters, format_section_number};
+use crate::stringify::Stringify;
+use crate::visit::Map;
+
+/// The kinds of element which can be cross-referenced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CrossrefKind {
+    /// A `Figure` whose identifier begins with `fig:`.
+    Figure,
+    /// A `Table` whose identifier begins with `tbl:`.
+    Table,
+    /// A `Span` whose identifier begins with `eq:` and which contains display
+    /// math.
+    Equation,
+    /// A `Header` whose identifier begins with `sec:`.
+    Section,
+}
+
+impl CrossrefKind {
+    /// The prefix of the identifiers of elements of this kind.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            CrossrefKind::Figure => "fig:",
+            CrossrefKind::Table => "tbl:",
+            CrossrefKind::Equation => "eq:",
+            CrossrefKind::Section => "sec:",
+        }
+    }
+
+    /// The kind of element an identifier refers to, according to its prefix.
+    pub fn from_identifier(identifier: &str) -> Option<Self> {
+        [
+            CrossrefKind::Figure,
+            CrossrefKind::Table,
+            CrossrefKind::Equation,
+            CrossrefKind::Section,
+        ]
+        .into_iter()
+        .find(|k| identifier.starts_with(k.prefix()))
+    }
+}
+
+/// The words used in cross-references and captions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossrefLabels {
+    /// Prefix for figure captions, as in "Figure 1: ...".
+    pub figure: String,
+    /// Prefix for table captions, as in "Table 1: ...".
+    pub table: String,
+    /// Singular and plural prefixes for references to figures.
+    pub figure_ref: (String, String),
+    pub table_ref: (String, String),
+    pub equation_ref: (String, String),
+    pub section_ref: (String, String),
+    pub list_of_figures: String,
+    pub list_of_tables: String,
+}
+
+impl CrossrefLabels {
+    /// Labels for a BCP 47 language tag. English, French, German and Spanish
+    /// are supported; other languages get English labels.
+    pub fn for_lang(lang: &str) -> Self {
+        let labels = |f: &str, t: &str, refs: [&str; 8], lof: &str, lot: &str| CrossrefLabels {
+            figure: f.to_string(),
+            table: t.to_string(),
+            figure_ref: (refs[0].to_string(), refs[1].to_string()),
+            table_ref: (refs[2].to_string(), refs[3].to_string()),
+            equation_ref: (refs[4].to_string(), refs[5].to_string()),
+            section_ref: (refs[6].to_string(), refs[7].to_string()),
+            list_of_figures: lof.to_string(),
+            list_of_tables: lot.to_string(),
+        };
+
+        match lang.split(['-', '_']).next().unwrap_or("") {
+            "de" => labels(
+                "Abbildung",
+                "Tabelle",
+                [
+                    "Abb.",
+                    "Abb.",
+                    "Tab.",
+                    "Tab.",
+                    "Gl.",
+                    "Gl.",
+                    "Abschnitt",
+                    "Abschnitte",
+                ],
+                "Abbildungsverzeichnis",
+                "Tabellenverzeichnis",
+            ),
+            "fr" => labels(
+                "Figure",
+                "Tableau",
+                ["fig.", "fig.", "tab.", "tab.", "éq.", "éq.", "sec.", "sec."],
+                "Liste des figures",
+                "Liste des tableaux",
+            ),
+            "es" => labels(
+                "Figura",
+                "Tabla",
+                [
+                    "fig.", "figs.", "tabla", "tablas", "ec.", "ecs.", "sec.", "secs.",
+                ],
+                "Índice de figuras",
+                "Índice de tablas",
+            ),
+            _ => labels(
+                "Figure",
+                "Table",
+                [
+                    "fig.", "figs.", "tbl.", "tbls.", "eq.", "eqns.", "sec.", "secs.",
+                ],
+                "List of Figures",
+                "List of Tables",
+            ),
+        }
+    }
+
+    fn reference(&self, kind: CrossrefKind, plural: bool) -> &str {
+        let (singular, plural_form) = match kind {
+            CrossrefKind::Figure => &self.figure_ref,
+            CrossrefKind::Table => &self.table_ref,
+            CrossrefKind::Equation => &self.equation_ref,
+            CrossrefKind::Section => &self.section_ref,
+        };
+        if plural {
+            plural_form
+        } else {
+            singular
+        }
+    }
+}
+
+impl Default for CrossrefLabels {
+    fn default() -> Self {
+        CrossrefLabels::for_lang("en")
+    }
+}
+
+/// Options for [`Pandoc::crossref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossrefOptions {
+    pub labels: CrossrefLabels,
+    /// Prefix the captions of labeled figures and tables with their label and
+    /// number, as in "Figure 1: ".
+    pub caption_prefix: bool,
+    /// Separates the number from the caption text.
+    pub caption_separator: String,
+    /// Append `\qquad(n)` to labeled display equations.
+    pub equation_tags: bool,
+}
+
+impl Default for CrossrefOptions {
+    fn default() -> Self {
+        CrossrefOptions {
+            labels: CrossrefLabels::default(),
+            caption_prefix: true,
+            caption_separator: ": ".to_string(),
+            equation_tags: true,
+        }
+    }
+}
+
+/// A labeled element found by [`Pandoc::crossref`].
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct CrossrefEntry<A>
+where
+    A: DecorationScheme,
+{
+    pub kind: CrossrefKind,
+    pub identifier: InternedString,
+    pub number: String,
+    /// The caption of a figure or table, without its prefix, or the title of
+    /// a section. Empty for equations.
+    pub caption: Inlines<A>,
+}
+
+/// The result of [`Pandoc::crossref`].
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct Crossrefs<A>
+where
+    A: DecorationScheme,
+{
+    /// Every labeled element, in document order.
+    pub entries: Vector<CrossrefEntry<A>>,
+    /// References to labels which were not found.
+    pub unresolved: Vector<InternedString>,
+}
+
+impl<A> Crossrefs<A>
+where
+    A: DecorationScheme,
+{
+    /// A list of the entries of the given kind: a level 1 `Header` with the
+    /// classes `unnumbered` and `unlisted`, followed by an `OrderedList` of
+    /// links to the entries. Returns an empty list if there are none.
+    pub fn list(&self, kind: CrossrefKind, title: &str) -> Vector<Block<A>> {
+        let items = Vec::from_iter(self.entries.iter().filter(|e| e.kind == kind).map(|e| {
+            let url = format!("#{}", e.identifier);
+            Blocks::from(Block::plain(Inline::link(
+                Attr::default(),
+                e.caption.clone(),
+                &url,
+                "",
+            )))
+        }));
+
+        if items.is_empty() {
+            return Vector::new();
+        }
+
+        let header_attr = Attr::builder()
+            .class("unnumbered")
+            .class("unlisted")
+            .build();
+        let list_attrs = ListAttributes {
+            start_number: 1,
+            number_style: ListNumberStyle::Decimal,
+            number_delim: ListNumberDelim::Period,
+        };
+        Vector::from_iter([
+            Block::header(1, header_attr, title),
+            Block::ordered_list(list_attrs, items),
+        ])
+    }
+}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    /// Number labeled figures, tables, equations and sections, and resolve
+    /// references to them, in the manner of pandoc-crossref.
+    ///
+    /// * Figures and tables are numbered in document order, and their
+    ///   captions are prefixed as configured.
+    /// * Equations are numbered in document order, and tagged as configured.
+    /// * Sections take their number from the header's `number` attribute if
+    ///   it has one (see [`Pandoc::number_sections`]), and otherwise are
+    ///   numbered hierarchically. Unnumbered sections, and those in a block
+    ///   other than a `Div` which [`Pandoc::number_sections`] would not
+    ///   number, are referred to by their title.
+    /// * Each `Cite` whose citations all have a cross-reference prefix is
+    ///   replaced by its rendering, such as "fig. 1" or "figs. 1, 2", with
+    ///   each number linked to its target. A citation with `SuppressAuthor`
+    ///   mode, as in `[-@fig:a]`, omits the prefix. References to unknown
+    ///   labels are rendered as `¿label?` in bold. A citation's prefix and
+    ///   suffix are kept around the reference, as in "see fig. 1, left";
+    ///   a citation with a prefix starts a new group, and one with a suffix
+    ///   ends its group.
+    /// * The placeholder divs `#lof` and `#lot`, if present, are filled with
+    ///   the lists of figures and tables.
+    ///
+    /// Numbered figures, tables and equations are given a `number`
+    /// attribute. Those which already have one, such as those numbered by an
+    /// earlier call, keep that number and are not prefixed or tagged again,
+    /// so calling this again on its result changes nothing.
+    pub fn crossref(&mut self, options: &CrossrefOptions) -> Crossrefs<A> {
+        let mut collector = Collector {
+            options,
+            counts: HashMap::new(),
+            entries: Vector::new(),
+            numbers: Vec::new(),
+            counters: Vec::new(),
+            divs: Vec::new(),
+        };
+        let blocks = std::mem::take(&mut self.blocks);
+        let blocks = blocks.walk(&mut collector);
+
+        let targets = HashMap::from_iter(
+            collector
+                .entries
+                .iter()
+                .map(|e| (e.identifier.clone(), (e.kind, e.number.clone()))),
+        );
+        let mut resolver = Resolver {
+            labels: &options.labels,
+            targets,
+            unresolved: Vector::new(),
+        };
+        let blocks = blocks.walk(&mut resolver);
+
+        let crossrefs = Crossrefs {
+            entries: collector.entries,
+            unresolved: resolver.unresolved,
+        };
+
+        let mut lists = ListPlaceholders {
+            lof: crossrefs.list(CrossrefKind::Figure, &options.labels.list_of_figures),
+            lot: crossrefs.list(CrossrefKind::Table, &options.labels.list_of_tables),
+        };
+        self.blocks = blocks.walk(&mut lists);
+
+        crossrefs
+    }
+}
+
+struct Collector<'a, A>
+where
+    A: DecorationScheme,
+{
+    options: &'a CrossrefOptions,
+    counts: HashMap<CrossrefKind, i32>,
+    entries: Vector<CrossrefEntry<A>>,
+    /// The numbers of the figures and tables entered but not yet mapped,
+    /// innermost last. `None` for those which were already numbered.
+    numbers: Vec<Option<String>>,
+    /// The section counters, as in [`Pandoc::number_sections`].
+    counters: Vec<i32>,
+    /// Whether each block entered but not yet mapped is a `Div`. The section
+    /// counters only count headers within nothing but `Div`s, as in
+    /// [`Pandoc::number_sections`].
+    divs: Vec<bool>,
+}
+
+impl<A> Collector<'_, A>
+where
+    A: DecorationScheme,
+{
+    /// Count an element and record its entry, with `number` if it was
+    /// already numbered. Returns its number.
+    fn add(
+        &mut self,
+        kind: CrossrefKind,
+        identifier: &InternedString,
+        number: Option<String>,
+        caption: Inlines<A>,
+    ) -> String {
+        let count = self.counts.entry(kind).or_insert(0);
+        *count += 1;
+        let number = number.unwrap_or_else(|| count.to_string());
+        self.entries.push_back(CrossrefEntry {
+            kind,
+            identifier: identifier.clone(),
+            number: number.clone(),
+            caption,
+        });
+        number
+    }
+
+    /// Record a section, if it is labeled. Only `counted` headers advance
+    /// the section counters; others are numbered only if they have a
+    /// `number` attribute, and are otherwise referred to by their title.
+    fn add_section(&mut self, level: i32, attr: &Attr, title: &Inlines<A>, counted: bool) {
+        let numbered = counted && !attr.has_class("unnumbered");
+        if numbered {
+            advance_counters(&mut self.counters, level);
+        }
+        if CrossrefKind::from_identifier(&attr.identifier) == Some(CrossrefKind::Section) {
+            let number = match attr.get_str("number") {
+                Some(number) if numbered || !counted => number,
+                _ if numbered => format_section_number(&self.counters, &[]),
+                _ => title.stringify(),
+            };
+            self.entries.push_back(CrossrefEntry {
+                kind: CrossrefKind::Section,
+                identifier: attr.identifier.clone(),
+                number,
+                caption: title.clone(),
+            });
+        }
+    }
+
+    /// The caption prefixes of a figure or table, with and without the
+    /// separator.
+    fn prefixes(&self, kind: CrossrefKind, number: &str) -> [String; 2] {
+        let label = match kind {
+            CrossrefKind::Table => &self.options.labels.table,
+            _ => &self.options.labels.figure,
+        };
+        [
+            format!("{} {}{}", label, number, self.options.caption_separator),
+            format!("{} {}", label, number),
+        ]
+    }
+
+    fn prefix_caption(&self, kind: CrossrefKind, number: &str, caption: &mut Caption<A>) {
+        if self.options.caption_prefix {
+            let [prefix, bare] = self.prefixes(kind, number);
+            match caption_inlines(&mut caption.full) {
+                Some(inlines) => {
+                    let text = std::mem::take(inlines);
+                    *inlines = Inlines::from_iter([Inlines::from_text(&prefix), text]);
+                }
+                None => caption.full.content.push_front(Block::plain(bare)),
+            }
+        }
+    }
+
+    fn number_equation<L>(&mut self, content: &mut InlineContent<A, L>) {
+        let InlineContent::Span(attr, inlines) = content else {
+            return;
+        };
+        if CrossrefKind::from_identifier(&attr.identifier) != Some(CrossrefKind::Equation) {
+            return;
+        }
+
+        let Some(tex) = display_math(inlines) else {
+            return;
+        };
+
+        let numbered = attr.get_str("number");
+        let tag = numbered.is_none() && self.options.equation_tags;
+        let number = self.add(
+            CrossrefKind::Equation,
+            &attr.identifier,
+            numbered,
+            Inlines::default(),
+        );
+        if tag {
+            for ch in format!("\\qquad({})", number).chars() {
+                tex.push_back(ch);
+            }
+        }
+        attr.set("number", &number);
+    }
+}
+
+/// The TeX of the first display math in a sequence of inlines.
+fn display_math<A>(inlines: &mut Inlines<A>) -> Option<&mut Rope>
+where
+    A: DecorationScheme,
+{
+    fn find<A, L>(v: &mut Vector<Inline<A, L>>) -> Option<&mut Rope>
+    where
+        A: DecorationScheme,
+        Inline<A, L>: Clone,
+    {
+        v.iter_mut().find_map(|i| match &mut i.content {
+            InlineContent::Math(MathType::Display, tex) => Some(tex),
+            _ => None,
+        })
+    }
+
+    match &mut inlines.content {
+        InlinesContent::Condensed(v) => find(v),
+        InlinesContent::Expanded(v) => find(v),
+    }
+}
+
+/// Sections, figures and tables are numbered as they are entered, so that
+/// entries are in document order and a figure is numbered before any figures
+/// within it. Captions are prefixed once their content has been walked.
+impl<A> Map<A> for Collector<'_, A>
+where
+    A: DecorationScheme,
+{
+    fn enter_block(&mut self, block: &Block<A>) {
+        if let BlockContent::Header(level, attr, title) = &block.content {
+            let counted = self.divs.iter().all(|&div| div);
+            self.add_section(*level, attr, title, counted);
+        }
+        self.divs
+            .push(matches!(block.content, BlockContent::Div(_, _)));
+
+        if let Some((kind, attr, caption)) = captioned(block) {
+            let text = caption
+                .short
+                .clone()
+                .or_else(|| match &caption.full.content.front()?.content {
+                    BlockContent::Plain(inlines) | BlockContent::Para(inlines) => {
+                        Some(inlines.clone())
+                    }
+                    _ => None,
+                })
+                .unwrap_or_default();
+            match attr.get_str("number") {
+                Some(number) => {
+                    let text = match self.options.caption_prefix {
+                        true => strip_prefix(text, &self.prefixes(kind, &number)),
+                        false => text,
+                    };
+                    self.add(kind, &attr.identifier, Some(number), text);
+                    self.numbers.push(None);
+                }
+                None => {
+                    let number = self.add(kind, &attr.identifier, None, text);
+                    self.numbers.push(Some(number));
+                }
+            }
+        }
+    }
+
+    fn map_block(&mut self, mut block: Block<A>) -> Block<A> {
+        self.divs.pop();
+
+        let kind = captioned(&block).map(|(kind, _, _)| kind);
+        let captioned = match &mut block.content {
+            BlockContent::Figure(attr, caption, _) => Some((attr, caption)),
+            BlockContent::Table(table) => Some((&mut table.attrs, &mut table.caption)),
+            _ => None,
+        };
+        if let (Some(kind), Some((attr, caption))) = (kind, captioned) {
+            let number = self.numbers.pop().expect("entered blocks are mapped");
+            if let Some(number) = number {
+                self.prefix_caption(kind, &number, caption);
+                attr.set("number", &number);
+            }
+        }
+        block
+    }
+
+    fn map_inline_condensed(
+        &mut self,
+        mut inline: Inline<A, CondensedLeaf>,
+    ) -> Inline<A, CondensedLeaf> {
+        self.number_equation(&mut inline.content);
+        inline
+    }
+
+    fn map_inline_expanded(
+        &mut self,
+        mut inline: Inline<A, ExpandedLeaf>,
+    ) -> Inline<A, ExpandedLeaf> {
+        self.number_equation(&mut inline.content);
+        inline
+    }
+}
+
+/// The kind, attributes and caption of a labeled figure or table.
+fn captioned<A>(block: &Block<A>) -> Option<(CrossrefKind, &Attr, &Caption<A>)>
+where
+    A: DecorationScheme,
+{
+    let (kind, attr, caption) = match &block.content {
+        BlockContent::Figure(attr, caption, _) => (CrossrefKind::Figure, attr, caption),
+        BlockContent::Table(table) => (CrossrefKind::Table, &table.attrs, &table.caption),
+        _ => return None,
+    };
+    (CrossrefKind::from_identifier(&attr.identifier) == Some(kind)).then_some((kind, attr, caption))
+}
+
+/// `text` without the first of `prefixes` it begins with, as added to a
+/// caption by an earlier call to [`Pandoc::crossref`].
+fn strip_prefix<A>(text: Inlines<A>, prefixes: &[String]) -> Inlines<A>
+where
+    A: DecorationScheme,
+{
+    let inlines = Vec::from_iter(text.expanded());
+    for prefix in prefixes {
+        let prefix = Inlines::<A>::from_text(prefix);
+        let n = prefix.expanded().count();
+        if n <= inlines.len()
+            && Inlines::from_iter(inlines[..n].iter().cloned()).stringify() == prefix.stringify()
+        {
+            return Inlines::from_iter(inlines[n..].iter().cloned());
+        }
+    }
+    text
+}
+
+/// The inlines of the first block of a caption, if it is a `Plain` or `Para`.
+fn caption_inlines<A>(blocks: &mut Blocks<A>) -> Option<&mut Inlines<A>>
+where
+    A: DecorationScheme,
+{
+    match &mut blocks.content.front_mut()?.content {
+        BlockContent::Plain(inlines) | BlockContent::Para(inlines) => Some(inlines),
+        _ => None,
+    }
+}
+
+struct Resolver<'a> {
+    labels: &'a CrossrefLabels,
+    targets: HashMap<InternedString, (CrossrefKind, String)>,
+    unresolved: Vector<InternedString>,
+}
+
+impl Resolver<'_> {
+    fn is_crossref<A, L>(content: &InlineContent<A, L>) -> bool
+    where
+        A: DecorationScheme,
+    {
+        match content {
+            InlineContent::Cite(citations, _) => {
+                !citations.is_empty()
+                    && citations
+                        .iter()
+                        .all(|c| CrossrefKind::from_identifier(&c.id).is_some())
+            }
+            _ => false,
+        }
+    }
+
+    fn render<A>(&mut self, citations: &Vector<Citation<A>>) -> Vec<Inline<A, ExpandedLeaf>>
+    where
+        A: DecorationScheme,
+    {
+        let mut out = Vec::new();
+        let mut rest = Vec::from_iter(citations.iter());
+
+        while !rest.is_empty() {
+            let kind = CrossrefKind::from_identifier(&rest[0].id);
+            let len = 1 + rest[1..]
+                .iter()
+                .zip(&rest)
+                .take_while(|(c, prev)| {
+                    CrossrefKind::from_identifier(&c.id) == kind
+                        && c.prefix.stringify().is_empty()
+                        && prev.suffix.stringify().is_empty()
+                })
+                .count();
+            let group = Vec::from_iter(rest.drain(..len));
+
+            if !out.is_empty() {
+                out.push(Inline::str(","));
+                out.push(Inline::space());
+            }
+            if !group[0].prefix.stringify().is_empty() {
+                out.extend(group[0].prefix.clone().into_expanded());
+                out.push(Inline::space());
+            }
+            let suffix = group[len - 1].suffix.clone().into_expanded();
+
+            let links = Vec::from_iter(group.iter().map(|c| match self.targets.get(&c.id) {
+                Some((_, number)) => {
+                    let url = format!("#{}", c.id);
+                    Some((number.clone(), url))
+                }
+                None => {
+                    self.unresolved.push_back(c.id.clone());
+                    None
+                }
+            }));
+            let prefix = kind
+                .filter(|_| group[0].mode != CitationMode::SuppressAuthor)
+                .map(|k| self.labels.reference(k, group.len() > 1));
+
+            if let ([Some((number, url))], Some(prefix)) = (links.as_slice(), prefix) {
+                let text = format!("{} {}", prefix, number);
+                out.push(Inline::link(Attr::default(), text, url, ""));
+                out.extend(suffix);
+                continue;
+            }
+
+            if let Some(prefix) = prefix {
+                out.push(Inline::str(prefix));
+                out.push(Inline::space());
+            }
+            for (i, (citation, link)) in group.iter().zip(links).enumerate() {
+                if i > 0 {
+                    out.push(Inline::str(","));
+                    out.push(Inline::space());
+                }
+                out.push(match link {
+                    Some((number, url)) => {
+                        Inline::link(Attr::default(), Inline::str(&number), &url, "")
+                    }
+                    None => Inline::strong(Inline::str(&format!("¿{}?", citation.id))),
+                });
+            }
+            out.extend(suffix);
+        }
+
+        out
+    }
+}
+
+impl<A> Map<A> for Resolver<'_>
+where
+    A: DecorationScheme,
+{
+    fn map_inlines(&mut self, mut inlines: Inlines<A>) -> Inlines<A> {
+        let any = match &inlines.content {
+            InlinesContent::Condensed(v) => v.iter().any(|i| Self::is_crossref(&i.content)),
+            InlinesContent::Expanded(v) => v.iter().any(|i| Self::is_crossref(&i.content)),
+        };
+        if !any {
+            return inlines;
+        }
+
+        let content = inlines.expand();
+        for inline in std::mem::take(content) {
+            match &inline.content {
+                InlineContent::Cite(citations, _) if Self::is_crossref(&inline.content) => {
+                    content.extend(self.render(citations))
+                }
+                _ => content.push_back(inline),
+            }
+        }
+        inlines
+    }
+}
+
+/// Fills the `#lof` and `#lot` placeholder divs.
+struct ListPlaceholders<A>
+where
+    A: DecorationScheme,
+{
+    lof: Vector<Block<A>>,
+    lot: Vector<Block<A>>,
+}
+
+impl<A> Map<A> for ListPlaceholders<A>
+where
+    A: DecorationScheme,
+{
+    fn map_block(&mut self, mut block: Block<A>) -> Block<A> {
+        if let BlockContent::Div(attr, content) = &mut block.content {
+            match attr.identifier.as_ref() {
+                "lof" => *content = self.lof.clone().into(),
+                "lot" => *content = self.lot.clone().into(),
+                _ => {}
+            }
+        }
+        block
+    }
+}
+
+#[test]
+fn test_crossref() {
+    let cite = |id: &str, mode: &str| {
+        ::serde_json::json!({"citationId":id,"citationPrefix":[],"citationSuffix":[],
+            "citationMode":{"t":mode},"citationNoteNum":0,"citationHash":0})
+    };
+    let affixed = |id: &str, prefix: &str| {
+        ::serde_json::json!({"citationId":id,"citationPrefix":[{"t":"Str","c":prefix}],
+            "citationSuffix":[{"t":"Str","c":","},{"t":"Space"},{"t":"Str","c":"left"}],
+            "citationMode":{"t":"NormalCitation"},"citationNoteNum":0,"citationHash":0})
+    };
+    let figure = |id: &str, caption: &str| ::serde_json::json!({"t":"Figure","c":[[id,[],[]],[null,[{"t":"Plain","c":[{"t":"Str","c":caption}]}]],[]]});
+    let input = ::serde_json::json!({"pandoc-api-version":[1,23,1],"meta":{},"blocks":[
+        {"t":"Header","c":[1,["sec:intro",[],[]],[{"t":"Str","c":"Intro"}]]},
+        figure("fig:a", "Apples"),
+        {"t":"Div","c":[["",[],[]],[{"t":"Header","c":[1,["sec:fruit",[],[]],[{"t":"Str","c":"Fruit"}]]}]]},
+        figure("fig:b", "Pears"),
+        {"t":"Para","c":[{"t":"Span","c":[["eq:e",[],[]],[{"t":"Math","c":[{"t":"DisplayMath"},"x"]}]]}]},
+        {"t":"Para","c":[
+            {"t":"Cite","c":[[cite("fig:a","NormalCitation"),cite("fig:b","NormalCitation")],[]]},
+            {"t":"Space"},
+            {"t":"Cite","c":[[cite("sec:intro","NormalCitation"),cite("eq:e","NormalCitation")],[]]},
+            {"t":"Space"},
+            {"t":"Cite","c":[[cite("fig:b","SuppressAuthor")],[]]},
+            {"t":"Space"},
+            {"t":"Cite","c":[[cite("tbl:x","NormalCitation")],[]]}
+        ]},
+        {"t":"Div","c":[["lof",[],[]],[]]},
+        {"t":"BlockQuote","c":[{"t":"Header","c":[2,["sec:quote",[],[]],[{"t":"Str","c":"Quoted"}]]}]},
+        {"t":"Para","c":[
+            {"t":"Cite","c":[[affixed("fig:a","see"),cite("fig:b","NormalCitation")],[]]},
+            {"t":"Space"},
+            {"t":"Cite","c":[[cite("sec:quote","NormalCitation")],[]]}
+        ]}
+    ]});
+    let mut doc: Pandoc<NullScheme> = ::serde_json::from_str(&input.to_string()).unwrap();
+    let crossrefs = doc.crossref(&CrossrefOptions::default());
+
+    ::std::assert_eq!(
+        Vec::from_iter(
+            crossrefs
+                .entries
+                .iter()
+                .map(|e| (e.kind, e.number.as_str()))
+        ),
+        vec![
+            (CrossrefKind::Section, "1"),
+            (CrossrefKind::Figure, "1"),
+            (CrossrefKind::Section, "2"),
+            (CrossrefKind::Figure, "2"),
+            (CrossrefKind::Equation, "1"),
+            (CrossrefKind::Section, "Quoted"),
+        ]
+    );
+    ::std::assert_eq!(
+        Vec::from_iter(crossrefs.unresolved.iter().map(|id| id.to_string())),
+        vec!["tbl:x".to_string()]
+    );
+
+    let text = Vec::from_iter(doc.blocks.content.iter().map(|b| b.stringify()));
+    ::std::assert_eq!(text[1], "Figure 1: Apples");
+    ::std::assert_eq!(text[4], "x\\qquad(1)");
+    ::std::assert_eq!(text[5], "figs. 1, 2 sec. 1, eq. 1 2 tbl. ¿tbl:x?");
+    ::std::assert_eq!(text[6], "List of Figures\n\nApples\nPears");
+    ::std::assert_eq!(text[8], "see fig. 1, left, fig. 2 sec. Quoted");
+
+    // Numbering again changes nothing.
+    let mut again = doc.clone();
+    let recrossrefs = again.crossref(&CrossrefOptions::default());
+    ::std::assert_eq!(
+        ::serde_json::to_value(&again).unwrap(),
+        ::serde_json::to_value(&doc).unwrap()
+    );
+    let summary = |c: &Crossrefs<NullScheme>| {
+        Vec::from_iter(
+            c.entries
+                .iter()
+                .map(|e| (e.kind, e.number.clone(), e.caption.stringify())),
+        )
+    };
+    ::std::assert_eq!(summary(&recrossrefs), summary(&crossrefs));
+}
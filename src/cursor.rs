@@ -0,0 +1,424 @@
+//! Local navigation and editing of a document
+
+use std::fmt::Debug;
+
+use crate::ast::*;
+use crate::path::{Node, NodeMut, NodePath, PathError, PathStep, Place};
+use crate::table::TablePart;
+
+#[cfg(test)]
+use crate::stringify::Stringify;
+
+/// A position in a document, which moves between the parent, siblings and
+/// children of the node it is on and edits the document around it.
+///
+/// The cursor owns the document, and keeps a copy of each node on the path
+/// from the root to the node it is on. Moves look at the node the cursor is
+/// on or its parent rather than walking down from the root, and edits are
+/// made in the copy of the parent. An edited node is written into its own
+/// parent when the cursor leaves it, and so on up to the document when the
+/// document is asked for; as the document's sequences are persistent, this
+/// copies little besides the nodes on the path.
+///
+/// Moves return whether the cursor moved; if it did not, it stays where it
+/// was.
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::Pandoc : Debug, A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone
+)]
+pub struct Cursor<A>
+where
+    A: DecorationScheme,
+{
+    document: Pandoc<A>,
+    path: NodePath,
+    /// The node at each step of the path, with whether it has been edited
+    /// since it was last written into its parent.
+    nodes: Vec<(Node<A>, bool)>,
+}
+
+impl<A> Cursor<A>
+where
+    A: DecorationScheme,
+{
+    /// A cursor on the root of a document.
+    pub fn new(document: Pandoc<A>) -> Self {
+        Cursor {
+            document,
+            path: NodePath::root(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// The path of the node the cursor is on.
+    pub fn path(&self) -> &NodePath {
+        &self.path
+    }
+
+    /// The node the cursor is on, or `None` on the root.
+    pub fn node(&self) -> Option<Node<A>> {
+        self.nodes.last().map(|(node, _)| node.clone())
+    }
+
+    /// The document, with the edits made so far.
+    pub fn document(&mut self) -> &Pandoc<A> {
+        for depth in (0..self.nodes.len()).rev() {
+            self.write_back(depth);
+        }
+        &self.document
+    }
+
+    pub fn into_document(mut self) -> Pandoc<A> {
+        self.document();
+        self.document
+    }
+
+    /// Move to the node at a path, if there is one.
+    pub fn goto(&mut self, path: NodePath) -> bool {
+        let here = self.path.clone();
+        let common = here
+            .0
+            .iter()
+            .zip(&path.0)
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.ascend_to(common);
+        if path.0[common..].iter().all(|step| self.child(step.clone())) {
+            return true;
+        }
+        self.ascend_to(common);
+        for step in &here.0[common..] {
+            self.child(step.clone());
+        }
+        false
+    }
+
+    /// Move to a child of the node.
+    pub fn child(&mut self, step: PathStep) -> bool {
+        let node = self.focus().place(&step).and_then(Place::into_node);
+        match node.and_then(|node| node.to_node()) {
+            Some(node) => {
+                self.nodes.push((node, false));
+                self.path.0.push(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn parent(&mut self) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        self.write_back(self.nodes.len() - 1);
+        self.nodes.pop();
+        self.path.0.pop();
+        true
+    }
+
+    /// Move to the first child of the node: the first of its blocks,
+    /// inlines, list items or lines, the term of a definition list item, the
+    /// first row of a table or the first cell of a row. Captions, the
+    /// definitions of a term, the citations of a cite and metadata map
+    /// entries are reached with [`Cursor::child`].
+    pub fn first_child(&mut self) -> bool {
+        let step = first_step(self.nodes.last().map(|(node, _)| node));
+        step.is_some_and(|step| self.child(step))
+    }
+
+    /// Move to the next node in the node's sequence. Rows don't move into
+    /// another part of a table.
+    pub fn next_sibling(&mut self) -> bool {
+        self.sibling(|i| i.checked_add(1))
+    }
+
+    pub fn prev_sibling(&mut self) -> bool {
+        self.sibling(|i| i.checked_sub(1))
+    }
+
+    fn sibling(&mut self, f: impl FnOnce(usize) -> Option<usize>) -> bool {
+        let step = self
+            .path
+            .last()
+            .and_then(|last| last.with_index(f(last.index()?)?));
+        let Some(step) = step else {
+            return false;
+        };
+        let depth = self.nodes.len() - 1;
+        self.write_back(depth);
+        let node = self
+            .parent_node()
+            .and_then(|parent| parent.place(&step))
+            .and_then(Place::into_node);
+        match node.and_then(|node| node.to_node()) {
+            Some(node) => {
+                self.nodes[depth] = (node, false);
+                self.path.0[depth] = step;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace the node, returning the old one. The cursor stays on the new
+    /// node.
+    pub fn replace(&mut self, node: Node<A>) -> Result<Node<A>, PathError> {
+        let Some(step) = self.path.last().cloned() else {
+            return Err(PathError::NotFound(self.path.clone()));
+        };
+        let place = self.parent_node().and_then(|parent| parent.place(&step));
+        match place.map(|place| place.replace(node.clone())) {
+            Some(Ok(Some(_))) => {}
+            Some(Err(())) => return Err(PathError::WrongKind(self.path.clone())),
+            _ => return Err(PathError::NotFound(self.path.clone())),
+        }
+        self.parent_edited();
+        let (old, edited) = self.nodes.last_mut().expect("a node for each step");
+        *edited = false;
+        Ok(std::mem::replace(old, node))
+    }
+
+    /// Insert a node after this one in its sequence. The cursor stays on
+    /// this node.
+    pub fn insert_after(&mut self, node: Node<A>) -> Result<(), PathError> {
+        let not_in_sequence = || PathError::NotInSequence(self.path.clone());
+        let last = self.path.last().ok_or_else(not_in_sequence)?;
+        let step = last
+            .index()
+            .and_then(|i| last.with_index(i + 1))
+            .ok_or_else(not_in_sequence)?;
+        let path = self
+            .path
+            .parent()
+            .ok_or_else(not_in_sequence)?
+            .child(step.clone());
+        match self.parent_node().and_then(|parent| parent.place(&step)) {
+            Some(place) => place.insert(node, &path)?,
+            None => return Err(PathError::NotFound(path)),
+        }
+        self.parent_edited();
+        Ok(())
+    }
+
+    /// Remove the node, returning it. The cursor moves to the next node in
+    /// the sequence, which takes its place, or if there is none to the
+    /// previous node, or if there is none of those either to the parent.
+    pub fn delete(&mut self) -> Result<Node<A>, PathError> {
+        let Some(step) = self.path.last().cloned() else {
+            return Err(PathError::NotRemovable(self.path.clone()));
+        };
+        let place = self.parent_node().and_then(|parent| parent.place(&step));
+        match place.map(Place::remove) {
+            Some(Ok(Some(_))) => {}
+            Some(Err(())) => return Err(PathError::NotRemovable(self.path.clone())),
+            _ => return Err(PathError::NotFound(self.path.clone())),
+        }
+        self.parent_edited();
+        let (node, _) = self.nodes.pop().expect("a node for each step");
+        self.path.0.pop();
+        let prev = step
+            .index()
+            .and_then(|i| step.with_index(i.checked_sub(1)?));
+        if !self.child(step) {
+            if let Some(prev) = prev {
+                self.child(prev);
+            }
+        }
+        Ok(node)
+    }
+
+    /// The node the cursor is on, or the document on the root.
+    fn focus(&mut self) -> NodeMut<'_, A> {
+        match self.nodes.last_mut() {
+            Some((node, _)) => NodeMut::from(node),
+            None => NodeMut::from(&mut self.document),
+        }
+    }
+
+    /// The parent of the node the cursor is on, or `None` on the root.
+    fn parent_node(&mut self) -> Option<NodeMut<'_, A>> {
+        let node = match self.nodes.len() {
+            0 => return None,
+            1 => NodeMut::from(&mut self.document),
+            n => NodeMut::from(&mut self.nodes[n - 2].0),
+        };
+        Some(node)
+    }
+
+    /// Note that the parent of the node the cursor is on has been edited.
+    fn parent_edited(&mut self) {
+        if let Some(n) = self.nodes.len().checked_sub(2) {
+            self.nodes[n].1 = true;
+        }
+    }
+
+    /// Write the node at a depth of the path into its parent, if it has
+    /// been edited.
+    fn write_back(&mut self, depth: usize) {
+        let (above, rest) = self.nodes.split_at_mut(depth);
+        let (node, edited) = &mut rest[0];
+        if !std::mem::take(edited) {
+            return;
+        }
+        let parent = match above.last_mut() {
+            Some((parent, parent_edited)) => {
+                *parent_edited = true;
+                NodeMut::from(parent)
+            }
+            None => NodeMut::from(&mut self.document),
+        };
+        if let Some(place) = parent.place(&self.path.0[depth]) {
+            let _ = place.replace(node.clone());
+        }
+    }
+
+    /// Move up to the node at a depth of the path.
+    fn ascend_to(&mut self, depth: usize) {
+        while self.nodes.len() > depth {
+            self.parent();
+        }
+    }
+}
+
+/// The step to the first child of a node, or of the document for `None`,
+/// among those which [`Cursor::first_child`] moves to. The child may not
+/// exist, if the sequence is empty.
+fn first_step<A>(node: Option<&Node<A>>) -> Option<PathStep>
+where
+    A: DecorationScheme,
+{
+    let step = match node {
+        None | Some(Node::Blocks(_) | Node::Cell(_)) => PathStep::Block(0),
+        Some(Node::Inlines(_)) => PathStep::Inline(0),
+        Some(Node::DefinitionItem(..)) => PathStep::Term,
+        Some(Node::Row(_)) => PathStep::Cell(0),
+        Some(Node::Citation(_)) => return None,
+        Some(Node::Block(block)) => match &block.content {
+            BlockContent::Plain(_) | BlockContent::Para(_) | BlockContent::Header(..) => {
+                PathStep::Inline(0)
+            }
+            BlockContent::LineBlock(_) => PathStep::Line(0),
+            BlockContent::BlockQuote(_) | BlockContent::Figure(..) | BlockContent::Div(..) => {
+                PathStep::Block(0)
+            }
+            BlockContent::OrderedList(..)
+            | BlockContent::BulletList(_)
+            | BlockContent::DefinitionList(_) => PathStep::Item(0),
+            BlockContent::Table(table) => {
+                let bodies = table.body.iter().enumerate().flat_map(|(b, body)| {
+                    [
+                        (TablePart::BodyHead(b), &body.intermediate_head),
+                        (TablePart::Body(b), &body.rows),
+                    ]
+                });
+                let (part, _) = std::iter::once((TablePart::Head, &table.head.rows))
+                    .chain(bodies)
+                    .chain(std::iter::once((TablePart::Foot, &table.foot.rows)))
+                    .find(|(_, rows)| !rows.is_empty())?;
+                PathStep::Row(part, 0)
+            }
+            _ => return None,
+        },
+        Some(Node::Inline(inline)) => match &inline.content {
+            InlineContent::Note(_) => PathStep::Block(0),
+            InlineContent::Leaf(_)
+            | InlineContent::Code(..)
+            | InlineContent::Math(..)
+            | InlineContent::RawInline(..) => return None,
+            _ => PathStep::Inline(0),
+        },
+        Some(Node::MetaValue(value)) => match &value.content {
+            MetaValueContent::List(_) => PathStep::Item(0),
+            MetaValueContent::Inlines(_) => PathStep::Inline(0),
+            MetaValueContent::Blocks(_) => PathStep::Block(0),
+            _ => return None,
+        },
+    };
+    Some(step)
+}
+
+#[test]
+fn test_cursor() {
+    let document: Pandoc<NullScheme> = Pandoc {
+        decoration: None,
+        meta: Default::default(),
+        blocks: crate::blocks![
+            Block::para("one two"),
+            Block::para("three"),
+            Block::bullet_list([crate::blocks![Block::plain("four")]]),
+        ],
+    };
+    let mut cursor = Cursor::new(document);
+
+    // Merge the first paragraph with the next one.
+    ::std::assert!(cursor.first_child());
+    ::std::assert!(cursor.next_sibling());
+    let Ok(Node::Block(next)) = cursor.delete() else {
+        panic!("expected a block");
+    };
+    ::std::assert_eq!(cursor.path().to_string(), "blocks[1]");
+    ::std::assert!(cursor.prev_sibling());
+    ::std::assert!(!cursor.prev_sibling());
+    let Some(Node::Block(first)) = cursor.node() else {
+        panic!("expected a block");
+    };
+    let (BlockContent::Para(a), BlockContent::Para(b)) = (first.content, next.content) else {
+        panic!("expected paragraphs");
+    };
+    let merged = Block::para(crate::inlines![a, Inline::space(), b]);
+    cursor.replace(Node::Block(merged)).unwrap();
+    cursor
+        .insert_after(Node::Block(Block::horizontal_rule()))
+        .unwrap();
+    ::std::assert_eq!(
+        cursor.document().blocks.stringify(),
+        "one two three\n\nfour"
+    );
+
+    // Descend into the list and back out again.
+    ::std::assert!(cursor.next_sibling());
+    ::std::assert!(cursor.next_sibling());
+    ::std::assert!(!cursor.next_sibling());
+    ::std::assert!(cursor.first_child());
+    ::std::assert!(cursor.first_child());
+    ::std::assert!(cursor.first_child());
+    ::std::assert_eq!(
+        cursor.path().to_string(),
+        "blocks[2]/items[0]/blocks[0]/inlines[0]"
+    );
+    ::std::assert!(!cursor.first_child());
+    ::std::assert!(cursor.delete().is_ok());
+    ::std::assert_eq!(cursor.path().to_string(), "blocks[2]/items[0]/blocks[0]");
+    ::std::assert_eq!(
+        cursor.insert_after(Node::Block(Block::horizontal_rule())),
+        Ok(())
+    );
+    ::std::assert!(cursor.parent() && cursor.parent() && cursor.parent());
+    ::std::assert!(!cursor.parent());
+    ::std::assert!(cursor.goto("blocks[2]/items[0]/blocks[1]".parse().unwrap()));
+    ::std::assert!(!cursor.goto("blocks[2]/items[1]".parse().unwrap()));
+    ::std::assert_eq!(cursor.path().to_string(), "blocks[2]/items[0]/blocks[1]");
+    ::std::assert!(cursor.goto(NodePath::root()));
+    ::std::assert_eq!(
+        cursor.insert_after(Node::Block(Block::horizontal_rule())),
+        Err(PathError::NotInSequence(NodePath::root()))
+    );
+
+    let document = cursor.into_document();
+    ::std::assert_eq!(document.blocks.stringify(), "one two three");
+    ::std::assert_eq!(document.blocks.content.len(), 3);
+    ::std::assert_eq!(
+        document.blocks.content[1].content,
+        BlockContent::HorizontalRule
+    );
+    ::std::assert!(matches!(
+        document.get(&"blocks[2]/items[0]/blocks[1]".parse().unwrap()),
+        Some(Node::Block(Block {
+            content: BlockContent::HorizontalRule,
+            ..
+        }))
+    ));
+}
@@ -0,0 +1,942 @@
+//! Structural comparison of documents
+
+use imbl::{HashMap, Vector};
+use std::collections::BTreeSet;
+use std::fmt::{self, Debug, Display};
+use std::ops::Range;
+
+use crate::ast::*;
+use crate::interned::InternedString;
+use crate::path::{Node, NodePath, PathStep};
+#[cfg(test)]
+use crate::stringify::Stringify;
+use crate::table::TablePart;
+
+/// A change found by [`diff`].
+///
+/// Paths of deleted and replaced nodes, and the `old_path` of other edits,
+/// refer to the old document. Paths of inserted nodes, and the `new_path` of
+/// other edits, refer to the new one.
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub enum Edit<A>
+where
+    A: DecorationScheme,
+{
+    /// A node present only in the new document.
+    Insert { path: NodePath, node: Node<A> },
+    /// A node present only in the old document.
+    Delete { path: NodePath, node: Node<A> },
+    /// A node which changed too much to be compared part by part, such as a
+    /// paragraph which became a header, or a word which was reworded.
+    Replace {
+        old_path: NodePath,
+        new_path: NodePath,
+        old: Node<A>,
+        new: Node<A>,
+    },
+    /// A block which was deleted in one place and inserted, unchanged, in
+    /// another.
+    Move {
+        old_path: NodePath,
+        new_path: NodePath,
+        node: Node<A>,
+    },
+    /// A change to the attributes of a block, inline, table row or table cell.
+    /// Changes to its content are separate edits.
+    SetAttr {
+        old_path: NodePath,
+        new_path: NodePath,
+        old: Attr,
+        new: Attr,
+    },
+}
+
+/// The changes between two documents, as found by [`diff`], in document
+/// order.
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct EditScript<A>
+where
+    A: DecorationScheme,
+{
+    pub edits: Vec<Edit<A>>,
+}
+
+impl<A> EditScript<A>
+where
+    A: DecorationScheme,
+{
+    /// Whether the documents are the same.
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+impl<A> Display for Edit<A>
+where
+    A: DecorationScheme,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let paths = |f: &mut fmt::Formatter<'_>, old: &NodePath, new: &NodePath| match old == new {
+            true => write!(f, "{}", old),
+            false => write!(f, "{} (now {})", old, new),
+        };
+        match self {
+            Edit::Insert { path, .. } => write!(f, "insert {}", path),
+            Edit::Delete { path, .. } => write!(f, "delete {}", path),
+            Edit::Replace {
+                old_path, new_path, ..
+            } => {
+                write!(f, "replace ")?;
+                paths(f, old_path, new_path)
+            }
+            Edit::Move {
+                old_path, new_path, ..
+            } => write!(f, "move {} to {}", old_path, new_path),
+            Edit::SetAttr {
+                old_path, new_path, ..
+            } => {
+                write!(f, "set attributes of ")?;
+                paths(f, old_path, new_path)
+            }
+        }
+    }
+}
+
+/// One edit per line.
+impl<A> Display for EditScript<A>
+where
+    A: DecorationScheme,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for edit in self.edits.iter() {
+            writeln!(f, "{}", edit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two documents, finding the edits which turn `old` into `new`.
+///
+/// Sequences of blocks, inlines, list items, table rows and so on are aligned
+/// by their longest common subsequence, and the unmatched elements between
+/// two matches are paired in order. A pair of the same kind of node, such as
+/// two paragraphs or two emphases, is compared part by part, and any other
+/// pair is an [`Edit::Replace`]. Elements left over are inserted or deleted,
+/// and a block which is both is a [`Edit::Move`]. Text is compared word by
+/// word, since inlines are compared in expanded form.
+///
+/// Subtrees which are shared by the two documents, as they are when `new`
+/// was made from a clone of `old`, are skipped without being traversed.
+/// Sequences which differ in too many elements to align in bounded memory
+/// are treated as changed from their first difference to their last.
+pub fn diff<A>(old: &Pandoc<A>, new: &Pandoc<A>) -> EditScript<A>
+where
+    A: DecorationScheme,
+    A::MetaValue: PartialEq,
+    A::Blocks: PartialEq,
+    A::Block: PartialEq,
+    A::Inlines: PartialEq,
+    A::Inline: PartialEq,
+    A::Caption: PartialEq,
+    A::Citation: PartialEq,
+    A::Table: PartialEq,
+    A::TableHead: PartialEq,
+    A::TableBody: PartialEq,
+    A::TableFoot: PartialEq,
+    A::Row: PartialEq,
+    A::Cell: PartialEq,
+{
+    let mut differ = Differ { edits: Vec::new() };
+    let root = NodePath::root();
+    differ.diff_meta_map(&old.meta, &new.meta, &root, &root);
+    differ.diff_blocks(&old.blocks, &new.blocks, &root, &root);
+    differ.find_moves();
+    EditScript {
+        edits: differ.edits,
+    }
+}
+
+/// Compare two sequences of blocks, as [`diff`] does. Paths are relative to
+/// the sequences.
+pub fn diff_blocks<A>(old: &Blocks<A>, new: &Blocks<A>) -> EditScript<A>
+where
+    A: DecorationScheme,
+    A::MetaValue: PartialEq,
+    A::Blocks: PartialEq,
+    A::Block: PartialEq,
+    A::Inlines: PartialEq,
+    A::Inline: PartialEq,
+    A::Caption: PartialEq,
+    A::Citation: PartialEq,
+    A::Table: PartialEq,
+    A::TableHead: PartialEq,
+    A::TableBody: PartialEq,
+    A::TableFoot: PartialEq,
+    A::Row: PartialEq,
+    A::Cell: PartialEq,
+{
+    let mut differ = Differ { edits: Vec::new() };
+    let root = NodePath::root();
+    differ.diff_blocks(old, new, &root, &root);
+    differ.find_moves();
+    EditScript {
+        edits: differ.edits,
+    }
+}
+
+/// A run of an alignment of two sequences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Chunk {
+    /// An element of the old sequence which matches one of the new.
+    Same(usize, usize),
+    /// Elements between two matches.
+    Changed(Range<usize>, Range<usize>),
+}
+
+/// The largest number of pairs of elements which [`align`] compares, after
+/// skipping a common prefix and suffix. Its tables take a few bytes per pair.
+const MAX_ALIGN_PAIRS: usize = 1 << 20;
+
+/// Align two sequences of the given lengths by their longest common
+/// subsequence, given whether the elements at two indices match. If more than
+/// [`MAX_ALIGN_PAIRS`] pairs would have to be compared, everything between
+/// the common prefix and suffix is one changed run.
+pub(crate) fn align<F>(old: usize, new: usize, mut eq: F) -> Vec<Chunk>
+where
+    F: FnMut(usize, usize) -> bool,
+{
+    let prefix = (0..old.min(new)).take_while(|i| eq(*i, *i)).count();
+    let suffix = (0..old.min(new) - prefix)
+        .take_while(|i| eq(old - 1 - i, new - 1 - i))
+        .count();
+    let (n, m) = (old - prefix - suffix, new - prefix - suffix);
+
+    let mut chunks = Vec::from_iter((0..prefix).map(|i| Chunk::Same(i, i)));
+    let changed = |chunks: &mut Vec<Chunk>, i: Range<usize>, j: Range<usize>| {
+        if !i.is_empty() || !j.is_empty() {
+            let shift = |r: Range<usize>| prefix + r.start..prefix + r.end;
+            chunks.push(Chunk::Changed(shift(i), shift(j)));
+        }
+    };
+    if n.saturating_mul(m) > MAX_ALIGN_PAIRS {
+        changed(&mut chunks, 0..n, 0..m);
+        chunks.extend((0..suffix).map(|k| Chunk::Same(old - suffix + k, new - suffix + k)));
+        return chunks;
+    }
+
+    // lcs[i][j] is the length of the longest common subsequence of the
+    // elements from i and j onwards.
+    let mut same = vec![vec![false; m]; n];
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            same[i][j] = eq(prefix + i, prefix + j);
+            lcs[i][j] = match same[i][j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let (mut gap_i, mut gap_j) = (0, 0);
+    while i < n && j < m {
+        if same[i][j] {
+            changed(&mut chunks, gap_i..i, gap_j..j);
+            chunks.push(Chunk::Same(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+            (gap_i, gap_j) = (i, j);
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    changed(&mut chunks, gap_i..n, gap_j..m);
+    chunks.extend((0..suffix).map(|k| Chunk::Same(old - suffix + k, new - suffix + k)));
+    chunks
+}
+
+struct Differ<A>
+where
+    A: DecorationScheme,
+{
+    edits: Vec<Edit<A>>,
+}
+
+/// How to compare the elements of a sequence.
+struct Elements<A, T>
+where
+    A: DecorationScheme,
+{
+    node: fn(&T) -> Node<A>,
+    eq: fn(&T, &T) -> bool,
+    /// Record the changes between two elements, returning `false` if they
+    /// should be replaced instead.
+    diff: fn(&mut Differ<A>, &T, &T, &NodePath, &NodePath) -> bool,
+}
+
+impl<A> Differ<A>
+where
+    A: DecorationScheme,
+    A::MetaValue: PartialEq,
+    A::Blocks: PartialEq,
+    A::Block: PartialEq,
+    A::Inlines: PartialEq,
+    A::Inline: PartialEq,
+    A::Caption: PartialEq,
+    A::Citation: PartialEq,
+    A::Table: PartialEq,
+    A::TableHead: PartialEq,
+    A::TableBody: PartialEq,
+    A::TableFoot: PartialEq,
+    A::Row: PartialEq,
+    A::Cell: PartialEq,
+{
+    fn diff_seq<T, S>(
+        &mut self,
+        old: &Vector<T>,
+        new: &Vector<T>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+        step: S,
+        elements: &Elements<A, T>,
+    ) where
+        T: Clone,
+        S: Fn(usize) -> PathStep,
+    {
+        if old.ptr_eq(new) {
+            return;
+        }
+        for chunk in align(old.len(), new.len(), |i, j| (elements.eq)(&old[i], &new[j])) {
+            let Chunk::Changed(o, n) = chunk else {
+                continue;
+            };
+            let paired = o.len().min(n.len());
+            for k in 0..paired {
+                let (i, j) = (o.start + k, n.start + k);
+                let (op, np) = (old_path.child(step(i)), new_path.child(step(j)));
+                self.diff_pair(&old[i], &new[j], op, np, elements);
+            }
+            for i in o.start + paired..o.end {
+                self.edits.push(Edit::Delete {
+                    path: old_path.child(step(i)),
+                    node: (elements.node)(&old[i]),
+                });
+            }
+            for j in n.start + paired..n.end {
+                self.edits.push(Edit::Insert {
+                    path: new_path.child(step(j)),
+                    node: (elements.node)(&new[j]),
+                });
+            }
+        }
+    }
+
+    fn diff_pair<T>(
+        &mut self,
+        old: &T,
+        new: &T,
+        old_path: NodePath,
+        new_path: NodePath,
+        elements: &Elements<A, T>,
+    ) {
+        let start = self.edits.len();
+        let compared = (elements.diff)(self, old, new, &old_path, &new_path);
+        // A difference the comparison can't see, such as in a decoration,
+        // still needs an edit.
+        if !compared || (self.edits.len() == start && !(elements.eq)(old, new)) {
+            self.edits.truncate(start);
+            self.edits.push(Edit::Replace {
+                old_path,
+                new_path,
+                old: (elements.node)(old),
+                new: (elements.node)(new),
+            });
+        }
+    }
+
+    fn diff_attr(&mut self, old: &Attr, new: &Attr, old_path: &NodePath, new_path: &NodePath) {
+        if old != new {
+            self.edits.push(Edit::SetAttr {
+                old_path: old_path.clone(),
+                new_path: new_path.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+    }
+
+    fn diff_blocks(
+        &mut self,
+        old: &Blocks<A>,
+        new: &Blocks<A>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) {
+        let elements = Elements {
+            node: |b: &Block<A>| Node::Block(b.clone()),
+            eq: same_block,
+            diff: Self::diff_block,
+        };
+        self.diff_seq(
+            &old.content,
+            &new.content,
+            old_path,
+            new_path,
+            PathStep::Block,
+            &elements,
+        );
+    }
+
+    fn diff_inlines(
+        &mut self,
+        old: &Inlines<A>,
+        new: &Inlines<A>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) {
+        if shares_inlines(old, new) {
+            return;
+        }
+        let elements = Elements {
+            node: |i: &Inline<A, ExpandedLeaf>| Node::Inline(i.clone()),
+            eq: same_inline,
+            diff: Self::diff_inline,
+        };
+        self.diff_seq(
+            &Vector::from_iter(old.expanded()),
+            &Vector::from_iter(new.expanded()),
+            old_path,
+            new_path,
+            PathStep::Inline,
+            &elements,
+        );
+    }
+
+    fn diff_block(
+        &mut self,
+        old: &Block<A>,
+        new: &Block<A>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> bool {
+        let (op, np) = (old_path, new_path);
+        match (&old.content, &new.content) {
+            (BlockContent::Plain(a), BlockContent::Plain(b))
+            | (BlockContent::Para(a), BlockContent::Para(b)) => self.diff_inlines(a, b, op, np),
+            (BlockContent::Header(l1, a1, i1), BlockContent::Header(l2, a2, i2)) if l1 == l2 => {
+                self.diff_attr(a1, a2, op, np);
+                self.diff_inlines(i1, i2, op, np);
+            }
+            (BlockContent::LineBlock(a), BlockContent::LineBlock(b)) => {
+                let elements = Elements {
+                    node: |i: &Inlines<A>| Node::Inlines(i.clone()),
+                    eq: |a, b| shares_inlines(a, b) || a == b,
+                    diff: |d, a, b, op, np| {
+                        d.diff_inlines(a, b, op, np);
+                        true
+                    },
+                };
+                self.diff_seq(a, b, op, np, PathStep::Line, &elements);
+            }
+            (BlockContent::CodeBlock(a1, c1), BlockContent::CodeBlock(a2, c2)) if c1 == c2 => {
+                self.diff_attr(a1, a2, op, np)
+            }
+            (BlockContent::BlockQuote(a), BlockContent::BlockQuote(b)) => {
+                self.diff_blocks(a, b, op, np)
+            }
+            (BlockContent::OrderedList(l1, a), BlockContent::OrderedList(l2, b)) if l1 == l2 => {
+                self.diff_seq(a, b, op, np, PathStep::Item, &Self::blocks_elements())
+            }
+            (BlockContent::BulletList(a), BlockContent::BulletList(b)) => {
+                self.diff_seq(a, b, op, np, PathStep::Item, &Self::blocks_elements())
+            }
+            (BlockContent::DefinitionList(a), BlockContent::DefinitionList(b)) => {
+                let elements = Elements {
+                    node: |(t, d): &(Inlines<A>, Vector<Blocks<A>>)| {
+                        Node::DefinitionItem(t.clone(), d.clone())
+                    },
+                    eq: |a, b| a == b,
+                    diff: |d, a, b, op, np| {
+                        let (ot, nt) = (op.child(PathStep::Term), np.child(PathStep::Term));
+                        d.diff_inlines(&a.0, &b.0, &ot, &nt);
+                        let elements = Self::blocks_elements();
+                        d.diff_seq(&a.1, &b.1, op, np, PathStep::Definition, &elements);
+                        true
+                    },
+                };
+                self.diff_seq(a, b, op, np, PathStep::Item, &elements);
+            }
+            (BlockContent::Table(a), BlockContent::Table(b)) => {
+                return self.diff_table(a, b, op, np)
+            }
+            (BlockContent::Figure(a1, c1, b1), BlockContent::Figure(a2, c2, b2)) => {
+                self.diff_attr(a1, a2, op, np);
+                if !self.diff_caption(c1, c2, op, np) {
+                    return false;
+                }
+                self.diff_blocks(b1, b2, op, np);
+            }
+            (BlockContent::Div(a1, b1), BlockContent::Div(a2, b2)) => {
+                self.diff_attr(a1, a2, op, np);
+                self.diff_blocks(b1, b2, op, np);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn diff_inline(
+        &mut self,
+        old: &Inline<A, ExpandedLeaf>,
+        new: &Inline<A, ExpandedLeaf>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> bool {
+        let (op, np) = (old_path, new_path);
+        match (&old.content, &new.content) {
+            (InlineContent::Emph(a), InlineContent::Emph(b))
+            | (InlineContent::Underline(a), InlineContent::Underline(b))
+            | (InlineContent::Strong(a), InlineContent::Strong(b))
+            | (InlineContent::Strikeout(a), InlineContent::Strikeout(b))
+            | (InlineContent::Superscript(a), InlineContent::Superscript(b))
+            | (InlineContent::Subscript(a), InlineContent::Subscript(b))
+            | (InlineContent::SmallCaps(a), InlineContent::SmallCaps(b)) => {
+                self.diff_inlines(a, b, op, np)
+            }
+            (InlineContent::Quoted(q1, a), InlineContent::Quoted(q2, b)) if q1 == q2 => {
+                self.diff_inlines(a, b, op, np)
+            }
+            (InlineContent::Cite(c1, a), InlineContent::Cite(c2, b)) if c1 == c2 => {
+                self.diff_inlines(a, b, op, np)
+            }
+            (InlineContent::Code(a1, c1), InlineContent::Code(a2, c2)) if c1 == c2 => {
+                self.diff_attr(a1, a2, op, np)
+            }
+            (InlineContent::Link(a1, i1, t1), InlineContent::Link(a2, i2, t2))
+            | (InlineContent::Image(a1, i1, t1), InlineContent::Image(a2, i2, t2))
+                if t1 == t2 =>
+            {
+                self.diff_attr(a1, a2, op, np);
+                self.diff_inlines(i1, i2, op, np);
+            }
+            (InlineContent::Note(a), InlineContent::Note(b)) => self.diff_blocks(a, b, op, np),
+            (InlineContent::Span(a1, i1), InlineContent::Span(a2, i2)) => {
+                self.diff_attr(a1, a2, op, np);
+                self.diff_inlines(i1, i2, op, np);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn diff_caption(
+        &mut self,
+        old: &Caption<A>,
+        new: &Caption<A>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> bool {
+        if old.decoration != new.decoration {
+            return false;
+        }
+        match (&old.short, &new.short) {
+            (Some(a), Some(b)) => {
+                let op = old_path.child(PathStep::ShortCaption);
+                let np = new_path.child(PathStep::ShortCaption);
+                self.diff_inlines(a, b, &op, &np);
+            }
+            (None, None) => {}
+            _ => return false,
+        }
+        let op = old_path.child(PathStep::Caption);
+        let np = new_path.child(PathStep::Caption);
+        self.diff_blocks(&old.full, &new.full, &op, &np);
+        true
+    }
+
+    /// Tables are compared row by row if they have the same columns and
+    /// bodies, and replaced otherwise.
+    fn diff_table(
+        &mut self,
+        old: &Table<A>,
+        new: &Table<A>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> bool {
+        let head = |t: &Table<A>| TableHead {
+            rows: Vector::new(),
+            ..t.head.clone()
+        };
+        let foot = |t: &Table<A>| TableFoot {
+            rows: Vector::new(),
+            ..t.foot.clone()
+        };
+        let bodies = |t: &Table<A>| {
+            Vector::from_iter(t.body.iter().map(|b| TableBody {
+                intermediate_head: Vector::new(),
+                rows: Vector::new(),
+                ..b.clone()
+            }))
+        };
+        if old.decoration != new.decoration
+            || old.colspecs != new.colspecs
+            || head(old) != head(new)
+            || foot(old) != foot(new)
+            || bodies(old) != bodies(new)
+        {
+            return false;
+        }
+
+        let (op, np) = (old_path, new_path);
+        self.diff_attr(&old.attrs, &new.attrs, op, np);
+        if !self.diff_caption(&old.caption, &new.caption, op, np) {
+            return false;
+        }
+        let rows = Elements {
+            node: |r: &Row<A>| Node::Row(r.clone()),
+            eq: |a, b| a == b,
+            diff: Self::diff_row,
+        };
+        let row = |part| move |i| PathStep::Row(part, i);
+        let (o, n) = (&old.head.rows, &new.head.rows);
+        self.diff_seq(o, n, op, np, row(TablePart::Head), &rows);
+        for (i, (a, b)) in old.body.iter().zip(new.body.iter()).enumerate() {
+            let (o, n) = (&a.intermediate_head, &b.intermediate_head);
+            self.diff_seq(o, n, op, np, row(TablePart::BodyHead(i)), &rows);
+            self.diff_seq(&a.rows, &b.rows, op, np, row(TablePart::Body(i)), &rows);
+        }
+        let (o, n) = (&old.foot.rows, &new.foot.rows);
+        self.diff_seq(o, n, op, np, row(TablePart::Foot), &rows);
+        true
+    }
+
+    fn diff_row(
+        &mut self,
+        old: &Row<A>,
+        new: &Row<A>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> bool {
+        if old.decoration != new.decoration {
+            return false;
+        }
+        self.diff_attr(&old.attrs, &new.attrs, old_path, new_path);
+        let cells = Elements {
+            node: |c: &Cell<A>| Node::Cell(c.clone()),
+            eq: |a, b| a == b,
+            diff: |d, a, b, op, np| {
+                if a.decoration != b.decoration
+                    || a.alignment != b.alignment
+                    || a.row_span != b.row_span
+                    || a.col_span != b.col_span
+                {
+                    return false;
+                }
+                d.diff_attr(&a.attrs, &b.attrs, op, np);
+                d.diff_blocks(&a.blocks, &b.blocks, op, np);
+                true
+            },
+        };
+        let (o, n) = (&old.cells, &new.cells);
+        self.diff_seq(o, n, old_path, new_path, PathStep::Cell, &cells);
+        true
+    }
+
+    fn diff_meta_map(
+        &mut self,
+        old: &HashMap<InternedString, MetaValue<A>>,
+        new: &HashMap<InternedString, MetaValue<A>>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) {
+        if old.ptr_eq(new) {
+            return;
+        }
+        let elements = Self::meta_elements();
+        let keys = BTreeSet::from_iter(old.keys().chain(new.keys()));
+        for key in keys {
+            let step = || PathStep::Meta(key.clone());
+            match (old.get(key), new.get(key)) {
+                (Some(a), Some(b)) if a != b => {
+                    let (op, np) = (old_path.child(step()), new_path.child(step()));
+                    self.diff_pair(a, b, op, np, &elements);
+                }
+                (Some(a), None) => self.edits.push(Edit::Delete {
+                    path: old_path.child(step()),
+                    node: Node::MetaValue(a.clone()),
+                }),
+                (None, Some(b)) => self.edits.push(Edit::Insert {
+                    path: new_path.child(step()),
+                    node: Node::MetaValue(b.clone()),
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    fn diff_meta(
+        &mut self,
+        old: &MetaValue<A>,
+        new: &MetaValue<A>,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> bool {
+        let (op, np) = (old_path, new_path);
+        match (&old.content, &new.content) {
+            (MetaValueContent::Map(a), MetaValueContent::Map(b)) => {
+                self.diff_meta_map(a, b, op, np)
+            }
+            (MetaValueContent::List(a), MetaValueContent::List(b)) => {
+                self.diff_seq(a, b, op, np, PathStep::Item, &Self::meta_elements())
+            }
+            (MetaValueContent::Inlines(a), MetaValueContent::Inlines(b)) => {
+                self.diff_inlines(a, b, op, np)
+            }
+            (MetaValueContent::Blocks(a), MetaValueContent::Blocks(b)) => {
+                self.diff_blocks(a, b, op, np)
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn blocks_elements() -> Elements<A, Blocks<A>> {
+        Elements {
+            node: |b| Node::Blocks(b.clone()),
+            eq: |a, b| shares_blocks(a, b) || a == b,
+            diff: |d, a, b, op, np| {
+                d.diff_blocks(a, b, op, np);
+                true
+            },
+        }
+    }
+
+    fn meta_elements() -> Elements<A, MetaValue<A>> {
+        Elements {
+            node: |m| Node::MetaValue(m.clone()),
+            eq: |a, b| a == b,
+            diff: Self::diff_meta,
+        }
+    }
+
+    /// Turn each deleted block which is inserted unchanged elsewhere into a
+    /// move, at the position of the deletion.
+    fn find_moves(&mut self) {
+        let mut moved = vec![None; self.edits.len()];
+        let mut used = vec![false; self.edits.len()];
+        for (i, edit) in self.edits.iter().enumerate() {
+            let Edit::Delete {
+                node: Node::Block(old),
+                ..
+            } = edit
+            else {
+                continue;
+            };
+            let found = self.edits.iter().enumerate().position(|(j, e)| {
+                !used[j]
+                    && matches!(e, Edit::Insert { node: Node::Block(new), .. } if same_block(old, new))
+            });
+            if let Some(j) = found {
+                used[j] = true;
+                moved[i] = Some(j);
+            }
+        }
+
+        let mut inserts = Vec::from_iter(self.edits.iter().map(|e| match e {
+            Edit::Insert { path, .. } => Some(path.clone()),
+            _ => None,
+        }));
+        let edits = std::mem::take(&mut self.edits);
+        self.edits = Vec::from_iter(edits.into_iter().enumerate().filter_map(|(i, edit)| {
+            match (edit, moved[i]) {
+                (Edit::Delete { path, node }, Some(j)) => Some(Edit::Move {
+                    old_path: path,
+                    new_path: inserts[j].take()?,
+                    node,
+                }),
+                _ if used[i] => None,
+                (edit, _) => Some(edit),
+            }
+        }));
+    }
+}
+
+pub(crate) fn shares_blocks<A>(a: &Blocks<A>, b: &Blocks<A>) -> bool
+where
+    A: DecorationScheme,
+    A::Blocks: PartialEq,
+{
+    a.content.ptr_eq(&b.content) && a.decoration == b.decoration
+}
+
+pub(crate) fn shares_inlines<A>(a: &Inlines<A>, b: &Inlines<A>) -> bool
+where
+    A: DecorationScheme,
+    A::Inlines: PartialEq,
+{
+    let shared = match (&a.content, &b.content) {
+        (InlinesContent::Condensed(x), InlinesContent::Condensed(y)) => x.ptr_eq(y),
+        (InlinesContent::Expanded(x), InlinesContent::Expanded(y)) => x.ptr_eq(y),
+        _ => false,
+    };
+    shared && a.decoration == b.decoration
+}
+
+/// Whether two blocks are equal, without comparing children which they share.
+pub(crate) fn same_block<A>(a: &Block<A>, b: &Block<A>) -> bool
+where
+    A: DecorationScheme,
+    Block<A>: PartialEq,
+    A::Block: PartialEq,
+    A::Blocks: PartialEq,
+    A::Inlines: PartialEq,
+    A::Caption: PartialEq,
+    Caption<A>: PartialEq,
+{
+    let shared = match (&a.content, &b.content) {
+        (BlockContent::Plain(x), BlockContent::Plain(y))
+        | (BlockContent::Para(x), BlockContent::Para(y)) => shares_inlines(x, y),
+        (BlockContent::Header(l1, a1, x), BlockContent::Header(l2, a2, y)) => {
+            l1 == l2 && a1 == a2 && shares_inlines(x, y)
+        }
+        (BlockContent::LineBlock(x), BlockContent::LineBlock(y)) => x.ptr_eq(y),
+        (BlockContent::BlockQuote(x), BlockContent::BlockQuote(y)) => shares_blocks(x, y),
+        (BlockContent::OrderedList(l1, x), BlockContent::OrderedList(l2, y)) => {
+            l1 == l2 && x.ptr_eq(y)
+        }
+        (BlockContent::BulletList(x), BlockContent::BulletList(y)) => x.ptr_eq(y),
+        (BlockContent::DefinitionList(x), BlockContent::DefinitionList(y)) => x.ptr_eq(y),
+        (BlockContent::Figure(a1, c1, x), BlockContent::Figure(a2, c2, y)) => {
+            a1 == a2 && c1 == c2 && shares_blocks(x, y)
+        }
+        (BlockContent::Div(a1, x), BlockContent::Div(a2, y)) => a1 == a2 && shares_blocks(x, y),
+        _ => false,
+    };
+    (shared && a.decoration == b.decoration) || a == b
+}
+
+/// Whether two inlines are equal, without comparing children which they
+/// share.
+pub(crate) fn same_inline<A>(a: &Inline<A, ExpandedLeaf>, b: &Inline<A, ExpandedLeaf>) -> bool
+where
+    A: DecorationScheme,
+    Inline<A, ExpandedLeaf>: PartialEq,
+    A::Inline: PartialEq,
+    A::Blocks: PartialEq,
+    A::Inlines: PartialEq,
+{
+    let shared = match (&a.content, &b.content) {
+        (InlineContent::Emph(x), InlineContent::Emph(y))
+        | (InlineContent::Underline(x), InlineContent::Underline(y))
+        | (InlineContent::Strong(x), InlineContent::Strong(y))
+        | (InlineContent::Strikeout(x), InlineContent::Strikeout(y))
+        | (InlineContent::Superscript(x), InlineContent::Superscript(y))
+        | (InlineContent::Subscript(x), InlineContent::Subscript(y))
+        | (InlineContent::SmallCaps(x), InlineContent::SmallCaps(y)) => shares_inlines(x, y),
+        (InlineContent::Quoted(q1, x), InlineContent::Quoted(q2, y)) => {
+            q1 == q2 && shares_inlines(x, y)
+        }
+        (InlineContent::Cite(c1, x), InlineContent::Cite(c2, y)) => {
+            c1.ptr_eq(c2) && shares_inlines(x, y)
+        }
+        (InlineContent::Link(a1, x, t1), InlineContent::Link(a2, y, t2))
+        | (InlineContent::Image(a1, x, t1), InlineContent::Image(a2, y, t2)) => {
+            a1 == a2 && t1 == t2 && shares_inlines(x, y)
+        }
+        (InlineContent::Note(x), InlineContent::Note(y)) => shares_blocks(x, y),
+        (InlineContent::Span(a1, x), InlineContent::Span(a2, y)) => {
+            a1 == a2 && shares_inlines(x, y)
+        }
+        _ => false,
+    };
+    (shared && a.decoration == b.decoration) || a == b
+}
+
+#[test]
+fn test_diff() {
+    let mut old: Pandoc<NullScheme> = Pandoc {
+        decoration: None,
+        meta: HashMap::new(),
+        blocks: crate::blocks![
+            Block::para("Draft."),
+            Block::header(1, Attr::default(), "Terms"),
+            Block::para("The buyer pays the seller."),
+            Block::div(
+                Attr::builder().id("notice").build(),
+                crate::blocks![Block::para("Read carefully.")]
+            ),
+        ],
+    };
+    old.meta
+        .insert("title".into(), MetaValueContent::Bool(true).into());
+
+    let mut new = old.clone();
+    let blocks = &mut new.blocks.content;
+    blocks[2] = Block::para("The buyer pays the agent.");
+    if let BlockContent::Div(attr, _) = &mut blocks[3].content {
+        attr.add_class("warning");
+    }
+    let draft = blocks.pop_front().unwrap();
+    blocks.push_back(draft);
+    blocks.push_back(Block::horizontal_rule());
+    new.meta.remove(&InternedString::from("title"));
+
+    ::std::assert!(diff(&old, &old.clone()).is_empty());
+
+    let script = diff(&old, &new);
+    ::std::assert_eq!(
+        script.to_string(),
+        "delete meta[title]\n\
+         move blocks[0] to blocks[3]\n\
+         replace blocks[2]/inlines[8] (now blocks[1]/inlines[8])\n\
+         set attributes of blocks[3] (now blocks[2])\n\
+         insert blocks[4]\n"
+    );
+    let Edit::Replace {
+        old: Node::Inline(old_word),
+        new: Node::Inline(new_word),
+        ..
+    } = &script.edits[2]
+    else {
+        panic!("expected a replaced word");
+    };
+    ::std::assert_eq!(
+        (old_word.stringify(), new_word.stringify()),
+        ("seller.".to_string(), "agent.".to_string())
+    );
+
+    // Sequences too long to align are changed between their common ends.
+    ::std::assert_eq!(
+        align(3000, 3000, |_, _| false),
+        vec![Chunk::Changed(0..3000, 0..3000)]
+    );
+    ::std::assert_eq!(
+        align(3000, 3000, |i, j| i == j && (i == 0 || i == 2999)),
+        vec![
+            Chunk::Same(0, 0),
+            Chunk::Changed(1..2999, 1..2999),
+            Chunk::Same(2999, 2999)
+        ]
+    );
+}
@@ -0,0 +1,323 @@
+//! Automatic identifiers for headers
+
+use std::collections::HashSet;
+use unicode_general_category::{get_general_category, GeneralCategory};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::ast::*;
+use crate::interned::InternedString;
+use crate::stringify::Stringify;
+use crate::visit::{Map, Query};
+
+/// The algorithm used to derive an identifier from text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IdentifierStyle {
+    /// Pandoc's `auto_identifiers` extension.
+    #[default]
+    Pandoc,
+    /// Pandoc's `gfm_auto_identifiers` extension, which mimics GitHub.
+    Gfm,
+}
+
+/// Options for generating identifiers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdentifierOptions {
+    pub style: IdentifierStyle,
+    /// Strip accents from letters and drop other non-ASCII characters, like
+    /// Pandoc's `ascii_identifiers` extension.
+    pub ascii: bool,
+}
+
+/// Derive an identifier from text, as Pandoc does for headers.
+///
+/// Characters are classified by their Unicode general category, as Haskell's
+/// `isAlpha`, `isAlphaNum` and `isSpace` do, and the `ascii` option keeps
+/// the ASCII characters of the text's canonical decomposition (NFD).
+///
+/// The result may be empty, in which case Pandoc uses `section`; see
+/// [`IdentifierSet::unique`].
+pub fn text_to_identifier(text: &str, options: &IdentifierOptions) -> String {
+    // Each character is lowercased on its own, as by `Data.Text.toLower`.
+    let lower = String::from_iter(text.chars().flat_map(char::to_lowercase));
+
+    let ident = match options.style {
+        IdentifierStyle::Pandoc => lower
+            .chars()
+            .filter(|&c| is_space(c) || is_alphanumeric(c) || matches!(c, '_' | '-' | '.'))
+            .collect::<String>()
+            .split(is_space)
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-"),
+        IdentifierStyle::Gfm => lower
+            .chars()
+            .map(|c| if is_space(c) { '-' } else { c })
+            .filter(|&c| is_alphanumeric(c) || c == '-' || is_mark_or_connector(c))
+            .collect(),
+    };
+
+    let ident = if options.ascii {
+        ident.nfd().filter(char::is_ascii).collect()
+    } else {
+        ident
+    };
+
+    match options.style {
+        IdentifierStyle::Pandoc => ident.trim_start_matches(|c| !is_letter(c)).to_string(),
+        IdentifierStyle::Gfm => ident,
+    }
+}
+
+/// Derive an identifier from inlines, as Pandoc does for headers.
+pub fn inlines_to_identifier<A>(inlines: &Inlines<A>, options: &IdentifierOptions) -> String
+where
+    A: DecorationScheme,
+{
+    text_to_identifier(&inlines.stringify(), options)
+}
+
+/// A set of identifiers already in use, from which unique identifiers can be
+/// generated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdentifierSet {
+    used: HashSet<String>,
+}
+
+impl IdentifierSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, identifier: &str) -> bool {
+        self.used.contains(identifier)
+    }
+
+    /// Mark `identifier` as used. Returns whether it was previously unused.
+    pub fn insert(&mut self, identifier: &str) -> bool {
+        self.used.insert(identifier.to_string())
+    }
+
+    /// Return a unique identifier based on `base` and mark it as used.
+    ///
+    /// As in Pandoc, an empty `base` is replaced by `section`, and if `base` is
+    /// already used then the first unused of `base-1`, `base-2`, and so on is
+    /// returned.
+    pub fn unique(&mut self, base: &str) -> String {
+        let base = if base.is_empty() { "section" } else { base };
+        let ident = if self.contains(base) {
+            (1..)
+                .map(|n| format!("{}-{}", base, n))
+                .find(|i| !self.contains(i))
+                .expect("identifier suffixes are unbounded")
+        } else {
+            base.to_string()
+        };
+        self.insert(&ident);
+        ident
+    }
+}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    /// Collect every non-empty identifier in the document: those of headers,
+    /// divs, spans, code, links, images, figures and tables.
+    pub fn identifiers(&self) -> IdentifierSet {
+        let mut collector = IdentifierCollector(IdentifierSet::new());
+        self.query(&mut collector);
+        collector.0
+    }
+
+    /// Give an identifier to every header which lacks one, as Pandoc does
+    /// when reading a document with `auto_identifiers` enabled.
+    ///
+    /// Headers are processed in document order. Explicit identifiers are
+    /// reserved as they are encountered, and generated ones are made unique
+    /// against all earlier ones.
+    pub fn auto_identifiers(&mut self, options: &IdentifierOptions) {
+        let mut identifiers = HeaderIdentifiers {
+            used: IdentifierSet::new(),
+            options,
+        };
+        let blocks = std::mem::take(&mut self.blocks);
+        self.blocks = blocks.walk(&mut identifiers);
+    }
+}
+
+struct HeaderIdentifiers<'a> {
+    used: IdentifierSet,
+    options: &'a IdentifierOptions,
+}
+
+impl<A> Map<A> for HeaderIdentifiers<'_>
+where
+    A: DecorationScheme,
+{
+    fn map_block(&mut self, mut block: Block<A>) -> Block<A> {
+        if let BlockContent::Header(_, attr, inlines) = &mut block.content {
+            if attr.identifier.is_empty() {
+                let ident = self
+                    .used
+                    .unique(&inlines_to_identifier(inlines, self.options));
+                attr.identifier = InternedString::from(ident);
+            } else {
+                self.used.insert(&attr.identifier);
+            }
+        }
+        block
+    }
+}
+
+struct IdentifierCollector(IdentifierSet);
+
+impl IdentifierCollector {
+    fn add(&mut self, attr: &Attr) {
+        if !attr.identifier.is_empty() {
+            self.0.insert(&attr.identifier);
+        }
+    }
+
+    fn add_inline<A, L>(&mut self, content: &InlineContent<A, L>)
+    where
+        A: DecorationScheme,
+    {
+        match content {
+            InlineContent::Code(attr, _)
+            | InlineContent::Link(attr, _, _)
+            | InlineContent::Image(attr, _, _)
+            | InlineContent::Span(attr, _) => self.add(attr),
+            _ => {}
+        }
+    }
+}
+
+impl<A> Query<'_, A> for IdentifierCollector
+where
+    A: DecorationScheme,
+{
+    fn query_block(&mut self, block: &Block<A>) {
+        match &block.content {
+            BlockContent::CodeBlock(attr, _)
+            | BlockContent::Header(_, attr, _)
+            | BlockContent::Figure(attr, _, _)
+            | BlockContent::Div(attr, _) => self.add(attr),
+            BlockContent::Table(table) => self.add(&table.attrs),
+            _ => {}
+        }
+    }
+
+    fn query_inline_condensed(&mut self, inline: &Inline<A, CondensedLeaf>) {
+        self.add_inline(&inline.content)
+    }
+
+    fn query_inline_expanded(&mut self, inline: &Inline<A, ExpandedLeaf>) {
+        self.add_inline(&inline.content)
+    }
+}
+
+/// Haskell's `isSpace`: the ASCII whitespace characters, the non-breaking
+/// space, and beyond Latin-1 the space separators.
+fn is_space(c: char) -> bool {
+    match c {
+        ' ' | '\t'..='\r' | '\u{a0}' => true,
+        '\0'..='\u{377}' => false,
+        _ => get_general_category(c) == GeneralCategory::SpaceSeparator,
+    }
+}
+
+/// Haskell's `isAlpha`: a letter of any general category.
+fn is_letter(c: char) -> bool {
+    use GeneralCategory::*;
+    matches!(
+        get_general_category(c),
+        UppercaseLetter | LowercaseLetter | TitlecaseLetter | ModifierLetter | OtherLetter
+    )
+}
+
+/// Haskell's `isAlphaNum`: a letter or a number of any general category.
+fn is_alphanumeric(c: char) -> bool {
+    use GeneralCategory::*;
+    is_letter(c)
+        || matches!(
+            get_general_category(c),
+            DecimalNumber | LetterNumber | OtherNumber
+        )
+}
+
+/// Whether a character is a mark or connector punctuation, which GitHub
+/// keeps in identifiers.
+fn is_mark_or_connector(c: char) -> bool {
+    use GeneralCategory::*;
+    matches!(
+        get_general_category(c),
+        NonspacingMark | SpacingMark | EnclosingMark | ConnectorPunctuation
+    )
+}
+
+#[test]
+fn test_identifiers() {
+    let pandoc = IdentifierOptions::default();
+    let gfm = IdentifierOptions {
+        style: IdentifierStyle::Gfm,
+        ascii: false,
+    };
+    let ascii = IdentifierOptions {
+        style: IdentifierStyle::Pandoc,
+        ascii: true,
+    };
+
+    ::std::assert_eq!(
+        text_to_identifier("Heading identifiers in HTML", &pandoc),
+        "heading-identifiers-in-html"
+    );
+    ::std::assert_eq!(
+        text_to_identifier("Maître d'hôtel", &pandoc),
+        "maître-dhôtel"
+    );
+    ::std::assert_eq!(
+        text_to_identifier("Maître d'hôtel", &ascii),
+        "maitre-dhotel"
+    );
+    ::std::assert_eq!(
+        text_to_identifier("Dogs?--in my house?", &pandoc),
+        "dogs--in-my-house"
+    );
+    ::std::assert_eq!(
+        text_to_identifier("3. Applications", &pandoc),
+        "applications"
+    );
+    ::std::assert_eq!(text_to_identifier("33", &pandoc), "");
+    ::std::assert_eq!(
+        text_to_identifier("3. Applications", &gfm),
+        "3-applications"
+    );
+    ::std::assert_eq!(text_to_identifier("v1.2 Notes", &gfm), "v12-notes");
+    // Thai tone marks and Tamil vowel signs are marks, which GitHub keeps.
+    ::std::assert_eq!(text_to_identifier("ไม่ ใช่", &gfm), "ไม่-ใช่");
+    ::std::assert_eq!(text_to_identifier("தமிழ்", &gfm), "தமிழ்");
+    ::std::assert_eq!(text_to_identifier("தமிழ்", &pandoc), "தமழ");
+    ::std::assert_eq!(
+        text_to_identifier("Știință și Tehnică", &ascii),
+        "stiinta-si-tehnica"
+    );
+    ::std::assert_eq!(text_to_identifier("Tiếng Việt", &ascii), "tieng-viet");
+    // U+0345 is an Other_Alphabetic mark, which `isAlphaNum` rejects.
+    ::std::assert_eq!(text_to_identifier("a\u{345}b", &pandoc), "ab");
+    ::std::assert_eq!(text_to_identifier("a\u{2028}b", &pandoc), "ab");
+
+    let input = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[
+        {"t":"Header","c":[1,["",[],[]],[{"t":"Str","c":"Intro"}]]},
+        {"t":"Header","c":[1,["",[],[]],[{"t":"Str","c":"Intro"}]]},
+        {"t":"Header","c":[1,["custom",[],[]],[{"t":"Str","c":"Intro"}]]},
+        {"t":"Header","c":[1,["",[],[]],[{"t":"Str","c":"42"}]]}
+    ]}"#;
+    let mut doc: Pandoc<NullScheme> = ::serde_json::from_str(input).unwrap();
+    doc.auto_identifiers(&pandoc);
+    let ids = Vec::from_iter(doc.blocks.content.iter().map(|b| match &b.content {
+        BlockContent::Header(_, attr, _) => attr.identifier.to_string(),
+        _ => unreachable!(),
+    }));
+    ::std::assert_eq!(ids, vec!["intro", "intro-1", "custom", "section"]);
+    ::std::assert!(doc.identifiers().contains("intro-1"));
+}
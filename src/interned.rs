@@ -40,6 +40,12 @@ impl Display for InternedString {
     }
 }
 
+impl Default for InternedString {
+    fn default() -> Self {
+        InternedString::from("")
+    }
+}
+
 impl From<InternedString> for String {
     fn from(v: InternedString) -> Self {
         v.as_ref().into()
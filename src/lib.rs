@@ -1,12 +1,44 @@
 #![recursion_limit = "256"]
 
 mod ast;
+mod citation;
+mod citeproc;
+mod crossref;
+mod cursor;
+mod diff;
+mod ident;
 mod interned;
+mod meta;
+mod notes;
+mod number;
+mod patch;
+mod path;
+mod redline;
+mod section;
+mod stringify;
+mod table;
+mod toc;
 mod visit;
 
 #[macro_use]
 extern crate educe;
 
 pub use ast::*;
+pub use citation::*;
+pub use citeproc::*;
+pub use crossref::*;
+pub use cursor::*;
+pub use diff::*;
+pub use ident::*;
 pub use interned::*;
-pub use visit::*;
\ No newline at end of file
+pub use meta::*;
+pub use notes::*;
+pub use number::*;
+pub use patch::*;
+pub use path::*;
+pub use redline::*;
+pub use section::*;
+pub use stringify::*;
+pub use table::*;
+pub use toc::*;
+pub use visit::*;
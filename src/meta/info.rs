@@ -0,0 +1,498 @@
+use im_rope::Rope;
+use imbl::{HashMap, Vector};
+use std::fmt::Debug;
+
+use super::{meta_inlines, meta_text};
+use crate::ast::*;
+use crate::interned::InternedString;
+
+#[cfg(test)]
+use crate::stringify::Stringify;
+
+/// An author of a document.
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct Author<A>
+where
+    A: DecorationScheme,
+{
+    /// The author's name, or `None` for an author given as a map without a
+    /// `name`, such as a CSL name with `family` and `given` keys.
+    pub name: Option<Inlines<A>>,
+    /// Any other fields given for the author, such as `affiliation` or `email`.
+    pub fields: HashMap<InternedString, MetaValue<A>>,
+}
+
+/// A normalized view of the standard fields of a document's metadata.
+///
+/// Pandoc's readers and templates accept these fields in several shapes: a
+/// title may be a string, inlines or a single paragraph, and an author may be
+/// given as inlines, as a map, or as a list of either. `DocumentInfo` reads all
+/// of these and writes back a canonical form. Fields in a shape it cannot
+/// read, such as a title of several paragraphs, are read as `None` or empty
+/// and left as they are by [`DocumentInfo::write_to`].
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct DocumentInfo<A>
+where
+    A: DecorationScheme,
+{
+    pub title: Option<Inlines<A>>,
+    pub subtitle: Option<Inlines<A>>,
+    pub authors: Vector<Author<A>>,
+    /// The date, as written in the document. See [`DocumentInfo::iso_date`].
+    pub date: Option<Inlines<A>>,
+    pub lang: Option<String>,
+    pub abstract_: Option<Blocks<A>>,
+    pub keywords: Vector<String>,
+}
+
+impl<A> DocumentInfo<A>
+where
+    A: DecorationScheme,
+{
+    /// Read the standard fields from document metadata.
+    pub fn from_meta(meta: &HashMap<InternedString, MetaValue<A>>) -> Self {
+        DocumentInfo {
+            title: get(meta, "title").and_then(meta_inlines),
+            subtitle: get(meta, "subtitle").and_then(meta_inlines),
+            authors: get(meta, "author")
+                .and_then(read_authors)
+                .unwrap_or_default(),
+            date: get(meta, "date").and_then(meta_inlines),
+            lang: get(meta, "lang").and_then(read_lang),
+            abstract_: get(meta, "abstract").and_then(read_blocks),
+            keywords: get(meta, "keywords")
+                .and_then(read_keywords)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Write the standard fields back into document metadata.
+    ///
+    /// Fields which are `None` or empty are removed from the metadata, except
+    /// where the metadata holds a value for them that [`DocumentInfo::from_meta`]
+    /// cannot read; such values are left as they are.
+    pub fn write_to(&self, meta: &mut HashMap<InternedString, MetaValue<A>>) {
+        fn put<A, T>(
+            meta: &mut HashMap<InternedString, MetaValue<A>>,
+            key: &str,
+            value: Option<MetaValueContent<A>>,
+            read: fn(&MetaValue<A>) -> Option<T>,
+        ) where
+            A: DecorationScheme,
+        {
+            let key = InternedString::from(key);
+            match value {
+                Some(v) => {
+                    meta.insert(key, v.into());
+                }
+                None if meta.get(&key).and_then(read).is_some() => {
+                    meta.remove(&key);
+                }
+                None => {}
+            }
+        }
+
+        put(
+            meta,
+            "title",
+            self.title.clone().map(MetaValueContent::Inlines),
+            meta_inlines,
+        );
+        put(
+            meta,
+            "subtitle",
+            self.subtitle.clone().map(MetaValueContent::Inlines),
+            meta_inlines,
+        );
+        put(
+            meta,
+            "author",
+            Some(MetaValueContent::List(Vector::from_iter(
+                self.authors.iter().map(write_author),
+            )))
+            .filter(|_| !self.authors.is_empty()),
+            read_authors,
+        );
+        put(
+            meta,
+            "date",
+            self.date.clone().map(MetaValueContent::Inlines),
+            meta_inlines,
+        );
+        put(
+            meta,
+            "lang",
+            self.lang
+                .as_deref()
+                .map(|l| MetaValueContent::String(Rope::from(l))),
+            read_lang,
+        );
+        put(
+            meta,
+            "abstract",
+            self.abstract_.clone().map(MetaValueContent::Blocks),
+            read_blocks,
+        );
+        put(
+            meta,
+            "keywords",
+            Some(MetaValueContent::List(Vector::from_iter(
+                self.keywords
+                    .iter()
+                    .map(|k| MetaValueContent::Inlines(Inlines::from_text(k)).into()),
+            )))
+            .filter(|_| !self.keywords.is_empty()),
+            read_keywords,
+        );
+    }
+
+    /// The date in ISO 8601 form, if it can be parsed by [`normalize_date`].
+    pub fn iso_date(&self) -> Option<String> {
+        let date: MetaValue<A> = MetaValueContent::Inlines(self.date.clone()?).into();
+        normalize_date(&meta_text(&date)?)
+    }
+}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    /// Read the standard fields of this document's metadata.
+    pub fn document_info(&self) -> DocumentInfo<A> {
+        DocumentInfo::from_meta(&self.meta)
+    }
+
+    /// Replace the standard fields of this document's metadata.
+    pub fn set_document_info(&mut self, info: &DocumentInfo<A>) {
+        info.write_to(&mut self.meta)
+    }
+}
+
+/// Normalize a date into `YYYY-MM-DD` form, following Pandoc's
+/// `normalizeDate`.
+///
+/// The formats accepted are `MM/DD/YY`, `MM/DD/YYYY`, `YYYY-MM-DD`,
+/// `DD Mon YYYY`, `D Month YYYY`, `Mon. D, YYYY`, `Month D, YYYY`, `YYYYMMDD`,
+/// `YYYYMM` and `YYYY`. Dates whose year falls outside 1601–9999 are rejected.
+pub fn normalize_date(date: &str) -> Option<String> {
+    const FORMATS: &[&str] = &[
+        "%m/%d/%y",
+        "%m/%d/%Y",
+        "%Y-%m-%d",
+        "%d %b %Y",
+        "%e %B %Y",
+        "%b. %e, %Y",
+        "%B %e, %Y",
+        "%Y%m%d",
+        "%Y%m",
+        "%Y",
+    ];
+
+    let date = date.trim();
+    FORMATS
+        .iter()
+        .find_map(|format| parse_date(date, format))
+        .filter(|(y, _, _)| (1601..=9999).contains(y))
+        .map(|(y, m, d)| format!("{:04}-{:02}-{:02}", y, m, d))
+}
+
+const MONTHS: &[&str] = &[
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+fn parse_date(input: &str, format: &str) -> Option<(u32, u32, u32)> {
+    fn digits(input: &mut &str, min: usize, max: usize) -> Option<u32> {
+        let len = input
+            .bytes()
+            .take(max)
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if len < min {
+            return None;
+        }
+        let (n, rest) = input.split_at(len);
+        *input = rest;
+        n.parse().ok()
+    }
+
+    fn month_name(input: &mut &str, full: bool) -> Option<u32> {
+        MONTHS.iter().zip(1..).find_map(|(name, n)| {
+            let name = if full { name } else { &name[..3] };
+            let candidate = input.get(..name.len())?;
+            if candidate.eq_ignore_ascii_case(name) {
+                *input = &input[name.len()..];
+                Some(n)
+            } else {
+                None
+            }
+        })
+    }
+
+    let (mut year, mut month, mut day) = (None, None, None);
+    let mut input = input;
+    let mut format = format.chars();
+
+    while let Some(f) = format.next() {
+        match f {
+            '%' => match format.next()? {
+                'Y' => year = Some(digits(&mut input, 4, 4)?),
+                'y' => {
+                    let y = digits(&mut input, 2, 2)?;
+                    year = Some(if y >= 69 { 1900 + y } else { 2000 + y });
+                }
+                'm' => month = Some(digits(&mut input, 1, 2)?),
+                'd' => day = Some(digits(&mut input, 1, 2)?),
+                'e' => {
+                    input = input.trim_start_matches(' ');
+                    day = Some(digits(&mut input, 1, 2)?);
+                }
+                'b' => month = Some(month_name(&mut input, false)?),
+                'B' => month = Some(month_name(&mut input, true)?),
+                _ => return None,
+            },
+            ' ' => input = input.trim_start(),
+            _ => input = input.strip_prefix(f)?,
+        }
+    }
+
+    if !input.is_empty() {
+        return None;
+    }
+
+    let year = year?;
+    let month = month.unwrap_or(1);
+    let day = day.unwrap_or(1);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+
+    if (1..=days_in_month).contains(&day) {
+        Some((year, month, day))
+    } else {
+        None
+    }
+}
+
+fn get<'a, A>(
+    meta: &'a HashMap<InternedString, MetaValue<A>>,
+    key: &str,
+) -> Option<&'a MetaValue<A>>
+where
+    A: DecorationScheme,
+{
+    meta.get(&InternedString::from(key))
+}
+
+fn read_blocks<A>(value: &MetaValue<A>) -> Option<Blocks<A>>
+where
+    A: DecorationScheme,
+{
+    let para = |inlines| Blocks {
+        decoration: None,
+        content: Vector::unit(Block {
+            decoration: None,
+            content: BlockContent::Para(inlines),
+        }),
+    };
+
+    match &value.content {
+        MetaValueContent::Blocks(b) => Some(b.clone()),
+        MetaValueContent::Inlines(_) | MetaValueContent::String(_) => meta_inlines(value).map(para),
+        _ => None,
+    }
+}
+
+fn read_lang<A>(value: &MetaValue<A>) -> Option<String>
+where
+    A: DecorationScheme,
+{
+    meta_text(value)
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+}
+
+/// Read an author or a list of authors. Fails if any of them can't be read.
+fn read_authors<A>(value: &MetaValue<A>) -> Option<Vector<Author<A>>>
+where
+    A: DecorationScheme,
+{
+    match &value.content {
+        MetaValueContent::List(l) => l.iter().map(read_author).collect(),
+        _ => read_author(value).map(Vector::unit),
+    }
+}
+
+fn read_author<A>(value: &MetaValue<A>) -> Option<Author<A>>
+where
+    A: DecorationScheme,
+{
+    match &value.content {
+        MetaValueContent::Map(m) => {
+            let key = InternedString::from("name");
+            let mut fields = m.clone();
+            // A name that can't be read as inlines stays among the fields.
+            let name = fields.get(&key).and_then(meta_inlines);
+            if name.is_some() {
+                fields.remove(&key);
+            }
+            Some(Author { name, fields })
+        }
+        _ => meta_inlines(value).map(|name| Author {
+            name: Some(name),
+            fields: HashMap::new(),
+        }),
+    }
+}
+
+/// Read a list of keywords, or a comma-separated string of them. Fails if any
+/// of the list can't be read.
+fn read_keywords<A>(value: &MetaValue<A>) -> Option<Vector<String>>
+where
+    A: DecorationScheme,
+{
+    match &value.content {
+        MetaValueContent::List(l) => l.iter().map(meta_text).collect(),
+        _ => Some(Vector::from_iter(
+            meta_text(value)?
+                .split(',')
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty()),
+        )),
+    }
+}
+
+fn write_author<A>(author: &Author<A>) -> MetaValue<A>
+where
+    A: DecorationScheme,
+{
+    match &author.name {
+        Some(name) if author.fields.is_empty() => MetaValueContent::Inlines(name.clone()).into(),
+        Some(name) => {
+            let mut fields = author.fields.clone();
+            fields.insert(
+                InternedString::from("name"),
+                MetaValueContent::Inlines(name.clone()).into(),
+            );
+            MetaValueContent::Map(fields).into()
+        }
+        None => MetaValueContent::Map(author.fields.clone()).into(),
+    }
+}
+
+#[test]
+fn test_normalize_date() {
+    ::std::assert_eq!(normalize_date("2023-05-01").as_deref(), Some("2023-05-01"));
+    ::std::assert_eq!(normalize_date("05/01/2023").as_deref(), Some("2023-05-01"));
+    ::std::assert_eq!(normalize_date("05/01/23").as_deref(), Some("2023-05-01"));
+    ::std::assert_eq!(normalize_date("1 May 2023").as_deref(), Some("2023-05-01"));
+    ::std::assert_eq!(normalize_date("May 1, 2023").as_deref(), Some("2023-05-01"));
+    ::std::assert_eq!(
+        normalize_date("Feb. 29, 2024").as_deref(),
+        Some("2024-02-29")
+    );
+    ::std::assert_eq!(normalize_date("202305").as_deref(), Some("2023-05-01"));
+    ::std::assert_eq!(normalize_date(" 2023 ").as_deref(), Some("2023-01-01"));
+    ::std::assert_eq!(normalize_date("Feb. 29, 2023"), None);
+    ::std::assert_eq!(normalize_date("1500"), None);
+    ::std::assert_eq!(normalize_date("yesterday"), None);
+}
+
+#[test]
+fn test_document_info() {
+    let input = r#"{"pandoc-api-version":[1,23,1],"blocks":[],"meta":{
+        "title":{"t":"MetaBlocks","c":[
+            {"t":"Para","c":[{"t":"Str","c":"One"}]},
+            {"t":"Para","c":[{"t":"Str","c":"Two"}]}]},
+        "subtitle":{"t":"MetaString","c":"A  subtitle"},
+        "date":{"t":"MetaInlines","c":[{"t":"Str","c":"May"},{"t":"Space"},{"t":"Str","c":"1,"},{"t":"Space"},{"t":"Str","c":"2023"}]},
+        "author":{"t":"MetaList","c":[
+            {"t":"MetaString","c":"Ann"},
+            {"t":"MetaInlines","c":[{"t":"Str","c":"Bob"}]},
+            {"t":"MetaMap","c":{
+                "name":{"t":"MetaInlines","c":[{"t":"Str","c":"Cy"}]},
+                "affiliation":{"t":"MetaString","c":"ACME"}}},
+            {"t":"MetaMap","c":{
+                "family":{"t":"MetaString","c":"Doe"},
+                "given":{"t":"MetaString","c":"Jane"}}},
+            {"t":"MetaMap","c":{"literal":{"t":"MetaString","c":"The Committee"}}}]},
+        "keywords":{"t":"MetaString","c":"a, b,"}
+    }}"#;
+    let doc: Pandoc<NullScheme> = ::serde_json::from_str(input).unwrap();
+    let text = |i: &Option<Inlines<NullScheme>>| i.as_ref().map(|i| i.stringify());
+
+    let mut info = doc.document_info();
+    ::std::assert_eq!(info.title, None);
+    ::std::assert_eq!(text(&info.subtitle).as_deref(), Some("A subtitle"));
+    ::std::assert_eq!(info.iso_date().as_deref(), Some("2023-05-01"));
+    ::std::assert_eq!(
+        Vec::from_iter(info.authors.iter().map(|a| text(&a.name))),
+        [
+            Some("Ann".to_string()),
+            Some("Bob".to_string()),
+            Some("Cy".to_string()),
+            None,
+            None
+        ]
+    );
+    ::std::assert_eq!(info.authors[2].fields.len(), 1);
+    ::std::assert_eq!(info.authors[3].fields.len(), 2);
+    ::std::assert_eq!(Vec::from_iter(info.keywords.iter()), ["a", "b"]);
+
+    // Writing back keeps what couldn't be read and reads back the same.
+    let mut meta = doc.meta.clone();
+    info.write_to(&mut meta);
+    ::std::assert_eq!(meta.get(&"title".into()), doc.meta.get(&"title".into()));
+    ::std::assert_eq!(DocumentInfo::from_meta(&meta), info);
+    let Some(MetaValueContent::List(authors)) = meta.get(&"author".into()).map(|a| &a.content)
+    else {
+        panic!("expected a list of authors");
+    };
+    ::std::assert!(matches!(authors[0].content, MetaValueContent::Inlines(_)));
+    ::std::assert!(matches!(
+        &authors[3].content,
+        MetaValueContent::Map(m) if m.len() == 2 && !m.contains_key(&"name".into())
+    ));
+
+    info.subtitle = None;
+    info.title = Some(Inlines::from_text("Title"));
+    info.write_to(&mut meta);
+    ::std::assert!(!meta.contains_key(&"subtitle".into()));
+    ::std::assert_eq!(
+        meta.get(&"title".into()).and_then(meta_text).as_deref(),
+        Some("Title")
+    );
+}
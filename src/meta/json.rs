@@ -0,0 +1,269 @@
+use im_rope::Rope;
+use imbl::{HashMap, Vector};
+use serde_json::{Map, Value};
+
+use crate::ast::*;
+use crate::interned::InternedString;
+use crate::stringify::Stringify;
+
+/// Options for converting between metadata and plain JSON.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetaJsonOptions {
+    /// When exporting, emit `MetaInlines` and `MetaBlocks` as their Pandoc AST
+    /// JSON rather than as plain strings. When importing, parse arrays which
+    /// are Pandoc AST JSON back into `MetaInlines` or `MetaBlocks`; arrays
+    /// which fail to parse as such are imported as `MetaList`s.
+    ///
+    /// Empty `MetaInlines` and `MetaBlocks` export as `[]`, like an empty
+    /// `MetaList`, so they import as an empty `MetaList`: an empty array
+    /// carries no AST to tell them apart.
+    pub ast: bool,
+}
+
+/// Convert a metadata value into plain JSON.
+///
+/// Maps become objects, lists become arrays, booleans become booleans, and
+/// everything else becomes a string unless `options.ast` is set.
+pub fn meta_value_to_json<A>(value: &MetaValue<A>, options: &MetaJsonOptions) -> Value
+where
+    A: DecorationScheme,
+{
+    match &value.content {
+        MetaValueContent::Map(m) => Value::Object(meta_to_json_map(m, options)),
+        MetaValueContent::List(l) => Value::Array(Vec::from_iter(
+            l.iter().map(|v| meta_value_to_json(v, options)),
+        )),
+        MetaValueContent::Bool(b) => Value::Bool(*b),
+        MetaValueContent::String(s) => Value::String(s.chars().collect()),
+        MetaValueContent::Inlines(i) if options.ast => {
+            serde_json::to_value(i).expect("inlines always serialize to JSON")
+        }
+        MetaValueContent::Blocks(b) if options.ast => {
+            serde_json::to_value(b).expect("blocks always serialize to JSON")
+        }
+        MetaValueContent::Inlines(i) => Value::String(i.stringify()),
+        MetaValueContent::Blocks(b) => Value::String(b.stringify()),
+    }
+}
+
+/// Convert plain JSON into a metadata value.
+///
+/// Objects become `MetaMap`s, arrays become `MetaList`s, booleans become
+/// `MetaBool`s, and strings and numbers become `MetaString`s. Object entries
+/// whose value is `null` are dropped; any other `null` becomes an empty
+/// `MetaString`.
+pub fn meta_value_from_json<A>(value: &Value, options: &MetaJsonOptions) -> MetaValue<A>
+where
+    A: DecorationScheme,
+{
+    let content = match value {
+        Value::Null => MetaValueContent::String(Rope::new()),
+        Value::Bool(b) => MetaValueContent::Bool(*b),
+        Value::Number(n) => MetaValueContent::String(Rope::from(n.to_string())),
+        Value::String(s) => MetaValueContent::String(Rope::from(s.as_str())),
+        Value::Array(a) => match ast_from_json(value, a, options) {
+            Some(content) => content,
+            None => MetaValueContent::List(Vector::from_iter(
+                a.iter().map(|v| meta_value_from_json(v, options)),
+            )),
+        },
+        Value::Object(o) => MetaValueContent::Map(meta_from_json_map(o, options)),
+    };
+
+    content.into()
+}
+
+/// Convert a document's metadata into a plain JSON object.
+pub fn meta_to_json<A>(
+    meta: &HashMap<InternedString, MetaValue<A>>,
+    options: &MetaJsonOptions,
+) -> Value
+where
+    A: DecorationScheme,
+{
+    Value::Object(meta_to_json_map(meta, options))
+}
+
+/// Convert a plain JSON object into document metadata.
+///
+/// Values other than objects yield empty metadata.
+pub fn meta_from_json<A>(
+    value: &Value,
+    options: &MetaJsonOptions,
+) -> HashMap<InternedString, MetaValue<A>>
+where
+    A: DecorationScheme,
+{
+    match value {
+        Value::Object(o) => meta_from_json_map(o, options),
+        _ => HashMap::new(),
+    }
+}
+
+fn meta_to_json_map<A>(
+    meta: &HashMap<InternedString, MetaValue<A>>,
+    options: &MetaJsonOptions,
+) -> Map<String, Value>
+where
+    A: DecorationScheme,
+{
+    let mut entries = Vec::from_iter(meta.iter());
+    entries.sort_by_key(|(k, _)| *k);
+
+    Map::from_iter(
+        entries
+            .into_iter()
+            .map(|(k, v)| (String::from(k.clone()), meta_value_to_json(v, options))),
+    )
+}
+
+fn meta_from_json_map<A>(
+    object: &Map<String, Value>,
+    options: &MetaJsonOptions,
+) -> HashMap<InternedString, MetaValue<A>>
+where
+    A: DecorationScheme,
+{
+    let mut meta = HashMap::new();
+    for (k, v) in object {
+        if !v.is_null() {
+            meta.insert(
+                InternedString::from(k.as_str()),
+                meta_value_from_json(v, options),
+            );
+        }
+    }
+    meta
+}
+
+/// Parse an array as `MetaBlocks` or `MetaInlines`, if `options.ast` is set
+/// and the array is Pandoc AST JSON.
+fn ast_from_json<A>(
+    value: &Value,
+    array: &[Value],
+    options: &MetaJsonOptions,
+) -> Option<MetaValueContent<A>>
+where
+    A: DecorationScheme,
+{
+    if !options.ast || !looks_like_ast(array) {
+        return None;
+    }
+
+    if array
+        .iter()
+        .any(|v| v["t"].as_str().is_some_and(is_block_tag))
+    {
+        serde_json::from_value(value.clone())
+            .ok()
+            .map(MetaValueContent::Blocks)
+    } else {
+        serde_json::from_value(value.clone())
+            .ok()
+            .map(MetaValueContent::Inlines)
+    }
+}
+
+fn looks_like_ast(array: &[Value]) -> bool {
+    !array.is_empty()
+        && array.iter().all(|v| {
+            v.as_object()
+                .is_some_and(|o| o.get("t").is_some_and(Value::is_string))
+        })
+}
+
+fn is_block_tag(tag: &str) -> bool {
+    matches!(
+        tag,
+        "Plain"
+            | "Para"
+            | "LineBlock"
+            | "CodeBlock"
+            | "RawBlock"
+            | "BlockQuote"
+            | "OrderedList"
+            | "BulletList"
+            | "DefinitionList"
+            | "Header"
+            | "HorizontalRule"
+            | "Table"
+            | "Figure"
+            | "Div"
+    )
+}
+
+impl<A> From<MetaValue<A>> for Value
+where
+    A: DecorationScheme,
+{
+    fn from(value: MetaValue<A>) -> Self {
+        meta_value_to_json(&value, &MetaJsonOptions::default())
+    }
+}
+
+impl<A> From<Value> for MetaValue<A>
+where
+    A: DecorationScheme,
+{
+    fn from(value: Value) -> Self {
+        meta_value_from_json(&value, &MetaJsonOptions::default())
+    }
+}
+
+// This module is only built with the `json` feature, so run this test with
+// `cargo test --features json`.
+#[cfg(feature = "json")]
+#[test]
+fn test_meta_json() {
+    let plain = ::serde_json::json!({
+        "title": "Report",
+        "draft": true,
+        "tags": ["a", "b"],
+        "build": {"format": "pdf", "flags": []},
+        "note": [{"t": "note", "v": "one"}]
+    });
+    let options = MetaJsonOptions::default();
+    let meta: HashMap<InternedString, MetaValue<NullScheme>> = meta_from_json(&plain, &options);
+    ::std::assert_eq!(meta_to_json(&meta, &options), plain);
+
+    // Numbers become strings and null entries are dropped.
+    let value: MetaValue<NullScheme> = ::serde_json::json!({"n": 3, "x": null}).into();
+    ::std::assert_eq!(Value::from(value), ::serde_json::json!({"n": "3"}));
+
+    let ast = ::serde_json::json!({
+        "title": [{"t": "Str", "c": "A"}, {"t": "Space"}, {"t": "Emph", "c": [{"t": "Str", "c": "b"}]}],
+        "abstract": [{"t": "Para", "c": [{"t": "Str", "c": "x"}]}],
+        "note": [{"t": "note", "v": 1}]
+    });
+    let options = MetaJsonOptions { ast: true };
+    let meta: HashMap<InternedString, MetaValue<NullScheme>> = meta_from_json(&ast, &options);
+    ::std::assert!(matches!(
+        meta[&InternedString::from("title")].content,
+        MetaValueContent::Inlines(_)
+    ));
+    ::std::assert!(matches!(
+        meta[&InternedString::from("abstract")].content,
+        MetaValueContent::Blocks(_)
+    ));
+    ::std::assert!(matches!(
+        meta[&InternedString::from("note")].content,
+        MetaValueContent::List(_)
+    ));
+
+    let mut expected = ast.clone();
+    expected["note"] = ::serde_json::json!([{"t": "note", "v": "1"}]);
+    ::std::assert_eq!(meta_to_json(&meta, &options), expected);
+
+    // Empty inlines come back as an empty list.
+    let empty: MetaValue<NullScheme> = MetaValueContent::Inlines(Inlines::default()).into();
+    let json = meta_value_to_json(&empty, &options);
+    ::std::assert_eq!(json, ::serde_json::json!([]));
+    ::std::assert!(matches!(
+        meta_value_from_json::<NullScheme>(&json, &options).content,
+        MetaValueContent::List(l) if l.is_empty()
+    ));
+
+    let text = meta_to_json(&meta, &MetaJsonOptions::default());
+    ::std::assert_eq!(text["title"], "A b");
+    ::std::assert_eq!(text["abstract"], "x");
+}
@@ -0,0 +1,46 @@
+//! Utilities for working with document metadata
+
+use crate::ast::*;
+use crate::stringify::Stringify;
+
+mod info;
+#[cfg(feature = "json")]
+mod json;
+mod path;
+mod ser;
+
+pub use info::*;
+#[cfg(feature = "json")]
+pub use json::*;
+pub use path::*;
+pub use ser::*;
+
+/// The plain text of a string-like metadata value.
+pub(crate) fn meta_text<A>(value: &MetaValue<A>) -> Option<String>
+where
+    A: DecorationScheme,
+{
+    match &value.content {
+        MetaValueContent::String(s) => Some(s.chars().collect()),
+        MetaValueContent::Inlines(i) => Some(i.stringify()),
+        _ => None,
+    }
+}
+
+/// The inlines of a string-like metadata value, or of a single paragraph.
+pub(crate) fn meta_inlines<A>(value: &MetaValue<A>) -> Option<Inlines<A>>
+where
+    A: DecorationScheme,
+{
+    match &value.content {
+        MetaValueContent::Inlines(i) => Some(i.clone()),
+        MetaValueContent::String(s) => Some(Inlines::from_text(&s.chars().collect::<String>())),
+        MetaValueContent::Blocks(b) => match b.content.front().map(|b| &b.content) {
+            Some(BlockContent::Plain(i)) | Some(BlockContent::Para(i)) if b.content.len() == 1 => {
+                Some(i.clone())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
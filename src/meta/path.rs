@@ -0,0 +1,563 @@
+use imbl::{HashMap, Vector};
+use std::error::Error;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::meta_text;
+use crate::ast::*;
+use crate::interned::InternedString;
+
+/// A step in a [`MetaPath`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MetaPathSegment {
+    /// A key within a `MetaMap` (or within the top-level metadata).
+    Key(InternedString),
+    /// An index within a `MetaList`.
+    Index(usize),
+}
+
+/// A dotted path into document metadata, such as `author[0].affiliation`.
+///
+/// Keys are separated by `.` and list indices are written in square brackets.
+/// Keys may contain any character other than `.`, `[` and `]`, so paths like
+/// `header-includes` need no quoting.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MetaPath {
+    segments: Vec<MetaPathSegment>,
+}
+
+/// Error returned when a [`MetaPath`] cannot be parsed or followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetaPathError {
+    /// The path is not syntactically valid.
+    Syntax(String),
+    /// The path is empty or does not begin with a key.
+    MissingKey,
+    /// The path steps into a value which is not a map or list.
+    NotAContainer(MetaPath),
+    /// A list index lies past the end of the list.
+    IndexOutOfBounds(MetaPath),
+}
+
+/// How [`merge_meta`] combines two lists found at the same place.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ListMergeStrategy {
+    /// Items from the overriding list are appended to the base list.
+    Append,
+    /// The overriding list replaces the base list.
+    #[default]
+    Replace,
+    /// Items are matched up by the plain-text value of the given map key.
+    /// Matching items are merged recursively; others are appended.
+    UnionBy(InternedString),
+}
+
+/// Options for [`merge_meta`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetaMergeOptions {
+    /// The strategy used for lists not listed in `paths`.
+    pub lists: ListMergeStrategy,
+    /// Per-path list strategies. Paths are matched against the keys leading to
+    /// a list, ignoring any list indices along the way.
+    pub paths: std::collections::HashMap<MetaPath, ListMergeStrategy>,
+}
+
+impl MetaPath {
+    /// Construct a path from its segments.
+    pub fn new<I>(segments: I) -> MetaPath
+    where
+        I: IntoIterator<Item = MetaPathSegment>,
+    {
+        MetaPath {
+            segments: Vec::from_iter(segments),
+        }
+    }
+
+    pub fn segments(&self) -> &[MetaPathSegment] {
+        &self.segments
+    }
+
+    /// Look up the value at this path.
+    pub fn lookup<'m, A>(
+        &self,
+        meta: &'m HashMap<InternedString, MetaValue<A>>,
+    ) -> Option<&'m MetaValue<A>>
+    where
+        A: DecorationScheme,
+    {
+        let (first, rest) = self.split_key().ok()?;
+        let mut value = meta.get(first)?;
+
+        for segment in rest {
+            value = match (segment, &value.content) {
+                (MetaPathSegment::Key(k), MetaValueContent::Map(m)) => m.get(k)?,
+                (MetaPathSegment::Index(i), MetaValueContent::List(l)) => l.get(*i)?,
+                _ => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    /// Set the value at this path, returning the value it replaced.
+    ///
+    /// Missing maps and lists along the path are created as needed. An index
+    /// equal to the length of a list appends to it.
+    pub fn set<A>(
+        &self,
+        meta: &mut HashMap<InternedString, MetaValue<A>>,
+        value: MetaValue<A>,
+    ) -> Result<Option<MetaValue<A>>, MetaPathError>
+    where
+        A: DecorationScheme,
+    {
+        let (first, rest) = self.split_key()?;
+
+        if rest.is_empty() {
+            return Ok(meta.insert(first.clone(), value));
+        }
+
+        let entry = meta
+            .entry(first.clone())
+            .or_insert_with(|| empty_container(&rest[0]));
+        self.set_in(entry, 1, value)
+    }
+
+    /// Remove the value at this path, returning it.
+    pub fn remove<A>(
+        &self,
+        meta: &mut HashMap<InternedString, MetaValue<A>>,
+    ) -> Option<MetaValue<A>>
+    where
+        A: DecorationScheme,
+    {
+        let (first, rest) = self.split_key().ok()?;
+
+        let (last, middle) = match rest.split_last() {
+            None => return meta.remove(first),
+            Some(x) => x,
+        };
+
+        let mut parent = meta.get_mut(first)?;
+        for segment in middle {
+            parent = match (segment, &mut parent.content) {
+                (MetaPathSegment::Key(k), MetaValueContent::Map(m)) => m.get_mut(k)?,
+                (MetaPathSegment::Index(i), MetaValueContent::List(l)) => l.get_mut(*i)?,
+                _ => return None,
+            };
+        }
+
+        match (last, &mut parent.content) {
+            (MetaPathSegment::Key(k), MetaValueContent::Map(m)) => m.remove(k),
+            (MetaPathSegment::Index(i), MetaValueContent::List(l)) if *i < l.len() => {
+                Some(l.remove(*i))
+            }
+            _ => None,
+        }
+    }
+
+    fn split_key(&self) -> Result<(&InternedString, &[MetaPathSegment]), MetaPathError> {
+        match self.segments.split_first() {
+            Some((MetaPathSegment::Key(k), rest)) => Ok((k, rest)),
+            _ => Err(MetaPathError::MissingKey),
+        }
+    }
+
+    fn prefix(&self, len: usize) -> MetaPath {
+        MetaPath::new(self.segments[..len].iter().cloned())
+    }
+
+    fn set_in<A>(
+        &self,
+        target: &mut MetaValue<A>,
+        depth: usize,
+        value: MetaValue<A>,
+    ) -> Result<Option<MetaValue<A>>, MetaPathError>
+    where
+        A: DecorationScheme,
+    {
+        let segment = &self.segments[depth];
+        let last = depth + 1 == self.segments.len();
+
+        let child = match (segment, &mut target.content) {
+            (MetaPathSegment::Key(k), MetaValueContent::Map(m)) => {
+                if last {
+                    return Ok(m.insert(k.clone(), value));
+                }
+                m.entry(k.clone())
+                    .or_insert_with(|| empty_container(&self.segments[depth + 1]))
+            }
+            (MetaPathSegment::Index(i), MetaValueContent::List(l)) => {
+                if *i > l.len() {
+                    return Err(MetaPathError::IndexOutOfBounds(self.prefix(depth + 1)));
+                }
+                if last && *i == l.len() {
+                    l.push_back(value);
+                    return Ok(None);
+                } else if last {
+                    return Ok(Some(l.set(*i, value)));
+                } else if *i == l.len() {
+                    l.push_back(empty_container(&self.segments[depth + 1]));
+                }
+                &mut l[*i]
+            }
+            _ => return Err(MetaPathError::NotAContainer(self.prefix(depth))),
+        };
+
+        self.set_in(child, depth + 1, value)
+    }
+}
+
+impl Display for MetaPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                MetaPathSegment::Key(k) if i == 0 => write!(f, "{}", k)?,
+                MetaPathSegment::Key(k) => write!(f, ".{}", k)?,
+                MetaPathSegment::Index(n) => write!(f, "[{}]", n)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MetaPath {
+    type Err = MetaPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+
+        loop {
+            let end = rest.find(['.', '[', ']']).unwrap_or(rest.len());
+            let (key, after) = rest.split_at(end);
+
+            if key.is_empty() {
+                return Err(MetaPathError::Syntax(format!("empty key in `{}`", s)));
+            }
+            segments.push(MetaPathSegment::Key(InternedString::from(key)));
+            rest = after;
+
+            while let Some(inner) = rest.strip_prefix('[') {
+                let close = inner
+                    .find(']')
+                    .ok_or_else(|| MetaPathError::Syntax(format!("unclosed `[` in `{}`", s)))?;
+                let index = inner[..close].parse().map_err(|_| {
+                    MetaPathError::Syntax(format!("invalid index `{}` in `{}`", &inner[..close], s))
+                })?;
+                segments.push(MetaPathSegment::Index(index));
+                rest = &inner[close + 1..];
+            }
+
+            if rest.is_empty() {
+                return Ok(MetaPath { segments });
+            } else if let Some(after) = rest.strip_prefix('.') {
+                rest = after;
+            } else {
+                return Err(MetaPathError::Syntax(format!(
+                    "unexpected `{}` in `{}`",
+                    rest, s
+                )));
+            }
+        }
+    }
+}
+
+impl Display for MetaPathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetaPathError::Syntax(msg) => write!(f, "invalid metadata path: {}", msg),
+            MetaPathError::MissingKey => write!(f, "metadata path must begin with a key"),
+            MetaPathError::NotAContainer(p) => write!(f, "`{}` is not a map or list", p),
+            MetaPathError::IndexOutOfBounds(p) => write!(f, "`{}` is out of bounds", p),
+        }
+    }
+}
+
+impl Error for MetaPathError {}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    /// Look up a metadata value by path, e.g. `author[0].affiliation`.
+    ///
+    /// Returns `None` if the path is invalid or leads nowhere.
+    pub fn meta_get(&self, path: &str) -> Option<&MetaValue<A>> {
+        path.parse::<MetaPath>().ok()?.lookup(&self.meta)
+    }
+
+    /// Set a metadata value by path, returning the value it replaced.
+    ///
+    /// See [`MetaPath::set`].
+    pub fn meta_set(
+        &mut self,
+        path: &str,
+        value: MetaValue<A>,
+    ) -> Result<Option<MetaValue<A>>, MetaPathError> {
+        path.parse::<MetaPath>()?.set(&mut self.meta, value)
+    }
+
+    /// Remove a metadata value by path, returning it.
+    pub fn meta_remove(&mut self, path: &str) -> Option<MetaValue<A>> {
+        path.parse::<MetaPath>().ok()?.remove(&mut self.meta)
+    }
+}
+
+/// Deep-merge two metadata trees.
+///
+/// Entries in `overrides` take precedence over those in `base`. Where both
+/// sides hold a map, the maps are merged recursively; where both hold a list,
+/// the lists are combined according to `options`. In every other case, the
+/// value from `overrides` wins.
+pub fn merge_meta<A>(
+    base: HashMap<InternedString, MetaValue<A>>,
+    overrides: HashMap<InternedString, MetaValue<A>>,
+    options: &MetaMergeOptions,
+) -> HashMap<InternedString, MetaValue<A>>
+where
+    A: DecorationScheme,
+{
+    merge_maps(base, overrides, options, &mut Vec::new())
+}
+
+fn merge_maps<A>(
+    mut base: HashMap<InternedString, MetaValue<A>>,
+    overrides: HashMap<InternedString, MetaValue<A>>,
+    options: &MetaMergeOptions,
+    path: &mut Vec<MetaPathSegment>,
+) -> HashMap<InternedString, MetaValue<A>>
+where
+    A: DecorationScheme,
+{
+    for (k, v) in overrides {
+        path.push(MetaPathSegment::Key(k.clone()));
+        let merged = match base.remove(&k) {
+            Some(b) => merge_values(b, v, options, path),
+            None => v,
+        };
+        path.pop();
+        base.insert(k, merged);
+    }
+    base
+}
+
+fn merge_values<A>(
+    base: MetaValue<A>,
+    over: MetaValue<A>,
+    options: &MetaMergeOptions,
+    path: &mut Vec<MetaPathSegment>,
+) -> MetaValue<A>
+where
+    A: DecorationScheme,
+{
+    match (base.content, over.content) {
+        (MetaValueContent::Map(b), MetaValueContent::Map(o)) => MetaValue {
+            decoration: over.decoration,
+            content: MetaValueContent::Map(merge_maps(b, o, options, path)),
+        },
+        (MetaValueContent::List(b), MetaValueContent::List(o)) => {
+            let strategy = options
+                .paths
+                .get(&MetaPath::new(path.iter().cloned()))
+                .unwrap_or(&options.lists);
+            MetaValue {
+                decoration: over.decoration,
+                content: MetaValueContent::List(merge_lists(b, o, strategy, options, path)),
+            }
+        }
+        (_, content) => MetaValue {
+            decoration: over.decoration,
+            content,
+        },
+    }
+}
+
+fn merge_lists<A>(
+    mut base: Vector<MetaValue<A>>,
+    overrides: Vector<MetaValue<A>>,
+    strategy: &ListMergeStrategy,
+    options: &MetaMergeOptions,
+    path: &mut Vec<MetaPathSegment>,
+) -> Vector<MetaValue<A>>
+where
+    A: DecorationScheme,
+{
+    match strategy {
+        ListMergeStrategy::Append => {
+            base.append(overrides);
+            base
+        }
+        ListMergeStrategy::Replace => overrides,
+        ListMergeStrategy::UnionBy(key) => {
+            for item in overrides {
+                let found = union_key(&item, key).and_then(|k| {
+                    base.iter()
+                        .position(|b| union_key(b, key).as_deref() == Some(k.as_str()))
+                });
+                match found {
+                    Some(i) => {
+                        let merged = merge_values(base[i].clone(), item, options, path);
+                        base.set(i, merged);
+                    }
+                    None => base.push_back(item),
+                }
+            }
+            base
+        }
+    }
+}
+
+fn union_key<A>(item: &MetaValue<A>, key: &InternedString) -> Option<String>
+where
+    A: DecorationScheme,
+{
+    match &item.content {
+        MetaValueContent::Map(m) => m.get(key).and_then(meta_text),
+        _ => None,
+    }
+}
+
+fn empty_container<A>(next: &MetaPathSegment) -> MetaValue<A>
+where
+    A: DecorationScheme,
+{
+    match next {
+        MetaPathSegment::Key(_) => MetaValueContent::Map(HashMap::new()).into(),
+        MetaPathSegment::Index(_) => MetaValueContent::List(Vector::new()).into(),
+    }
+}
+
+#[test]
+fn test_meta_path() {
+    let mut doc: Pandoc<NullScheme> =
+        ::serde_json::from_str(r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[]}"#).unwrap();
+
+    let name: MetaValue<NullScheme> = MetaValueContent::String("Jane Doe".into()).into();
+    let affiliation: MetaValue<NullScheme> = MetaValueContent::String("ACME".into()).into();
+
+    doc.meta_set("author[0].name", name).unwrap();
+    doc.meta_set("author[0].affiliation", affiliation).unwrap();
+    ::std::assert!(doc.meta_get("author[0].name").is_some());
+    ::std::assert!(doc.meta_get("author[1]").is_none());
+    ::std::assert_eq!(
+        doc.meta_set("author[2]", MetaValueContent::Bool(true).into()),
+        Err(MetaPathError::IndexOutOfBounds(
+            "author[2]".parse().unwrap()
+        ))
+    );
+
+    ::std::assert!(doc.meta_remove("author[0].affiliation").is_some());
+    ::std::assert!(doc.meta_get("author[0].affiliation").is_none());
+    ::std::assert_eq!(
+        "header-includes".parse::<MetaPath>().unwrap().to_string(),
+        "header-includes"
+    );
+    ::std::assert!("a..b".parse::<MetaPath>().is_err());
+}
+
+#[test]
+fn test_merge_meta() {
+    type Meta = HashMap<InternedString, MetaValue<NullScheme>>;
+
+    fn s(s: &str) -> MetaValue<NullScheme> {
+        MetaValueContent::String(s.into()).into()
+    }
+    fn list<const N: usize>(items: [MetaValue<NullScheme>; N]) -> MetaValue<NullScheme> {
+        MetaValueContent::List(Vector::from_iter(items)).into()
+    }
+    fn map<const N: usize>(entries: [(&str, MetaValue<NullScheme>); N]) -> MetaValue<NullScheme> {
+        MetaValueContent::Map(HashMap::from_iter(
+            entries.map(|(k, v)| (InternedString::from(k), v)),
+        ))
+        .into()
+    }
+    fn at<'m>(meta: &'m Meta, path: &str) -> Option<&'m MetaValue<NullScheme>> {
+        path.parse::<MetaPath>().unwrap().lookup(meta)
+    }
+    fn text(meta: &Meta, path: &str) -> Option<String> {
+        at(meta, path).and_then(meta_text)
+    }
+    fn texts(meta: &Meta, path: &str) -> Vec<String> {
+        match at(meta, path).map(|v| &v.content) {
+            Some(MetaValueContent::List(l)) => Vec::from_iter(l.iter().filter_map(meta_text)),
+            _ => Vec::new(),
+        }
+    }
+
+    let MetaValueContent::Map(base) = map([
+        ("title", s("Base")),
+        ("tags", list([s("a"), s("b")])),
+        (
+            "author",
+            list([
+                map([("name", s("Ann")), ("affiliation", s("X"))]),
+                map([("name", s("Bob"))]),
+            ]),
+        ),
+        (
+            "build",
+            map([("format", s("pdf")), ("flags", list([s("x")]))]),
+        ),
+    ])
+    .content
+    else {
+        unreachable!()
+    };
+    let MetaValueContent::Map(overrides) = map([
+        ("tags", list([s("c")])),
+        (
+            "author",
+            list([
+                map([("name", s("Ann")), ("email", s("e"))]),
+                map([("name", s("Cy"))]),
+            ]),
+        ),
+        ("build", map([("flags", list([s("y")]))])),
+    ])
+    .content
+    else {
+        unreachable!()
+    };
+
+    // Maps merge recursively at any depth; lists are replaced by default.
+    let merged = merge_meta(base.clone(), overrides.clone(), &Default::default());
+    ::std::assert_eq!(text(&merged, "title").as_deref(), Some("Base"));
+    ::std::assert_eq!(texts(&merged, "tags"), ["c"]);
+    ::std::assert_eq!(text(&merged, "build.format").as_deref(), Some("pdf"));
+    ::std::assert_eq!(texts(&merged, "build.flags"), ["y"]);
+    ::std::assert_eq!(text(&merged, "author[0].email").as_deref(), Some("e"));
+    ::std::assert!(at(&merged, "author[0].affiliation").is_none());
+    ::std::assert!(at(&merged, "author[2]").is_none());
+
+    let options = MetaMergeOptions {
+        lists: ListMergeStrategy::Append,
+        paths: Default::default(),
+    };
+    let merged = merge_meta(base.clone(), overrides.clone(), &options);
+    ::std::assert_eq!(texts(&merged, "tags"), ["a", "b", "c"]);
+    ::std::assert_eq!(texts(&merged, "build.flags"), ["x", "y"]);
+    ::std::assert_eq!(text(&merged, "author[2].name").as_deref(), Some("Ann"));
+    ::std::assert_eq!(text(&merged, "author[3].name").as_deref(), Some("Cy"));
+
+    // Per-path strategies apply to nested lists and override the default.
+    let options = MetaMergeOptions {
+        lists: ListMergeStrategy::Append,
+        paths: std::collections::HashMap::from_iter([
+            (
+                "author".parse().unwrap(),
+                ListMergeStrategy::UnionBy("name".into()),
+            ),
+            ("build.flags".parse().unwrap(), ListMergeStrategy::Replace),
+        ]),
+    };
+    let merged = merge_meta(base, overrides, &options);
+    ::std::assert_eq!(texts(&merged, "tags"), ["a", "b", "c"]);
+    ::std::assert_eq!(texts(&merged, "build.flags"), ["y"]);
+    ::std::assert_eq!(text(&merged, "build.format").as_deref(), Some("pdf"));
+    ::std::assert_eq!(text(&merged, "author[0].affiliation").as_deref(), Some("X"));
+    ::std::assert_eq!(text(&merged, "author[0].email").as_deref(), Some("e"));
+    ::std::assert_eq!(text(&merged, "author[1].name").as_deref(), Some("Bob"));
+    ::std::assert_eq!(text(&merged, "author[2].name").as_deref(), Some("Cy"));
+    ::std::assert!(at(&merged, "author[3]").is_none());
+}
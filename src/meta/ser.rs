@@ -0,0 +1,763 @@
+use ::serde::ser::{self, Serialize};
+use im_rope::Rope;
+use imbl::{HashMap, Vector};
+use std::error::Error;
+use std::fmt::Display;
+use std::marker::PhantomData;
+
+use crate::ast::*;
+use crate::interned::InternedString;
+
+/// Options controlling how [`to_meta_value_with`] builds metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetaSerializerOptions {
+    /// Emit strings (and numbers) as `MetaInlines` rather than `MetaString`.
+    pub strings_as_inlines: bool,
+}
+
+/// Error returned when a value cannot be serialized into a [`MetaValue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaSerializeError(String);
+
+impl Display for MetaSerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for MetaSerializeError {}
+
+impl ser::Error for MetaSerializeError {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: Display,
+    {
+        MetaSerializeError(msg.to_string())
+    }
+}
+
+/// Serialize a value into a [`MetaValue`].
+///
+/// Structs and maps become `MetaMap`s, sequences and tuples become `MetaList`s,
+/// booleans become `MetaBool`s, and strings, characters and numbers become
+/// `MetaString`s. Enum variants are externally tagged, as in `serde_json`.
+/// Struct fields and map entries whose value is `None` or `()` are omitted;
+/// anywhere else, such values become an empty `MetaString`.
+pub fn to_meta_value<A, T>(value: &T) -> Result<MetaValue<A>, MetaSerializeError>
+where
+    A: DecorationScheme,
+    T: Serialize + ?Sized,
+{
+    to_meta_value_with(value, &MetaSerializerOptions::default())
+}
+
+/// Serialize a value into a [`MetaValue`] using the given options.
+pub fn to_meta_value_with<A, T>(
+    value: &T,
+    options: &MetaSerializerOptions,
+) -> Result<MetaValue<A>, MetaSerializeError>
+where
+    A: DecorationScheme,
+    T: Serialize + ?Sized,
+{
+    let serializer = MetaValueSerializer {
+        options,
+        phantom: PhantomData,
+    };
+    Ok(value
+        .serialize(serializer)?
+        .unwrap_or_else(|| serializer.string("")))
+}
+
+struct MetaValueSerializer<'o, A> {
+    options: &'o MetaSerializerOptions,
+    phantom: PhantomData<A>,
+}
+
+impl<'o, A> Clone for MetaValueSerializer<'o, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'o, A> Copy for MetaValueSerializer<'o, A> {}
+
+impl<'o, A> MetaValueSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    fn string(self, s: &str) -> MetaValue<A> {
+        if self.options.strings_as_inlines {
+            MetaValueContent::Inlines(Inlines::from_text(s)).into()
+        } else {
+            MetaValueContent::String(Rope::from(s)).into()
+        }
+    }
+
+    fn element<T>(self, value: &T) -> Result<MetaValue<A>, MetaSerializeError>
+    where
+        T: Serialize + ?Sized,
+    {
+        Ok(value.serialize(self)?.unwrap_or_else(|| self.string("")))
+    }
+
+    fn tagged(self, variant: &'static str, value: MetaValue<A>) -> Option<MetaValue<A>> {
+        Some(MetaValueContent::Map(HashMap::unit(InternedString::from(variant), value)).into())
+    }
+}
+
+struct SeqSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    serializer: MetaValueSerializer<'o, A>,
+    variant: Option<&'static str>,
+    items: Vector<MetaValue<A>>,
+}
+
+struct MapSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    serializer: MetaValueSerializer<'o, A>,
+    variant: Option<&'static str>,
+    entries: HashMap<InternedString, MetaValue<A>>,
+    key: Option<InternedString>,
+}
+
+impl<'o, A> SeqSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    fn push<T>(&mut self, value: &T) -> Result<(), MetaSerializeError>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.items.push_back(self.serializer.element(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Option<MetaValue<A>> {
+        let list = MetaValueContent::List(self.items).into();
+        match self.variant {
+            Some(variant) => self.serializer.tagged(variant, list),
+            None => Some(list),
+        }
+    }
+}
+
+impl<'o, A> MapSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    fn insert<T>(&mut self, key: InternedString, value: &T) -> Result<(), MetaSerializeError>
+    where
+        T: Serialize + ?Sized,
+    {
+        if let Some(value) = value.serialize(self.serializer)? {
+            self.entries.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Option<MetaValue<A>> {
+        let map = MetaValueContent::Map(self.entries).into();
+        match self.variant {
+            Some(variant) => self.serializer.tagged(variant, map),
+            None => Some(map),
+        }
+    }
+}
+
+impl<'o, A> ser::Serializer for MetaValueSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    type Ok = Option<MetaValue<A>>;
+    type Error = MetaSerializeError;
+    type SerializeSeq = SeqSerializer<'o, A>;
+    type SerializeTuple = SeqSerializer<'o, A>;
+    type SerializeTupleStruct = SeqSerializer<'o, A>;
+    type SerializeTupleVariant = SeqSerializer<'o, A>;
+    type SerializeMap = MapSerializer<'o, A>;
+    type SerializeStruct = MapSerializer<'o, A>;
+    type SerializeStructVariant = MapSerializer<'o, A>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(MetaValueContent::Bool(v).into()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(&v.to_string())))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(v.encode_utf8(&mut [0; 4]))))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(v)))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(
+            MetaValueContent::List(Vector::from_iter(
+                v.iter().map(|b| self.string(&b.to_string())),
+            ))
+            .into(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.string(variant)))
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        Ok(self.tagged(variant, self.element(value)?))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SeqSerializer {
+            serializer: self,
+            variant: None,
+            items: Vector::new(),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(SeqSerializer {
+            serializer: self,
+            variant: Some(variant),
+            items: Vector::new(),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(MapSerializer {
+            serializer: self,
+            variant: None,
+            entries: HashMap::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(MapSerializer {
+            serializer: self,
+            variant: Some(variant),
+            entries: HashMap::new(),
+            key: None,
+        })
+    }
+}
+
+impl<'o, A> ser::SerializeSeq for SeqSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    type Ok = Option<MetaValue<A>>;
+    type Error = MetaSerializeError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl<'o, A> ser::SerializeTuple for SeqSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    type Ok = Option<MetaValue<A>>;
+    type Error = MetaSerializeError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl<'o, A> ser::SerializeTupleStruct for SeqSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    type Ok = Option<MetaValue<A>>;
+    type Error = MetaSerializeError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl<'o, A> ser::SerializeTupleVariant for SeqSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    type Ok = Option<MetaValue<A>>;
+    type Error = MetaSerializeError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl<'o, A> ser::SerializeMap for MapSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    type Ok = Option<MetaValue<A>>;
+    type Error = MetaSerializeError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.key = Some(key.serialize(MapKeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| MetaSerializeError("map value serialized before its key".into()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl<'o, A> ser::SerializeStruct for MapSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    type Ok = Option<MetaValue<A>>;
+    type Error = MetaSerializeError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.insert(InternedString::from(key), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl<'o, A> ser::SerializeStructVariant for MapSerializer<'o, A>
+where
+    A: DecorationScheme,
+{
+    type Ok = Option<MetaValue<A>>;
+    type Error = MetaSerializeError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.insert(InternedString::from(key), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+/// Serializer for map keys, which must be strings or scalars.
+struct MapKeySerializer;
+
+impl MapKeySerializer {
+    fn key<T>(v: T) -> Result<InternedString, MetaSerializeError>
+    where
+        T: Display,
+    {
+        Ok(InternedString::from(v.to_string()))
+    }
+
+    fn invalid<T>() -> Result<T, MetaSerializeError> {
+        Err(MetaSerializeError(
+            "metadata map keys must be strings or scalars".into(),
+        ))
+    }
+}
+
+impl ser::Serializer for MapKeySerializer {
+    type Ok = InternedString;
+    type Error = MetaSerializeError;
+    type SerializeSeq = ser::Impossible<InternedString, MetaSerializeError>;
+    type SerializeTuple = ser::Impossible<InternedString, MetaSerializeError>;
+    type SerializeTupleStruct = ser::Impossible<InternedString, MetaSerializeError>;
+    type SerializeTupleVariant = ser::Impossible<InternedString, MetaSerializeError>;
+    type SerializeMap = ser::Impossible<InternedString, MetaSerializeError>;
+    type SerializeStruct = ser::Impossible<InternedString, MetaSerializeError>;
+    type SerializeStructVariant = ser::Impossible<InternedString, MetaSerializeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Self::key(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(InternedString::from(v))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(InternedString::from(variant))
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        Self::invalid()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Self::invalid()
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Self::invalid()
+    }
+}
+
+#[test]
+fn test_to_meta_value() {
+    #[derive(::serde::Serialize)]
+    struct Build {
+        timestamp: &'static str,
+        dirty: bool,
+        tags: Vec<&'static str>,
+        branch: Option<&'static str>,
+    }
+
+    let build = Build {
+        timestamp: "2023-05-01T12:00:00Z",
+        dirty: false,
+        tags: vec!["v1", "v2"],
+        branch: None,
+    };
+
+    let meta: MetaValue<NullScheme> = to_meta_value(&build).unwrap();
+    let json = ::serde_json::to_value(&meta).unwrap();
+    ::std::assert_eq!(
+        json,
+        ::serde_json::json!({
+            "t": "MetaMap",
+            "c": {
+                "timestamp": {"t": "MetaString", "c": "2023-05-01T12:00:00Z"},
+                "dirty": {"t": "MetaBool", "c": false},
+                "tags": {"t": "MetaList", "c": [
+                    {"t": "MetaString", "c": "v1"},
+                    {"t": "MetaString", "c": "v2"},
+                ]},
+            }
+        })
+    );
+
+    let options = MetaSerializerOptions {
+        strings_as_inlines: true,
+    };
+    let meta: MetaValue<NullScheme> = to_meta_value_with("Hello  world", &options).unwrap();
+    let json = ::serde_json::to_value(&meta).unwrap();
+    ::std::assert_eq!(
+        json,
+        ::serde_json::json!({
+            "t": "MetaInlines",
+            "c": [
+                {"t": "Str", "c": "Hello"},
+                {"t": "Space"},
+                {"t": "Str", "c": "world"},
+            ]
+        })
+    );
+}
@@ -0,0 +1,531 @@
+//! Footnotes, endnotes and sidenotes
+
+use imbl::Vector;
+use std::fmt::Debug;
+
+use crate::ast::*;
+use crate::stringify::Stringify;
+use crate::visit::{Map, Query};
+
+/// A note found by [`Pandoc::notes`].
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone
+)]
+pub struct NoteRef<'a, A>
+where
+    A: DecorationScheme,
+{
+    /// The note's number, counting from 1 in document order.
+    pub number: usize,
+    /// The number of the first note with the same content as this one, which
+    /// is `number` itself unless this note is a duplicate.
+    pub first: usize,
+    pub blocks: &'a Blocks<A>,
+    /// The blocks enclosing the note, outermost first. For a note within
+    /// another note, these include the blocks of the outer note.
+    pub ancestors: Vec<&'a Block<A>>,
+}
+
+/// Where [`Pandoc::notes_to_endnotes`] places the notes.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum NoteLocation {
+    /// In a single block at the end of the document.
+    #[default]
+    Document,
+    /// At the end of each section of the given level or less. Only headers at
+    /// the top level of the document are considered, as in
+    /// [`SectionTree`](crate::SectionTree).
+    Section(i32),
+}
+
+/// Options for [`Pandoc::notes_to_endnotes`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EndnoteOptions {
+    pub location: NoteLocation,
+    /// Refer to the first of several notes with the same content, rather
+    /// than repeating it.
+    pub dedupe: bool,
+    /// Prepended to the identifiers of notes and references, like Pandoc's
+    /// `--id-prefix`.
+    pub id_prefix: String,
+}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+    Blocks<A>: PartialEq,
+{
+    /// The document's notes, in document order.
+    pub fn notes(&self) -> Vec<NoteRef<'_, A>> {
+        let mut collector = NoteCollector {
+            notes: Vec::new(),
+            ancestors: Vec::new(),
+        };
+        self.blocks.query(&mut collector);
+        let mut notes = collector.notes;
+
+        for i in 0..notes.len() {
+            let first = notes[..i].iter().find(|n| n.blocks == notes[i].blocks);
+            notes[i].first = first.map_or(i + 1, |n| n.number);
+        }
+        notes
+    }
+
+    /// Move the document's notes into endnotes, in the manner of Pandoc's
+    /// HTML writer.
+    ///
+    /// Each note is replaced by a `Link` with class `footnote-ref` to its
+    /// content, whose text is the note's number in a `Superscript`. The notes
+    /// are collected in a `Div` with class `footnotes`, which holds a
+    /// `HorizontalRule` and an `OrderedList`. Each item of the list is a `Div`
+    /// with the note's identifier, and ends with a `Link` with class
+    /// `footnote-back` to the first reference to the note.
+    ///
+    /// Returns the number of endnotes.
+    pub fn notes_to_endnotes(&mut self, options: &EndnoteOptions) -> usize {
+        let mut endnotes = Endnotes {
+            options,
+            seen: Vec::new(),
+            pending: Vec::new(),
+            divs: 0,
+            open: Vec::new(),
+        };
+
+        let mut content = Vector::new();
+        for block in std::mem::take(&mut self.blocks.content) {
+            if let (NoteLocation::Section(level), BlockContent::Header(l, _, _)) =
+                (&options.location, &block.content)
+            {
+                if l <= level {
+                    content.extend(endnotes.flush());
+                }
+            }
+            content.push_back(block.walk(&mut endnotes));
+        }
+        content.extend(endnotes.flush());
+        self.blocks.content = content;
+
+        endnotes.seen.len()
+    }
+
+    /// Replace the document's notes with sidenotes.
+    ///
+    /// Each note is replaced by a `Span` with class `sidenote-ref` holding
+    /// the note's number in a `Superscript`, followed by a `Span` with class
+    /// `sidenote` and identifier `sn-n` holding the number and the note's
+    /// content. Paragraphs of the note are separated by line breaks, and
+    /// blocks other than paragraphs are reduced to their text.
+    ///
+    /// Returns the number of sidenotes.
+    pub fn notes_to_sidenotes(&mut self) -> usize {
+        let mut sidenotes = Sidenotes {
+            count: 0,
+            numbers: Vec::new(),
+        };
+        let blocks = std::mem::take(&mut self.blocks);
+        self.blocks = blocks.walk(&mut sidenotes);
+        sidenotes.count
+    }
+}
+
+/// Collects the notes of a document.
+struct NoteCollector<'a, A>
+where
+    A: DecorationScheme,
+{
+    notes: Vec<NoteRef<'a, A>>,
+    ancestors: Vec<&'a Block<A>>,
+}
+
+impl<'a, A> NoteCollector<'a, A>
+where
+    A: DecorationScheme,
+{
+    fn add<L>(&mut self, inline: &'a InlineContent<A, L>) {
+        if let InlineContent::Note(blocks) = inline {
+            let number = self.notes.len() + 1;
+            self.notes.push(NoteRef {
+                number,
+                first: number,
+                blocks,
+                ancestors: self.ancestors.clone(),
+            });
+        }
+    }
+}
+
+impl<'a, A> Query<'a, A> for NoteCollector<'a, A>
+where
+    A: DecorationScheme,
+{
+    fn enter_block(&mut self, block: &'a Block<A>) {
+        self.ancestors.push(block);
+    }
+
+    fn query_block(&mut self, _block: &'a Block<A>) {
+        self.ancestors.pop();
+    }
+
+    fn enter_inline_condensed(&mut self, inline: &'a Inline<A, CondensedLeaf>) {
+        self.add(&inline.content);
+    }
+
+    fn enter_inline_expanded(&mut self, inline: &'a Inline<A, ExpandedLeaf>) {
+        self.add(&inline.content);
+    }
+}
+
+struct Endnotes<'a, A>
+where
+    A: DecorationScheme,
+{
+    options: &'a EndnoteOptions,
+    /// The original content of each endnote, and the number of references to
+    /// it so far.
+    seen: Vec<(Blocks<A>, usize)>,
+    /// The converted content and number of each endnote not yet placed, in
+    /// order of number. A note's slot is taken when it is numbered and filled
+    /// once its content has been walked.
+    pending: Vec<(Blocks<A>, usize)>,
+    divs: usize,
+    /// The notes entered but not yet mapped, innermost last. `None` for the
+    /// notes within a duplicate, which are dropped with it.
+    open: Vec<Option<OpenNote>>,
+}
+
+/// A note which [`Endnotes`] has numbered but not yet replaced.
+struct OpenNote {
+    number: usize,
+    /// The identifier of the reference to the note.
+    id: String,
+    /// The note's slot in `pending`, if it is the first with its content.
+    slot: Option<usize>,
+}
+
+impl<A> Endnotes<'_, A>
+where
+    A: DecorationScheme,
+    Blocks<A>: PartialEq,
+{
+    /// Number a note as it is entered, so that a note is numbered before any
+    /// notes within it.
+    fn enter<L>(&mut self, inline: &InlineContent<A, L>) {
+        let InlineContent::Note(blocks) = inline else {
+            return;
+        };
+        if matches!(
+            self.open.last(),
+            Some(None | Some(OpenNote { slot: None, .. }))
+        ) {
+            self.open.push(None);
+            return;
+        }
+
+        let prefix = &self.options.id_prefix;
+        let duplicate = match self.options.dedupe {
+            true => self.seen.iter().position(|(b, _)| b == blocks),
+            false => None,
+        };
+        let note = match duplicate {
+            Some(i) => {
+                self.seen[i].1 += 1;
+                OpenNote {
+                    number: i + 1,
+                    id: format!("{}fnref{}-{}", prefix, i + 1, self.seen[i].1),
+                    slot: None,
+                }
+            }
+            None => {
+                self.seen.push((blocks.clone(), 1));
+                let number = self.seen.len();
+                self.pending.push((Blocks::default(), number));
+                OpenNote {
+                    number,
+                    id: format!("{}fnref{}", prefix, number),
+                    slot: Some(self.pending.len() - 1),
+                }
+            }
+        };
+        self.open.push(Some(note));
+    }
+
+    /// Replace a note, whose content has been walked, with a reference to
+    /// it.
+    fn map<L>(&mut self, inline: &mut InlineContent<A, L>) {
+        let InlineContent::Note(blocks) = inline else {
+            return;
+        };
+        let Some(note) = self.open.pop().expect("entered notes are mapped") else {
+            return;
+        };
+
+        if let Some(slot) = note.slot {
+            let mut content = std::mem::take(blocks);
+            let back = Inline::link(
+                Attr::builder()
+                    .class("footnote-back")
+                    .attr("role", "doc-backlink")
+                    .build(),
+                "\u{21a9}\u{fe0e}",
+                &format!("#{}fnref{}", self.options.id_prefix, note.number),
+                "",
+            );
+            match content.content.back_mut().map(|b| &mut b.content) {
+                Some(BlockContent::Para(i) | BlockContent::Plain(i)) => {
+                    i.expand().push_back(back);
+                }
+                _ => content.content.push_back(Block::plain(back)),
+            }
+            self.pending[slot].0 = content;
+        }
+
+        *inline = InlineContent::Link(
+            Attr::builder()
+                .id(&note.id)
+                .class("footnote-ref")
+                .attr("role", "doc-noteref")
+                .build(),
+            Inline::superscript(Inline::str(&note.number.to_string())).into(),
+            Target::new(&format!("#{}fn{}", self.options.id_prefix, note.number), ""),
+        );
+    }
+
+    /// The block holding the pending endnotes, if there are any.
+    fn flush(&mut self) -> Option<Block<A>> {
+        let first = self.pending.first()?.1;
+        let prefix = &self.options.id_prefix;
+
+        self.divs += 1;
+        let id = match self.options.location {
+            NoteLocation::Document => format!("{}footnotes", prefix),
+            NoteLocation::Section(_) => format!("{}footnotes-{}", prefix, self.divs),
+        };
+        let items = self.pending.drain(..).map(|(blocks, number)| {
+            let attr = Attr::builder()
+                .id(&format!("{}fn{}", prefix, number))
+                .build();
+            Blocks::from(Block::div(attr, blocks))
+        });
+        let list_attrs = ListAttributes {
+            start_number: first as i32,
+            number_style: ListNumberStyle::Decimal,
+            number_delim: ListNumberDelim::Period,
+        };
+
+        Some(Block::div(
+            Attr::builder()
+                .id(&id)
+                .class("footnotes")
+                .attr("role", "doc-endnotes")
+                .build(),
+            Blocks::from_iter([
+                Block::horizontal_rule(),
+                Block::ordered_list(list_attrs, Vec::from_iter(items)),
+            ]),
+        ))
+    }
+}
+
+impl<A> Map<A> for Endnotes<'_, A>
+where
+    A: DecorationScheme,
+    Blocks<A>: PartialEq,
+{
+    fn enter_inline_condensed(&mut self, inline: &Inline<A, CondensedLeaf>) {
+        self.enter(&inline.content);
+    }
+
+    fn enter_inline_expanded(&mut self, inline: &Inline<A, ExpandedLeaf>) {
+        self.enter(&inline.content);
+    }
+
+    fn map_inline_condensed(
+        &mut self,
+        mut inline: Inline<A, CondensedLeaf>,
+    ) -> Inline<A, CondensedLeaf> {
+        self.map(&mut inline.content);
+        inline
+    }
+
+    fn map_inline_expanded(
+        &mut self,
+        mut inline: Inline<A, ExpandedLeaf>,
+    ) -> Inline<A, ExpandedLeaf> {
+        self.map(&mut inline.content);
+        inline
+    }
+}
+
+struct Sidenotes {
+    count: usize,
+    /// For each inline sequence being walked, the numbers of its notes so
+    /// far. Notes are numbered as they are entered, so that a note is
+    /// numbered before any notes within it.
+    numbers: Vec<Vec<usize>>,
+}
+
+impl Sidenotes {
+    fn sidenote<A>(number: usize, blocks: Blocks<A>) -> [Inline<A, ExpandedLeaf>; 2]
+    where
+        A: DecorationScheme,
+    {
+        let number = number.to_string();
+
+        let mut text = Vec::from_iter([
+            Inline::superscript(Inline::str(&number)).into(),
+            Inline::space().into(),
+        ]);
+        for (i, block) in blocks.content.into_iter().enumerate() {
+            if i > 0 {
+                text.push(Inline::line_break().into());
+            }
+            text.push(match block.content {
+                BlockContent::Plain(i) | BlockContent::Para(i) => i,
+                other => Inlines::from_text(&Block::from(other).stringify()),
+            });
+        }
+
+        [
+            Inline::span(
+                Attr::builder().class("sidenote-ref").build(),
+                Inline::superscript(Inline::str(&number)),
+            ),
+            Inline::span(
+                Attr::builder()
+                    .id(&format!("sn-{}", number))
+                    .class("sidenote")
+                    .build(),
+                Inlines::from_iter(text),
+            ),
+        ]
+    }
+}
+
+impl Sidenotes {
+    fn enter<A, L>(&mut self, inline: &InlineContent<A, L>)
+    where
+        A: DecorationScheme,
+    {
+        if let (InlineContent::Note(_), Some(numbers)) = (inline, self.numbers.last_mut()) {
+            self.count += 1;
+            numbers.push(self.count);
+        }
+    }
+}
+
+impl<A> Map<A> for Sidenotes
+where
+    A: DecorationScheme,
+{
+    fn enter_inlines(&mut self, _inlines: &Inlines<A>) {
+        self.numbers.push(Vec::new());
+    }
+
+    fn enter_inline_condensed(&mut self, inline: &Inline<A, CondensedLeaf>) {
+        self.enter(&inline.content);
+    }
+
+    fn enter_inline_expanded(&mut self, inline: &Inline<A, ExpandedLeaf>) {
+        self.enter(&inline.content);
+    }
+
+    fn map_inlines(&mut self, mut inlines: Inlines<A>) -> Inlines<A> {
+        let numbers = self.numbers.pop().unwrap_or_default();
+        if numbers.is_empty() {
+            return inlines;
+        }
+
+        let mut numbers = numbers.into_iter();
+        let content = inlines.expand();
+        for inline in std::mem::take(content) {
+            match inline.content {
+                InlineContent::Note(blocks) => {
+                    content.extend(Self::sidenote(numbers.next().unwrap_or_default(), blocks))
+                }
+                other => content.push_back(Inline {
+                    decoration: inline.decoration,
+                    content: other,
+                }),
+            }
+        }
+        inlines
+    }
+}
+
+#[test]
+fn test_notes() {
+    let input = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[
+        {"t":"Para","c":[{"t":"Str","c":"a"},{"t":"Note","c":[{"t":"Para","c":[{"t":"Str","c":"x"}]}]}]},
+        {"t":"BlockQuote","c":[{"t":"Para","c":[
+            {"t":"Note","c":[{"t":"Para","c":[{"t":"Str","c":"x"}]}]},
+            {"t":"Note","c":[{"t":"Plain","c":[{"t":"Str","c":"y"}]},{"t":"CodeBlock","c":[["",[],[]],"z"]}]}
+        ]}]}
+    ]}"#;
+    let doc: Pandoc<NullScheme> = ::serde_json::from_str(input).unwrap();
+
+    let notes = doc.notes();
+    ::std::assert_eq!(
+        Vec::from_iter(notes.iter().map(|n| (n.number, n.first, n.ancestors.len()))),
+        vec![(1, 1, 1), (2, 1, 2), (3, 3, 2)]
+    );
+
+    let mut endnotes = doc.clone();
+    let count = endnotes.notes_to_endnotes(&EndnoteOptions {
+        dedupe: true,
+        ..EndnoteOptions::default()
+    });
+    ::std::assert_eq!(count, 2);
+    ::std::assert_eq!(
+        ::serde_json::to_value(&endnotes.blocks.content[0]).unwrap(),
+        ::serde_json::json!({"t":"Para","c":[{"t":"Str","c":"a"},{"t":"Link","c":[
+            ["fnref1",["footnote-ref"],[["role","doc-noteref"]]],
+            [{"t":"Superscript","c":[{"t":"Str","c":"1"}]}],
+            ["#fn1",""]
+        ]}]})
+    );
+    let text = Vec::from_iter(endnotes.blocks.content.iter().map(|b| b.stringify()));
+    ::std::assert_eq!(text[1], "12");
+    ::std::assert_eq!(text[2], "x\u{21a9}\u{fe0e}\ny\n\nz\n\n\u{21a9}\u{fe0e}");
+
+    let mut sidenotes = doc;
+    ::std::assert_eq!(sidenotes.notes_to_sidenotes(), 3);
+    ::std::assert_eq!(sidenotes.blocks.stringify(), "a11 x\n\n22 x33 y z");
+
+    // An outer note is numbered, and placed, before the notes within it.
+    let input = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[
+        {"t":"Para","c":[
+            {"t":"Note","c":[{"t":"Para","c":[{"t":"Str","c":"b"},{"t":"Note","c":[{"t":"Para","c":[{"t":"Str","c":"c"}]}]}]}]},
+            {"t":"Note","c":[{"t":"Para","c":[{"t":"Str","c":"d"}]}]}
+        ]}
+    ]}"#;
+    let doc: Pandoc<NullScheme> = ::serde_json::from_str(input).unwrap();
+    let mut endnotes = doc.clone();
+    ::std::assert_eq!(endnotes.notes_to_endnotes(&EndnoteOptions::default()), 3);
+    ::std::assert_eq!(endnotes.blocks.content[0].stringify(), "13");
+    let BlockContent::Div(_, footnotes) = &endnotes.blocks.content[1].content else {
+        panic!("expected the endnotes");
+    };
+    let BlockContent::OrderedList(_, items) = &footnotes.content[1].content else {
+        panic!("expected a list");
+    };
+    let items = Vec::from_iter(items.iter().map(|item| match &item.content[0].content {
+        BlockContent::Div(attr, blocks) => format!("{} {}", attr.identifier, blocks.stringify()),
+        _ => panic!("expected a div"),
+    }));
+    ::std::assert_eq!(
+        items,
+        vec![
+            "fn1 b2\u{21a9}\u{fe0e}",
+            "fn2 c\u{21a9}\u{fe0e}",
+            "fn3 d\u{21a9}\u{fe0e}"
+        ]
+    );
+
+    let mut sidenotes = doc;
+    ::std::assert_eq!(sidenotes.notes_to_sidenotes(), 3);
+    ::std::assert_eq!(sidenotes.blocks.stringify(), "11 b22 c33 d");
+}
@@ -0,0 +1,193 @@
+//! Section numbering
+
+use crate::ast::*;
+
+/// Options for [`Pandoc::number_sections`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberingOptions {
+    /// Added to the numbers of headers at each level, starting with level 1,
+    /// like Pandoc's `--number-offset`. For example, `[1, 4]` numbers a
+    /// document which begins with a level 2 header from `1.5`.
+    pub offset: Vec<i32>,
+    /// The style of the number at each level, starting with level 1. Levels
+    /// beyond the end of the list, and levels whose style is `Default` or
+    /// `Example`, use decimal numbers.
+    pub styles: Vec<ListNumberStyle>,
+    /// Write each header's number into its `number` attribute, as Pandoc
+    /// does.
+    pub attribute: bool,
+    /// Prepend each header's number to its text, in a `Span` with class
+    /// `header-section-number` followed by a space, as Pandoc's HTML writer
+    /// does.
+    pub span: bool,
+}
+
+impl Default for NumberingOptions {
+    fn default() -> Self {
+        NumberingOptions {
+            offset: Vec::new(),
+            styles: Vec::new(),
+            attribute: true,
+            span: false,
+        }
+    }
+}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    /// Number the document's headers hierarchically, as `1`, `1.1`, `1.2`,
+    /// `2`, and so on.
+    ///
+    /// As with Pandoc's `--number-sections`, headers at the top level of the
+    /// document and within divs are numbered, and headers with the class
+    /// `unnumbered` are skipped without affecting the count. A header whose
+    /// level skips intermediate levels gets zeros for them, as in `1.0.1`.
+    pub fn number_sections(&mut self, options: &NumberingOptions) {
+        let mut counters = options.offset.clone();
+        number_blocks(&mut self.blocks, &mut counters, options);
+    }
+}
+
+fn number_blocks<A>(blocks: &mut Blocks<A>, counters: &mut Vec<i32>, options: &NumberingOptions)
+where
+    A: DecorationScheme,
+{
+    for block in blocks.content.iter_mut() {
+        match &mut block.content {
+            BlockContent::Header(level, attr, inlines) if !attr.has_class("unnumbered") => {
+                advance_counters(counters, *level);
+                let number = format_section_number(counters, &options.styles);
+                if options.attribute {
+                    attr.set("number", &number);
+                }
+                if options.span {
+                    let span = Inline::span(
+                        Attr::builder().class("header-section-number").build(),
+                        Inline::str(&number),
+                    );
+                    let text = std::mem::take(inlines);
+                    *inlines = Inlines::from_iter([span.into(), Inline::space().into(), text]);
+                }
+            }
+            BlockContent::Div(_, content) => number_blocks(content, counters, options),
+            _ => {}
+        }
+    }
+}
+
+/// Advance the section counters past a header of the given level.
+pub(crate) fn advance_counters(counters: &mut Vec<i32>, level: i32) {
+    let level = usize::try_from(level).unwrap_or(0).max(1);
+    let next = counters.get(level - 1).copied().unwrap_or(0) + 1;
+    counters.resize(level - 1, 0);
+    counters.push(next);
+}
+
+pub(crate) fn format_section_number(counters: &[i32], styles: &[ListNumberStyle]) -> String {
+    let parts = counters.iter().enumerate().map(|(i, &n)| {
+        let style = styles.get(i).unwrap_or(&ListNumberStyle::Decimal);
+        format_list_number(n, style)
+    });
+    Vec::from_iter(parts).join(".")
+}
+
+/// Format `n` in the given list number style. Numbers which cannot be
+/// represented in the style, such as zero in roman numerals, are formatted in
+/// decimal.
+pub(crate) fn format_list_number(n: i32, style: &ListNumberStyle) -> String {
+    match style {
+        _ if n <= 0 => n.to_string(),
+        ListNumberStyle::LowerRoman => roman(n).to_lowercase(),
+        ListNumberStyle::UpperRoman => roman(n),
+        ListNumberStyle::LowerAlpha => alpha(n).to_lowercase(),
+        ListNumberStyle::UpperAlpha => alpha(n),
+        ListNumberStyle::Default | ListNumberStyle::Example | ListNumberStyle::Decimal => {
+            n.to_string()
+        }
+    }
+}
+
+fn roman(mut n: i32) -> String {
+    const NUMERALS: &[(i32, &str)] = &[
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+
+    let mut out = String::new();
+    for &(value, numeral) in NUMERALS {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+/// `A` to `Z`, then `AA`, `AB`, and so on.
+fn alpha(mut n: i32) -> String {
+    let mut out = Vec::new();
+    while n > 0 {
+        n -= 1;
+        out.push(char::from(b'A' + (n % 26) as u8));
+        n /= 26;
+    }
+    out.iter().rev().collect()
+}
+
+#[test]
+fn test_number_sections() {
+    let input = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[
+        {"t":"Header","c":[2,["",[],[]],[{"t":"Str","c":"a"}]]},
+        {"t":"Header","c":[1,["",[],[]],[{"t":"Str","c":"b"}]]},
+        {"t":"Header","c":[1,["",["unnumbered"],[]],[{"t":"Str","c":"c"}]]},
+        {"t":"Div","c":[["",[],[]],[{"t":"Header","c":[3,["",[],[]],[{"t":"Str","c":"d"}]]}]]},
+        {"t":"Header","c":[2,["",[],[]],[{"t":"Str","c":"e"}]]},
+        {"t":"Header","c":[1,["",[],[]],[{"t":"Str","c":"f"}]]}
+    ]}"#;
+    let mut doc: Pandoc<NullScheme> = ::serde_json::from_str(input).unwrap();
+    doc.number_sections(&NumberingOptions {
+        offset: vec![1, 4],
+        styles: vec![
+            ListNumberStyle::UpperRoman,
+            ListNumberStyle::Decimal,
+            ListNumberStyle::LowerAlpha,
+        ],
+        ..NumberingOptions::default()
+    });
+
+    let mut numbers = Vec::new();
+    for block in doc.blocks.content.iter() {
+        match &block.content {
+            BlockContent::Header(_, attr, _) => numbers.push(attr.get_str("number")),
+            BlockContent::Div(_, b) => match &b.content[0].content {
+                BlockContent::Header(_, attr, _) => numbers.push(attr.get_str("number")),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+    ::std::assert_eq!(
+        numbers,
+        vec![
+            Some("I.5".to_string()),
+            Some("II".to_string()),
+            None,
+            Some("II.0.a".to_string()),
+            Some("II.1".to_string()),
+            Some("III".to_string()),
+        ]
+    );
+}
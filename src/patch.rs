@@ -0,0 +1,346 @@
+//! Serializable edit operations on a document, addressed by node path
+
+use ::serde::{Deserialize, Serialize};
+use imbl::Vector;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+
+use crate::ast::*;
+use crate::path::{Node, NodePath, PathError};
+use crate::visit::Map;
+
+/// One operation of an [`AstPatch`].
+///
+/// Each operation records the node or attributes it expects to find, so
+/// that applying it can detect a document which has changed since the patch
+/// was made, and so that it can be inverted. Operations serialize in the
+/// style of JSON Patch, as `{"op": "replace", "path": "blocks[0]", ...}`.
+#[derive(Educe, Serialize, Deserialize)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+#[serde(tag = "op", rename_all = "kebab-case", bound = "")]
+pub enum PatchOp<A>
+where
+    A: DecorationScheme,
+{
+    /// Insert a node, as [`Pandoc::insert`] does.
+    Add { path: NodePath, value: Node<A> },
+    /// Remove the node `value`.
+    Remove { path: NodePath, value: Node<A> },
+    /// Replace the node `old` with `value`.
+    Replace {
+        path: NodePath,
+        old: Node<A>,
+        value: Node<A>,
+    },
+    /// Remove the node `value` from `from` and insert it at `path`, which
+    /// addresses the document as it is after the removal.
+    Move {
+        from: NodePath,
+        path: NodePath,
+        value: Node<A>,
+    },
+    /// Change the attributes of the node at `path` from `old` to `value`.
+    SetAttr {
+        path: NodePath,
+        old: Attr,
+        value: Attr,
+    },
+}
+
+/// A sequence of operations on a document, applied in order.
+///
+/// A patch serializes as the list of its operations.
+#[derive(Educe, Serialize, Deserialize)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    ),
+    Default
+)]
+#[serde(transparent, bound = "")]
+pub struct AstPatch<A>
+where
+    A: DecorationScheme,
+{
+    pub ops: Vec<PatchOp<A>>,
+}
+
+/// Error returned by [`AstPatch::apply`]. Each variant gives the index of
+/// the operation which failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// The operation's path can't be used for it.
+    Path(usize, PathError),
+    /// The node or attributes the operation expects at the path are not
+    /// there, as the document has changed since the patch was made.
+    Conflict(usize, NodePath),
+}
+
+impl<A> PatchOp<A>
+where
+    A: DecorationScheme,
+{
+    /// The operation which undoes this one.
+    pub fn invert(&self) -> PatchOp<A> {
+        match self.clone() {
+            PatchOp::Add { path, value } => PatchOp::Remove { path, value },
+            PatchOp::Remove { path, value } => PatchOp::Add { path, value },
+            PatchOp::Replace { path, old, value } => PatchOp::Replace {
+                path,
+                old: value,
+                value: old,
+            },
+            PatchOp::Move { from, path, value } => PatchOp::Move {
+                from: path,
+                path: from,
+                value,
+            },
+            PatchOp::SetAttr { path, old, value } => PatchOp::SetAttr {
+                path,
+                old: value,
+                value: old,
+            },
+        }
+    }
+}
+
+impl<A> AstPatch<A>
+where
+    A: DecorationScheme,
+{
+    pub fn new() -> Self {
+        AstPatch { ops: Vec::new() }
+    }
+
+    /// The patch which undoes this one: the inverse of each operation, in
+    /// reverse order.
+    pub fn invert(&self) -> AstPatch<A> {
+        AstPatch {
+            ops: Vec::from_iter(self.ops.iter().rev().map(PatchOp::invert)),
+        }
+    }
+
+    /// Apply the patch to a document. If an operation fails, the document
+    /// is left unchanged.
+    ///
+    /// Nodes are compared with those the operations expect whether their
+    /// inlines are condensed or expanded, but including their decorations,
+    /// which patches don't serialize.
+    pub fn apply(&self, document: &mut Pandoc<A>) -> Result<(), PatchError>
+    where
+        A::MetaValue: PartialEq,
+        A::Blocks: PartialEq,
+        A::Block: PartialEq,
+        A::Inlines: PartialEq,
+        A::Inline: PartialEq,
+        A::Caption: PartialEq,
+        A::Citation: PartialEq,
+        A::Table: PartialEq,
+        A::TableHead: PartialEq,
+        A::TableBody: PartialEq,
+        A::TableFoot: PartialEq,
+        A::Row: PartialEq,
+        A::Cell: PartialEq,
+    {
+        let mut result = document.clone();
+        for (i, op) in self.ops.iter().enumerate() {
+            apply_op(&mut result, i, op)?;
+        }
+        *document = result;
+        Ok(())
+    }
+}
+
+fn apply_op<A>(document: &mut Pandoc<A>, i: usize, op: &PatchOp<A>) -> Result<(), PatchError>
+where
+    A: DecorationScheme,
+    A::MetaValue: PartialEq,
+    A::Blocks: PartialEq,
+    A::Block: PartialEq,
+    A::Inlines: PartialEq,
+    A::Inline: PartialEq,
+    A::Caption: PartialEq,
+    A::Citation: PartialEq,
+    A::Table: PartialEq,
+    A::TableHead: PartialEq,
+    A::TableBody: PartialEq,
+    A::TableFoot: PartialEq,
+    A::Row: PartialEq,
+    A::Cell: PartialEq,
+{
+    let path_error = |error| PatchError::Path(i, error);
+    let found = |path: &NodePath| {
+        document
+            .get(path)
+            .ok_or_else(|| path_error(PathError::NotFound(path.clone())))
+    };
+    let expect = |path: &NodePath, node: &Node<A>| {
+        let current = found(path)?;
+        match condense(current) == condense(node.clone()) {
+            true => Ok(()),
+            false => Err(PatchError::Conflict(i, path.clone())),
+        }
+    };
+
+    match op {
+        PatchOp::Add { path, value } => {
+            document.insert(path, value.clone()).map_err(path_error)?;
+        }
+        PatchOp::Remove { path, value } => {
+            expect(path, value)?;
+            document.remove(path).map_err(path_error)?;
+        }
+        PatchOp::Replace { path, old, value } => {
+            expect(path, old)?;
+            document.replace(path, value.clone()).map_err(path_error)?;
+        }
+        PatchOp::Move { from, path, value } => {
+            expect(from, value)?;
+            let node = document.remove(from).map_err(path_error)?;
+            document.insert(path, node).map_err(path_error)?;
+        }
+        PatchOp::SetAttr { path, old, value } => {
+            let mut node = found(path)?;
+            let attrs = node
+                .attrs_mut()
+                .ok_or_else(|| path_error(PathError::WrongKind(path.clone())))?;
+            if attrs != old {
+                return Err(PatchError::Conflict(i, path.clone()));
+            }
+            *attrs = value.clone();
+            document.replace(path, node).map_err(path_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// Condense all the inlines of a node, so that it compares equal to the same
+/// node with its inlines in either form. An inline becomes a sequence of
+/// inlines.
+fn condense<A>(node: Node<A>) -> Node<A>
+where
+    A: DecorationScheme,
+{
+    struct Condenser;
+
+    impl<A> Map<A> for Condenser
+    where
+        A: DecorationScheme,
+    {
+        fn map_inlines(&mut self, mut inlines: Inlines<A>) -> Inlines<A> {
+            inlines.condense();
+            inlines
+        }
+    }
+
+    match node {
+        Node::Block(block) => Node::Block(block.walk(&mut Condenser)),
+        Node::Inline(inline) => Node::Inlines(Inlines::from(inline).walk(&mut Condenser)),
+        Node::Blocks(blocks) => Node::Blocks(blocks.walk(&mut Condenser)),
+        Node::Inlines(inlines) => Node::Inlines(inlines.walk(&mut Condenser)),
+        Node::DefinitionItem(term, definitions) => Node::DefinitionItem(
+            term.walk(&mut Condenser),
+            Vector::from_iter(definitions.into_iter().map(|d| d.walk(&mut Condenser))),
+        ),
+        Node::Row(row) => Node::Row(row.walk(&mut Condenser)),
+        Node::Cell(cell) => Node::Cell(cell.walk(&mut Condenser)),
+        Node::MetaValue(value) => Node::MetaValue(value.walk(&mut Condenser)),
+        Node::Citation(citation) => Node::Citation(citation.walk(&mut Condenser)),
+    }
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Path(i, error) => write!(f, "operation {}: {}", i, error),
+            PatchError::Conflict(i, path) => {
+                write!(f, "operation {}: node at {} has changed", i, path)
+            }
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+#[test]
+fn test_patch() {
+    let mut document: Pandoc<NullScheme> = Pandoc {
+        decoration: None,
+        meta: imbl::HashMap::new(),
+        blocks: crate::blocks![
+            Block::para("one"),
+            Block::div(
+                Attr::builder().id("a").build(),
+                crate::blocks![Block::para("two")]
+            ),
+            Block::para("three"),
+        ],
+    };
+    let original = document.clone();
+
+    let patch: AstPatch<NullScheme> = ::serde_json::from_value(::serde_json::json!([
+        {
+            "op": "replace",
+            "path": "blocks[0]/inlines[0]",
+            "old": {"t": "Inline", "c": [{"t": "Str", "c": "one"}]},
+            "value": {"t": "Inline", "c": [{"t": "Str", "c": "1"}]}
+        },
+        {
+            "op": "move",
+            "from": "blocks[2]",
+            "path": "blocks[0]",
+            "value": {"t": "Block", "c": {"t": "Para", "c": [{"t": "Str", "c": "three"}]}}
+        },
+        {"op": "set-attr", "path": "blocks[2]", "old": ["a", [], []], "value": ["b", [], []]},
+        {
+            "op": "add",
+            "path": "meta[title]",
+            "value": {"t": "MetaValue", "c": {"t": "MetaString", "c": "T"}}
+        },
+        {"op": "remove", "path": "blocks[2]/blocks[0]", "value": {"t": "Block", "c": {"t": "Para", "c": [{"t": "Str", "c": "two"}]}}}
+    ]))
+    .unwrap();
+    let json = ::serde_json::to_value(&patch).unwrap();
+    ::std::assert_eq!(
+        ::serde_json::from_value::<AstPatch<NullScheme>>(json).unwrap(),
+        patch
+    );
+
+    // Compare as JSON, as the patch leaves the inlines it edits expanded.
+    let json = |blocks: &Blocks<NullScheme>| ::serde_json::to_value(blocks).unwrap();
+    patch.apply(&mut document).unwrap();
+    let expected: Blocks<NullScheme> = crate::blocks![
+        Block::para("three"),
+        Block::para("1"),
+        Block::div(Attr::builder().id("b").build(), crate::blocks![]),
+    ];
+    ::std::assert_eq!(json(&document.blocks), json(&expected));
+    ::std::assert!(document.meta.contains_key(&"title".into()));
+
+    // The patch no longer matches the document it has changed.
+    let patched = document.clone();
+    ::std::assert_eq!(
+        patch.apply(&mut document),
+        Err(PatchError::Conflict(
+            0,
+            "blocks[0]/inlines[0]".parse().unwrap()
+        ))
+    );
+    ::std::assert_eq!(document, patched);
+
+    patch.invert().apply(&mut document).unwrap();
+    ::std::assert_eq!(json(&document.blocks), json(&original.blocks));
+    ::std::assert!(document.meta.is_empty());
+}
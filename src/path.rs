@@ -0,0 +1,1003 @@
+//! Addresses of nodes within a document
+
+use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+use imbl::{HashMap, Vector};
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+use crate::ast::*;
+use crate::interned::InternedString;
+use crate::table::TablePart;
+
+#[cfg(test)]
+use crate::stringify::Stringify;
+
+/// One step of a [`NodePath`], from a node to one of its children.
+///
+/// Which sequence a `Block` or `Inline` step indexes depends on the node it
+/// starts from: the document's blocks, the content of a block, inline, list
+/// item, definition, caption, table cell or metadata value. No node has more
+/// than one such sequence of each kind; a citation's prefix and suffix are
+/// reached with steps of their own.
+///
+/// A table's head, bodies and foot are not nodes: a `Row` step goes from the
+/// table straight to one of their rows. Their attributes, the number of row
+/// head columns of a body and the table's column specs have no path, and are
+/// changed by replacing the table.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PathStep {
+    /// A block of a sequence of blocks.
+    Block(usize),
+    /// An inline of a sequence of inlines, counted in expanded form, so that
+    /// each word, space and break is a step of its own.
+    Inline(usize),
+    /// An item of an ordered, bullet or definition list, or of a metadata
+    /// list.
+    Item(usize),
+    /// A line of a line block.
+    Line(usize),
+    /// The term of a definition list item.
+    Term,
+    /// A definition of a definition list item.
+    Definition(usize),
+    /// The caption of a figure or table.
+    Caption,
+    /// The short caption of a figure or table.
+    ShortCaption,
+    /// A row of a part of a table.
+    Row(TablePart, usize),
+    /// A cell of a table row.
+    Cell(usize),
+    /// A metadata entry, or an entry of a metadata map.
+    Meta(InternedString),
+    /// A citation of a cite.
+    Citation(usize),
+    /// The prefix of a citation.
+    Prefix,
+    /// The suffix of a citation.
+    Suffix,
+}
+
+/// The address of a node within a [`Pandoc`] document, as a sequence of
+/// steps from the root.
+///
+/// Its text form joins the steps with `/`, as in
+/// `blocks[3]/items[2]/blocks[0]/inlines[5]`. The empty path addresses the
+/// document itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodePath(pub Vec<PathStep>);
+
+/// Error returned by [`Pandoc::replace`], [`Pandoc::remove`] and
+/// [`Pandoc::insert`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// No node is at the path.
+    NotFound(NodePath),
+    /// The node given is not of the kind the path addresses, such as a block
+    /// for a path ending in an inline step.
+    WrongKind(NodePath),
+    /// The node at the path is not an element of a sequence or map, such as
+    /// a definition list term or the document itself, so it can only be
+    /// replaced.
+    NotRemovable(NodePath),
+    /// A node is already at the path, and it is not an element of a sequence
+    /// which the new node could be inserted into.
+    Occupied(NodePath),
+    /// The node at the path is not an element of a sequence, so it has no
+    /// siblings.
+    NotInSequence(NodePath),
+}
+
+/// Error returned when parsing a [`NodePath`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePathError(String);
+
+/// A node of a document, as addressed by a [`NodePath`].
+///
+/// Nodes serialize in the form of Pandoc's JSON, tagged with their kind, as
+/// `{"t": "Block", "c": {"t": "Para", "c": [...]}}`. An inline serializes as
+/// a list holding just that inline. Decorations are not serialized.
+#[derive(Educe, Serialize, Deserialize)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+#[serde(try_from = "NodeSerde<A>", into = "NodeSerde<A>", bound = "")]
+pub enum Node<A>
+where
+    A: DecorationScheme,
+{
+    Block(Block<A>),
+    Inline(Inline<A, ExpandedLeaf>),
+    /// A list item, definition or caption.
+    Blocks(Blocks<A>),
+    /// A line of a line block, a definition list term, a short caption, or
+    /// the prefix or suffix of a citation.
+    Inlines(Inlines<A>),
+    /// An item of a definition list: a term and its definitions.
+    DefinitionItem(Inlines<A>, Vector<Blocks<A>>),
+    Row(Row<A>),
+    Cell(Cell<A>),
+    MetaValue(MetaValue<A>),
+    Citation(Citation<A>),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "t", content = "c", bound = "")]
+enum NodeSerde<A>
+where
+    A: DecorationScheme,
+{
+    Block(Block<A>),
+    Inline(Inlines<A>),
+    Blocks(Blocks<A>),
+    Inlines(Inlines<A>),
+    DefinitionItem(Inlines<A>, Vector<Blocks<A>>),
+    Row(Row<A>),
+    Cell(Cell<A>),
+    MetaValue(MetaValue<A>),
+    Citation(Citation<A>),
+}
+
+impl PathStep {
+    /// The index of this step in its sequence, or `None` for a step to a
+    /// metadata key or a part which is not in a sequence.
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            PathStep::Block(i)
+            | PathStep::Inline(i)
+            | PathStep::Item(i)
+            | PathStep::Line(i)
+            | PathStep::Definition(i)
+            | PathStep::Row(_, i)
+            | PathStep::Cell(i)
+            | PathStep::Citation(i) => Some(*i),
+            PathStep::Term
+            | PathStep::Caption
+            | PathStep::ShortCaption
+            | PathStep::Meta(_)
+            | PathStep::Prefix
+            | PathStep::Suffix => None,
+        }
+    }
+
+    /// This step with its index changed, or `None` if it has no index.
+    pub fn with_index(&self, index: usize) -> Option<PathStep> {
+        let step = match self {
+            PathStep::Block(_) => PathStep::Block(index),
+            PathStep::Inline(_) => PathStep::Inline(index),
+            PathStep::Item(_) => PathStep::Item(index),
+            PathStep::Line(_) => PathStep::Line(index),
+            PathStep::Definition(_) => PathStep::Definition(index),
+            PathStep::Row(part, _) => PathStep::Row(*part, index),
+            PathStep::Cell(_) => PathStep::Cell(index),
+            PathStep::Citation(_) => PathStep::Citation(index),
+            PathStep::Term
+            | PathStep::Caption
+            | PathStep::ShortCaption
+            | PathStep::Meta(_)
+            | PathStep::Prefix
+            | PathStep::Suffix => return None,
+        };
+        Some(step)
+    }
+}
+
+impl NodePath {
+    /// The path of the document itself.
+    pub fn root() -> Self {
+        NodePath(Vec::new())
+    }
+
+    /// The path of a child of this node.
+    pub fn child(&self, step: PathStep) -> Self {
+        let mut path = self.clone();
+        path.0.push(step);
+        path
+    }
+
+    /// The path of this node's parent, or `None` for the root.
+    pub fn parent(&self) -> Option<Self> {
+        let (_, parent) = self.0.split_last()?;
+        Some(NodePath(parent.to_vec()))
+    }
+
+    /// The last step of the path, or `None` for the root.
+    pub fn last(&self) -> Option<&PathStep> {
+        self.0.last()
+    }
+
+    /// Whether this path addresses `other` or one of its descendants.
+    pub fn starts_with(&self, other: &NodePath) -> bool {
+        self.0.starts_with(&other.0)
+    }
+}
+
+impl Display for PathStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathStep::Block(i) => write!(f, "blocks[{}]", i),
+            PathStep::Inline(i) => write!(f, "inlines[{}]", i),
+            PathStep::Item(i) => write!(f, "items[{}]", i),
+            PathStep::Line(i) => write!(f, "lines[{}]", i),
+            PathStep::Term => write!(f, "term"),
+            PathStep::Definition(i) => write!(f, "definitions[{}]", i),
+            PathStep::Caption => write!(f, "caption"),
+            PathStep::ShortCaption => write!(f, "short"),
+            PathStep::Row(TablePart::Head, i) => write!(f, "head.rows[{}]", i),
+            PathStep::Row(TablePart::BodyHead(b), i) => write!(f, "bodies[{}].head[{}]", b, i),
+            PathStep::Row(TablePart::Body(b), i) => write!(f, "bodies[{}].rows[{}]", b, i),
+            PathStep::Row(TablePart::Foot, i) => write!(f, "foot.rows[{}]", i),
+            PathStep::Cell(i) => write!(f, "cells[{}]", i),
+            PathStep::Citation(i) => write!(f, "citations[{}]", i),
+            PathStep::Prefix => write!(f, "prefix"),
+            PathStep::Suffix => write!(f, "suffix"),
+            PathStep::Meta(key) => {
+                write!(f, "meta[")?;
+                for c in key.chars() {
+                    if matches!(c, ']' | '\\') {
+                        write!(f, "\\")?;
+                    }
+                    write!(f, "{}", c)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl Display for NodePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            write!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<PathStep>> for NodePath {
+    fn from(steps: Vec<PathStep>) -> Self {
+        NodePath(steps)
+    }
+}
+
+impl FromIterator<PathStep> for NodePath {
+    fn from_iter<T: IntoIterator<Item = PathStep>>(iter: T) -> Self {
+        NodePath(Vec::from_iter(iter))
+    }
+}
+
+impl<A> Node<A>
+where
+    A: DecorationScheme,
+{
+    /// The attributes of the node, if it has any: those of a header, code
+    /// block, figure, div, table, inline code, link, image, span, row or
+    /// cell.
+    pub fn attrs(&self) -> Option<&Attr> {
+        match self {
+            Node::Block(block) => match &block.content {
+                BlockContent::CodeBlock(attr, _)
+                | BlockContent::Header(_, attr, _)
+                | BlockContent::Figure(attr, _, _)
+                | BlockContent::Div(attr, _) => Some(attr),
+                BlockContent::Table(table) => Some(&table.attrs),
+                _ => None,
+            },
+            Node::Inline(inline) => match &inline.content {
+                InlineContent::Code(attr, _)
+                | InlineContent::Link(attr, _, _)
+                | InlineContent::Image(attr, _, _)
+                | InlineContent::Span(attr, _) => Some(attr),
+                _ => None,
+            },
+            Node::Row(row) => Some(&row.attrs),
+            Node::Cell(cell) => Some(&cell.attrs),
+            _ => None,
+        }
+    }
+
+    pub fn attrs_mut(&mut self) -> Option<&mut Attr> {
+        match self {
+            Node::Block(block) => match &mut block.content {
+                BlockContent::CodeBlock(attr, _)
+                | BlockContent::Header(_, attr, _)
+                | BlockContent::Figure(attr, _, _)
+                | BlockContent::Div(attr, _) => Some(attr),
+                BlockContent::Table(table) => Some(&mut table.attrs),
+                _ => None,
+            },
+            Node::Inline(inline) => match &mut inline.content {
+                InlineContent::Code(attr, _)
+                | InlineContent::Link(attr, _, _)
+                | InlineContent::Image(attr, _, _)
+                | InlineContent::Span(attr, _) => Some(attr),
+                _ => None,
+            },
+            Node::Row(row) => Some(&mut row.attrs),
+            Node::Cell(cell) => Some(&mut cell.attrs),
+            _ => None,
+        }
+    }
+}
+
+impl<A> From<Node<A>> for NodeSerde<A>
+where
+    A: DecorationScheme,
+{
+    fn from(node: Node<A>) -> Self {
+        match node {
+            Node::Block(b) => NodeSerde::Block(b),
+            Node::Inline(i) => NodeSerde::Inline(Inlines::from(i)),
+            Node::Blocks(b) => NodeSerde::Blocks(b),
+            Node::Inlines(i) => NodeSerde::Inlines(i),
+            Node::DefinitionItem(t, d) => NodeSerde::DefinitionItem(t, d),
+            Node::Row(r) => NodeSerde::Row(r),
+            Node::Cell(c) => NodeSerde::Cell(c),
+            Node::MetaValue(m) => NodeSerde::MetaValue(m),
+            Node::Citation(c) => NodeSerde::Citation(c),
+        }
+    }
+}
+
+impl<A> TryFrom<NodeSerde<A>> for Node<A>
+where
+    A: DecorationScheme,
+{
+    type Error = &'static str;
+
+    fn try_from(node: NodeSerde<A>) -> Result<Self, Self::Error> {
+        let node = match node {
+            NodeSerde::Block(b) => Node::Block(b),
+            NodeSerde::Inline(i) => {
+                let mut inlines = i.into_expanded();
+                match (inlines.next(), inlines.next()) {
+                    (Some(inline), None) => Node::Inline(inline),
+                    _ => return Err("expected a single inline"),
+                }
+            }
+            NodeSerde::Blocks(b) => Node::Blocks(b),
+            NodeSerde::Inlines(i) => Node::Inlines(i),
+            NodeSerde::DefinitionItem(t, d) => Node::DefinitionItem(t, d),
+            NodeSerde::Row(r) => Node::Row(r),
+            NodeSerde::Cell(c) => Node::Cell(c),
+            NodeSerde::MetaValue(m) => Node::MetaValue(m),
+            NodeSerde::Citation(c) => Node::Citation(c),
+        };
+        Ok(node)
+    }
+}
+
+/// Paths serialize as their text form.
+impl Serialize for NodePath {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for NodePath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(::serde::de::Error::custom)
+    }
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::NotFound(path) => write!(f, "no node at {}", path),
+            PathError::WrongKind(path) => write!(f, "wrong kind of node for {}", path),
+            PathError::NotRemovable(path) => write!(f, "node at {} cannot be removed", path),
+            PathError::Occupied(path) => write!(f, "a node is already at {}", path),
+            PathError::NotInSequence(path) => write!(f, "node at {} is not in a sequence", path),
+        }
+    }
+}
+
+impl Error for PathError {}
+
+impl Display for ParsePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid node path: {:?}", self.0)
+    }
+}
+
+impl Error for ParsePathError {}
+
+/// Parses the text form of a path. Spaces around the `/` between steps are
+/// allowed.
+impl FromStr for NodePath {
+    type Err = ParsePathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParsePathError(s.to_string());
+        let mut chars = s.trim().chars().peekable();
+        let mut steps = Vec::new();
+        while chars.peek().is_some() {
+            steps.push(parse_step(&mut chars).ok_or_else(err)?);
+            skip_spaces(&mut chars);
+            match chars.next() {
+                Some('/') if chars.peek().is_some() => skip_spaces(&mut chars),
+                None => {}
+                Some(_) => return Err(err()),
+            }
+        }
+        Ok(NodePath(steps))
+    }
+}
+
+fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// Read a name and an optional bracketed argument, such as `blocks[3]`.
+fn parse_part(chars: &mut Peekable<Chars>) -> Option<(String, Option<String>)> {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+        name.push(c);
+    }
+    if name.is_empty() {
+        return None;
+    }
+    if chars.next_if_eq(&'[').is_none() {
+        return Some((name, None));
+    }
+    let mut arg = String::new();
+    loop {
+        match chars.next()? {
+            ']' => return Some((name, Some(arg))),
+            '\\' => arg.push(chars.next()?),
+            c => arg.push(c),
+        }
+    }
+}
+
+fn parse_step(chars: &mut Peekable<Chars>) -> Option<PathStep> {
+    let (name, arg) = parse_part(chars)?;
+    let sub = match chars.next_if_eq(&'.') {
+        Some(_) => Some(parse_part(chars)?),
+        None => None,
+    };
+    let index = |arg: &Option<String>| arg.as_ref()?.parse::<usize>().ok();
+    let step = match (name.as_str(), sub) {
+        ("blocks", None) => PathStep::Block(index(&arg)?),
+        ("inlines", None) => PathStep::Inline(index(&arg)?),
+        ("items", None) => PathStep::Item(index(&arg)?),
+        ("lines", None) => PathStep::Line(index(&arg)?),
+        ("definitions", None) => PathStep::Definition(index(&arg)?),
+        ("cells", None) => PathStep::Cell(index(&arg)?),
+        ("citations", None) => PathStep::Citation(index(&arg)?),
+        ("meta", None) => PathStep::Meta(InternedString::from(arg?)),
+        ("term", None) if arg.is_none() => PathStep::Term,
+        ("caption", None) if arg.is_none() => PathStep::Caption,
+        ("short", None) if arg.is_none() => PathStep::ShortCaption,
+        ("prefix", None) if arg.is_none() => PathStep::Prefix,
+        ("suffix", None) if arg.is_none() => PathStep::Suffix,
+        (part, Some((rows, row))) => {
+            let part = match (part, rows.as_str()) {
+                ("head", "rows") if arg.is_none() => TablePart::Head,
+                ("foot", "rows") if arg.is_none() => TablePart::Foot,
+                ("bodies", "head") => TablePart::BodyHead(index(&arg)?),
+                ("bodies", "rows") => TablePart::Body(index(&arg)?),
+                _ => return None,
+            };
+            PathStep::Row(part, index(&row)?)
+        }
+        _ => return None,
+    };
+    Some(step)
+}
+
+/// A node of a document, borrowed mutably.
+pub(crate) enum NodeMut<'a, A>
+where
+    A: DecorationScheme,
+{
+    Pandoc(&'a mut Pandoc<A>),
+    Block(&'a mut Block<A>),
+    Inline(&'a mut Inline<A, ExpandedLeaf>),
+    Blocks(&'a mut Blocks<A>),
+    Inlines(&'a mut Inlines<A>),
+    DefinitionItem(&'a mut Inlines<A>, &'a mut Vector<Blocks<A>>),
+    Row(&'a mut Row<A>),
+    Cell(&'a mut Cell<A>),
+    MetaValue(&'a mut MetaValue<A>),
+    Citation(&'a mut Citation<A>),
+}
+
+/// The place a step leads to from a node: an element of one of its
+/// sequences or maps, or one of its fixed parts.
+pub(crate) enum Place<'a, A>
+where
+    A: DecorationScheme,
+{
+    Block(&'a mut Vector<Block<A>>, usize),
+    Inline(&'a mut Vector<Inline<A, ExpandedLeaf>>, usize),
+    Blocks(&'a mut Vector<Blocks<A>>, usize),
+    Inlines(&'a mut Vector<Inlines<A>>, usize),
+    DefinitionItem(&'a mut Vector<(Inlines<A>, Vector<Blocks<A>>)>, usize),
+    Row(&'a mut Vector<Row<A>>, usize),
+    Cell(&'a mut Vector<Cell<A>>, usize),
+    MetaItem(&'a mut Vector<MetaValue<A>>, usize),
+    Citation(&'a mut Vector<Citation<A>>, usize),
+    Meta(
+        &'a mut HashMap<InternedString, MetaValue<A>>,
+        InternedString,
+    ),
+    Term(&'a mut Inlines<A>),
+    Prefix(&'a mut Inlines<A>),
+    Suffix(&'a mut Inlines<A>),
+    Caption(&'a mut Blocks<A>),
+    ShortCaption(&'a mut Option<Inlines<A>>),
+}
+
+impl<'a, A> NodeMut<'a, A>
+where
+    A: DecorationScheme,
+{
+    pub(crate) fn place(self, step: &PathStep) -> Option<Place<'a, A>> {
+        let place = match (self, step) {
+            (NodeMut::Pandoc(p), PathStep::Block(i)) => Place::Block(&mut p.blocks.content, *i),
+            (NodeMut::Pandoc(p), PathStep::Meta(k)) => Place::Meta(&mut p.meta, k.clone()),
+            (NodeMut::Block(b), step) => match (&mut b.content, step) {
+                (
+                    BlockContent::Plain(inlines)
+                    | BlockContent::Para(inlines)
+                    | BlockContent::Header(_, _, inlines),
+                    PathStep::Inline(i),
+                ) => Place::Inline(inlines.expand(), *i),
+                (BlockContent::LineBlock(lines), PathStep::Line(i)) => Place::Inlines(lines, *i),
+                (
+                    BlockContent::BlockQuote(blocks)
+                    | BlockContent::Figure(_, _, blocks)
+                    | BlockContent::Div(_, blocks),
+                    PathStep::Block(i),
+                ) => Place::Block(&mut blocks.content, *i),
+                (
+                    BlockContent::OrderedList(_, items) | BlockContent::BulletList(items),
+                    PathStep::Item(i),
+                ) => Place::Blocks(items, *i),
+                (BlockContent::DefinitionList(items), PathStep::Item(i)) => {
+                    Place::DefinitionItem(items, *i)
+                }
+                (BlockContent::Figure(_, caption, _), PathStep::Caption) => {
+                    Place::Caption(&mut caption.full)
+                }
+                (BlockContent::Table(table), PathStep::Caption) => {
+                    Place::Caption(&mut table.caption.full)
+                }
+                (BlockContent::Figure(_, caption, _), PathStep::ShortCaption) => {
+                    Place::ShortCaption(&mut caption.short)
+                }
+                (BlockContent::Table(table), PathStep::ShortCaption) => {
+                    Place::ShortCaption(&mut table.caption.short)
+                }
+                (BlockContent::Table(table), PathStep::Row(part, i)) => {
+                    let rows = match part {
+                        TablePart::Head => &mut table.head.rows,
+                        TablePart::BodyHead(b) => &mut table.body.get_mut(*b)?.intermediate_head,
+                        TablePart::Body(b) => &mut table.body.get_mut(*b)?.rows,
+                        TablePart::Foot => &mut table.foot.rows,
+                    };
+                    Place::Row(rows, *i)
+                }
+                _ => return None,
+            },
+            (NodeMut::Inline(inline), step) => match (&mut inline.content, step) {
+                (
+                    InlineContent::Emph(inlines)
+                    | InlineContent::Underline(inlines)
+                    | InlineContent::Strong(inlines)
+                    | InlineContent::Strikeout(inlines)
+                    | InlineContent::Superscript(inlines)
+                    | InlineContent::Subscript(inlines)
+                    | InlineContent::SmallCaps(inlines)
+                    | InlineContent::Quoted(_, inlines)
+                    | InlineContent::Cite(_, inlines)
+                    | InlineContent::Link(_, inlines, _)
+                    | InlineContent::Image(_, inlines, _)
+                    | InlineContent::Span(_, inlines),
+                    PathStep::Inline(i),
+                ) => Place::Inline(inlines.expand(), *i),
+                (InlineContent::Note(blocks), PathStep::Block(i)) => {
+                    Place::Block(&mut blocks.content, *i)
+                }
+                (InlineContent::Cite(citations, _), PathStep::Citation(i)) => {
+                    Place::Citation(citations, *i)
+                }
+                _ => return None,
+            },
+            (NodeMut::Citation(citation), PathStep::Prefix) => Place::Prefix(&mut citation.prefix),
+            (NodeMut::Citation(citation), PathStep::Suffix) => Place::Suffix(&mut citation.suffix),
+            (NodeMut::Blocks(blocks), PathStep::Block(i)) => Place::Block(&mut blocks.content, *i),
+            (NodeMut::Inlines(inlines), PathStep::Inline(i)) => Place::Inline(inlines.expand(), *i),
+            (NodeMut::DefinitionItem(term, _), PathStep::Term) => Place::Term(term),
+            (NodeMut::DefinitionItem(_, definitions), PathStep::Definition(i)) => {
+                Place::Blocks(definitions, *i)
+            }
+            (NodeMut::Row(row), PathStep::Cell(i)) => Place::Cell(&mut row.cells, *i),
+            (NodeMut::Cell(cell), PathStep::Block(i)) => Place::Block(&mut cell.blocks.content, *i),
+            (NodeMut::MetaValue(value), step) => match (&mut value.content, step) {
+                (MetaValueContent::Map(map), PathStep::Meta(k)) => Place::Meta(map, k.clone()),
+                (MetaValueContent::List(items), PathStep::Item(i)) => Place::MetaItem(items, *i),
+                (MetaValueContent::Inlines(inlines), PathStep::Inline(i)) => {
+                    Place::Inline(inlines.expand(), *i)
+                }
+                (MetaValueContent::Blocks(blocks), PathStep::Block(i)) => {
+                    Place::Block(&mut blocks.content, *i)
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(place)
+    }
+
+    pub(crate) fn to_node(&self) -> Option<Node<A>> {
+        let node = match self {
+            NodeMut::Pandoc(_) => return None,
+            NodeMut::Block(b) => Node::Block((*b).clone()),
+            NodeMut::Inline(i) => Node::Inline((*i).clone()),
+            NodeMut::Blocks(b) => Node::Blocks((*b).clone()),
+            NodeMut::Inlines(i) => Node::Inlines((*i).clone()),
+            NodeMut::DefinitionItem(t, d) => Node::DefinitionItem((*t).clone(), (*d).clone()),
+            NodeMut::Row(r) => Node::Row((*r).clone()),
+            NodeMut::Cell(c) => Node::Cell((*c).clone()),
+            NodeMut::MetaValue(m) => Node::MetaValue((*m).clone()),
+            NodeMut::Citation(c) => Node::Citation((*c).clone()),
+        };
+        Some(node)
+    }
+}
+
+impl<'a, A> From<&'a mut Pandoc<A>> for NodeMut<'a, A>
+where
+    A: DecorationScheme,
+{
+    fn from(document: &'a mut Pandoc<A>) -> Self {
+        NodeMut::Pandoc(document)
+    }
+}
+
+impl<'a, A> From<&'a mut Node<A>> for NodeMut<'a, A>
+where
+    A: DecorationScheme,
+{
+    fn from(node: &'a mut Node<A>) -> Self {
+        match node {
+            Node::Block(b) => NodeMut::Block(b),
+            Node::Inline(i) => NodeMut::Inline(i),
+            Node::Blocks(b) => NodeMut::Blocks(b),
+            Node::Inlines(i) => NodeMut::Inlines(i),
+            Node::DefinitionItem(t, d) => NodeMut::DefinitionItem(t, d),
+            Node::Row(r) => NodeMut::Row(r),
+            Node::Cell(c) => NodeMut::Cell(c),
+            Node::MetaValue(m) => NodeMut::MetaValue(m),
+            Node::Citation(c) => NodeMut::Citation(c),
+        }
+    }
+}
+
+impl<'a, A> Place<'a, A>
+where
+    A: DecorationScheme,
+{
+    pub(crate) fn into_node(self) -> Option<NodeMut<'a, A>> {
+        let node = match self {
+            Place::Block(v, i) => NodeMut::Block(v.get_mut(i)?),
+            Place::Inline(v, i) => NodeMut::Inline(v.get_mut(i)?),
+            Place::Blocks(v, i) => NodeMut::Blocks(v.get_mut(i)?),
+            Place::Inlines(v, i) => NodeMut::Inlines(v.get_mut(i)?),
+            Place::DefinitionItem(v, i) => {
+                let (term, definitions) = v.get_mut(i)?;
+                NodeMut::DefinitionItem(term, definitions)
+            }
+            Place::Row(v, i) => NodeMut::Row(v.get_mut(i)?),
+            Place::Cell(v, i) => NodeMut::Cell(v.get_mut(i)?),
+            Place::MetaItem(v, i) => NodeMut::MetaValue(v.get_mut(i)?),
+            Place::Meta(map, k) => NodeMut::MetaValue(map.get_mut(&k)?),
+            Place::Citation(v, i) => NodeMut::Citation(v.get_mut(i)?),
+            Place::Term(term) | Place::Prefix(term) | Place::Suffix(term) => NodeMut::Inlines(term),
+            Place::Caption(caption) => NodeMut::Blocks(caption),
+            Place::ShortCaption(short) => NodeMut::Inlines(short.as_mut()?),
+        };
+        Some(node)
+    }
+
+    /// Replace the node at this place, returning the old one. Returns `Err`
+    /// if the node is of the wrong kind, and `Ok(None)` if there is no node
+    /// here.
+    pub(crate) fn replace(self, node: Node<A>) -> Result<Option<Node<A>>, ()> {
+        fn swap<T>(v: &mut Vector<T>, i: usize, x: T) -> Option<T>
+        where
+            T: Clone,
+        {
+            Some(std::mem::replace(v.get_mut(i)?, x))
+        }
+
+        let old = match (self, node) {
+            (Place::Block(v, i), Node::Block(x)) => swap(v, i, x).map(Node::Block),
+            (Place::Inline(v, i), Node::Inline(x)) => swap(v, i, x).map(Node::Inline),
+            (Place::Blocks(v, i), Node::Blocks(x)) => swap(v, i, x).map(Node::Blocks),
+            (Place::Inlines(v, i), Node::Inlines(x)) => swap(v, i, x).map(Node::Inlines),
+            (Place::DefinitionItem(v, i), Node::DefinitionItem(t, d)) => {
+                swap(v, i, (t, d)).map(|(t, d)| Node::DefinitionItem(t, d))
+            }
+            (Place::Row(v, i), Node::Row(x)) => swap(v, i, x).map(Node::Row),
+            (Place::Cell(v, i), Node::Cell(x)) => swap(v, i, x).map(Node::Cell),
+            (Place::MetaItem(v, i), Node::MetaValue(x)) => swap(v, i, x).map(Node::MetaValue),
+            (Place::Meta(map, k), Node::MetaValue(x)) => match map.get_mut(&k) {
+                Some(old) => Some(Node::MetaValue(std::mem::replace(old, x))),
+                None => None,
+            },
+            (Place::Citation(v, i), Node::Citation(x)) => swap(v, i, x).map(Node::Citation),
+            (
+                Place::Term(inlines) | Place::Prefix(inlines) | Place::Suffix(inlines),
+                Node::Inlines(x),
+            ) => Some(Node::Inlines(std::mem::replace(inlines, x))),
+            (Place::Caption(caption), Node::Blocks(x)) => {
+                Some(Node::Blocks(std::mem::replace(caption, x)))
+            }
+            (Place::ShortCaption(short), Node::Inlines(x)) => match short {
+                Some(old) => Some(Node::Inlines(std::mem::replace(old, x))),
+                None => None,
+            },
+            _ => return Err(()),
+        };
+        Ok(old)
+    }
+
+    /// Remove the node at this place. Returns `Err` if it can't be removed,
+    /// and `Ok(None)` if there is no node here.
+    pub(crate) fn remove(self) -> Result<Option<Node<A>>, ()> {
+        fn take<T>(v: &mut Vector<T>, i: usize) -> Option<T>
+        where
+            T: Clone,
+        {
+            (i < v.len()).then(|| v.remove(i))
+        }
+
+        let old = match self {
+            Place::Block(v, i) => take(v, i).map(Node::Block),
+            Place::Inline(v, i) => take(v, i).map(Node::Inline),
+            Place::Blocks(v, i) => take(v, i).map(Node::Blocks),
+            Place::Inlines(v, i) => take(v, i).map(Node::Inlines),
+            Place::DefinitionItem(v, i) => take(v, i).map(|(t, d)| Node::DefinitionItem(t, d)),
+            Place::Row(v, i) => take(v, i).map(Node::Row),
+            Place::Cell(v, i) => take(v, i).map(Node::Cell),
+            Place::MetaItem(v, i) => take(v, i).map(Node::MetaValue),
+            Place::Meta(map, k) => map.remove(&k).map(Node::MetaValue),
+            Place::ShortCaption(short) => short.take().map(Node::Inlines),
+            Place::Citation(v, i) => take(v, i).map(Node::Citation),
+            Place::Term(_) | Place::Prefix(_) | Place::Suffix(_) | Place::Caption(_) => {
+                return Err(())
+            }
+        };
+        Ok(old)
+    }
+
+    /// Insert a node at this place, moving any node already there and those
+    /// after it along.
+    pub(crate) fn insert(self, node: Node<A>, path: &NodePath) -> Result<(), PathError> {
+        fn put<T>(v: &mut Vector<T>, i: usize, x: T, path: &NodePath) -> Result<(), PathError>
+        where
+            T: Clone,
+        {
+            if i > v.len() {
+                return Err(PathError::NotFound(path.clone()));
+            }
+            v.insert(i, x);
+            Ok(())
+        }
+
+        match (self, node) {
+            (Place::Block(v, i), Node::Block(x)) => put(v, i, x, path),
+            (Place::Inline(v, i), Node::Inline(x)) => put(v, i, x, path),
+            (Place::Blocks(v, i), Node::Blocks(x)) => put(v, i, x, path),
+            (Place::Inlines(v, i), Node::Inlines(x)) => put(v, i, x, path),
+            (Place::DefinitionItem(v, i), Node::DefinitionItem(t, d)) => put(v, i, (t, d), path),
+            (Place::Row(v, i), Node::Row(x)) => put(v, i, x, path),
+            (Place::Cell(v, i), Node::Cell(x)) => put(v, i, x, path),
+            (Place::MetaItem(v, i), Node::MetaValue(x)) => put(v, i, x, path),
+            (Place::Citation(v, i), Node::Citation(x)) => put(v, i, x, path),
+            (Place::Meta(map, k), Node::MetaValue(x)) => match map.contains_key(&k) {
+                true => Err(PathError::Occupied(path.clone())),
+                false => {
+                    map.insert(k, x);
+                    Ok(())
+                }
+            },
+            (Place::ShortCaption(short), Node::Inlines(x)) => match short {
+                Some(_) => Err(PathError::Occupied(path.clone())),
+                None => {
+                    *short = Some(x);
+                    Ok(())
+                }
+            },
+            (Place::Term(_) | Place::Prefix(_) | Place::Suffix(_), Node::Inlines(_))
+            | (Place::Caption(_), Node::Blocks(_)) => Err(PathError::Occupied(path.clone())),
+            _ => Err(PathError::WrongKind(path.clone())),
+        }
+    }
+}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    fn node_mut(&mut self, steps: &[PathStep]) -> Option<NodeMut<'_, A>> {
+        let mut node = NodeMut::Pandoc(self);
+        for step in steps {
+            node = node.place(step)?.into_node()?;
+        }
+        Some(node)
+    }
+
+    /// The place the last step of a path leads to.
+    fn place_mut(&mut self, path: &NodePath) -> Option<Place<'_, A>> {
+        let (last, parent) = path.0.split_last()?;
+        self.node_mut(parent)?.place(last)
+    }
+
+    /// The node at a path, or `None` if there is none, or if the path is the
+    /// root.
+    ///
+    /// Inline steps count the inlines in expanded form, so the inlines along
+    /// the path are expanded in a copy of the document. Copying is cheap, as
+    /// the copy shares the document's content.
+    pub fn get(&self, path: &NodePath) -> Option<Node<A>> {
+        self.clone().node_mut(&path.0)?.to_node()
+    }
+
+    /// Replace the node at a path, returning the old node.
+    ///
+    /// Sequences of inlines along the path are left in expanded form.
+    pub fn replace(&mut self, path: &NodePath, node: Node<A>) -> Result<Node<A>, PathError> {
+        let not_found = || PathError::NotFound(path.clone());
+        match self.place_mut(path).ok_or_else(not_found)?.replace(node) {
+            Ok(old) => old.ok_or_else(not_found),
+            Err(()) => Err(PathError::WrongKind(path.clone())),
+        }
+    }
+
+    /// Remove the node at a path from its sequence or map, returning it. A
+    /// short caption may also be removed.
+    ///
+    /// Sequences of inlines along the path are left in expanded form.
+    pub fn remove(&mut self, path: &NodePath) -> Result<Node<A>, PathError> {
+        let not_found = || PathError::NotFound(path.clone());
+        if path.0.is_empty() {
+            return Err(PathError::NotRemovable(path.clone()));
+        }
+        match self.place_mut(path).ok_or_else(not_found)?.remove() {
+            Ok(old) => old.ok_or_else(not_found),
+            Err(()) => Err(PathError::NotRemovable(path.clone())),
+        }
+    }
+
+    /// Insert a node at a path, so that it is then found there. The nodes at
+    /// the path and after it in its sequence move along by one; a path one
+    /// past the end of a sequence appends to it. A metadata key or a short
+    /// caption may be inserted if there is none yet.
+    ///
+    /// Sequences of inlines along the path are left in expanded form.
+    pub fn insert(&mut self, path: &NodePath, node: Node<A>) -> Result<(), PathError> {
+        if path.0.is_empty() {
+            return Err(PathError::Occupied(path.clone()));
+        }
+        match self.place_mut(path) {
+            Some(place) => place.insert(node, path),
+            None => Err(PathError::NotFound(path.clone())),
+        }
+    }
+}
+
+#[test]
+fn test_path() {
+    let input = r#"{"pandoc-api-version":[1,23,1],"meta":{"title":{"t":"MetaInlines","c":[{"t":"Str","c":"T"}]}},"blocks":[
+        {"t":"Para","c":[{"t":"Str","c":"a"}]},
+        {"t":"BulletList","c":[
+            [{"t":"Para","c":[{"t":"Str","c":"one"},{"t":"Space"},{"t":"Emph","c":[{"t":"Str","c":"two"}]}]}],
+            [{"t":"Plain","c":[{"t":"Str","c":"three"}]}]
+        ]}
+    ]}"#;
+    let mut doc: Pandoc<NullScheme> = ::serde_json::from_str(input).unwrap();
+
+    let path: NodePath = "blocks[1] / items[0]/blocks[0]/inlines[2]/inlines[0]"
+        .parse()
+        .unwrap();
+    ::std::assert_eq!(
+        path.to_string(),
+        "blocks[1]/items[0]/blocks[0]/inlines[2]/inlines[0]"
+    );
+    ::std::assert_eq!(path.to_string().parse::<NodePath>().unwrap(), path);
+    ::std::assert_eq!(path.last(), Some(&PathStep::Inline(0)));
+    ::std::assert!(path.starts_with(&path.parent().unwrap()));
+    let Some(Node::Inline(two)) = doc.get(&path) else {
+        panic!("expected an inline");
+    };
+    ::std::assert_eq!(
+        two.content,
+        InlineContent::Leaf(ExpandedLeaf::Str("two".into()))
+    );
+
+    let old = doc.replace(&path, Node::Inline(Inline::str("2"))).unwrap();
+    ::std::assert_eq!(old, Node::Inline(two));
+    ::std::assert_eq!(doc.blocks.stringify(), "a\n\none 2\nthree");
+
+    let item: NodePath = "blocks[1]/items[1]".parse().unwrap();
+    ::std::assert!(matches!(doc.remove(&item), Ok(Node::Blocks(_))));
+    ::std::assert_eq!(doc.remove(&item), Err(PathError::NotFound(item.clone())));
+    ::std::assert_eq!(
+        doc.replace(
+            &"meta[title]".parse().unwrap(),
+            Node::Block(Block::horizontal_rule())
+        ),
+        Err(PathError::WrongKind("meta[title]".parse().unwrap()))
+    );
+    ::std::assert!(matches!(
+        doc.get(&"meta[title]/inlines[0]".parse().unwrap()),
+        Some(Node::Inline(_))
+    ));
+
+    let path = NodePath::from(vec![
+        PathStep::Meta("a]b/c".into()),
+        PathStep::Row(TablePart::BodyHead(1), 0),
+        PathStep::Cell(2),
+    ]);
+    ::std::assert_eq!(path.to_string(), "meta[a\\]b/c]/bodies[1].head[0]/cells[2]");
+    ::std::assert_eq!(path.to_string().parse::<NodePath>().unwrap(), path);
+    ::std::assert!("blocks[x]".parse::<NodePath>().is_err());
+
+    let input = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[
+        {"t":"Para","c":[{"t":"Cite","c":[
+            [{"citationId":"doe","citationPrefix":[{"t":"Str","c":"see"}],"citationSuffix":[],"citationMode":{"t":"NormalCitation"},"citationNoteNum":1,"citationHash":0}],
+            [{"t":"Str","c":"[see"},{"t":"Space"},{"t":"Str","c":"@doe]"}]
+        ]}]}
+    ]}"#;
+    let mut doc: Pandoc<NullScheme> = ::serde_json::from_str(input).unwrap();
+    let citation: NodePath = "blocks[0]/inlines[0]/citations[0]".parse().unwrap();
+    let prefix = citation.child(PathStep::Prefix);
+    ::std::assert_eq!(
+        prefix.to_string(),
+        "blocks[0]/inlines[0]/citations[0]/prefix"
+    );
+    ::std::assert!(matches!(doc.get(&citation), Some(Node::Citation(c)) if c.id == "doe".into()));
+    ::std::assert!(matches!(
+        doc.get(&prefix.child(PathStep::Inline(0))),
+        Some(Node::Inline(_))
+    ));
+    doc.replace(
+        &citation.child(PathStep::Suffix),
+        Node::Inlines(crate::inlines!["p. 4"]),
+    )
+    .unwrap();
+    ::std::assert_eq!(
+        doc.remove(&prefix),
+        Err(PathError::NotRemovable(prefix.clone()))
+    );
+    let Some(Node::Citation(doe)) = doc.get(&citation) else {
+        panic!("expected a citation");
+    };
+    ::std::assert_eq!(doe.suffix.stringify(), "p. 4");
+    let next: NodePath = "blocks[0]/inlines[0]/citations[1]".parse().unwrap();
+    doc.insert(&next, Node::Citation(doe)).unwrap();
+    ::std::assert!(doc.get(&next).is_some());
+    ::std::assert!("blocks[1]/".parse::<NodePath>().is_err());
+}
@@ -0,0 +1,611 @@
+//! Change-tracked documents merged from two versions
+
+use imbl::Vector;
+
+use crate::ast::*;
+use crate::diff::{align, same_block, same_inline, Chunk};
+use crate::visit::Map;
+
+/// Options for [`redline_with`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RedlineOptions {
+    /// The author of the changes, given to each change as an `author`
+    /// attribute.
+    pub author: Option<String>,
+    /// The date of the changes, given to each change as a `date` attribute.
+    /// The docx writer expects an ISO 8601 date, such as
+    /// `2024-05-01T00:00:00Z`.
+    pub date: Option<String>,
+    /// Also wrap deleted text in `Strikeout`, so that it is shown struck out
+    /// by writers which don't style the `deletion` class.
+    pub strikeout: bool,
+}
+
+/// Merge two versions of a document into one which shows the changes from
+/// `old` to `new`. See [`redline_with`].
+pub fn redline<A>(old: &Pandoc<A>, new: &Pandoc<A>) -> Pandoc<A>
+where
+    A: DecorationScheme,
+    A::MetaValue: PartialEq,
+    A::Blocks: PartialEq,
+    A::Block: PartialEq,
+    A::Inlines: PartialEq,
+    A::Inline: PartialEq,
+    A::Caption: PartialEq,
+    A::Citation: PartialEq,
+    A::Table: PartialEq,
+    A::TableHead: PartialEq,
+    A::TableBody: PartialEq,
+    A::TableFoot: PartialEq,
+    A::Row: PartialEq,
+    A::Cell: PartialEq,
+{
+    redline_with(old, new, &RedlineOptions::default())
+}
+
+/// Merge two versions of a document into one which shows the changes from
+/// `old` to `new`.
+///
+/// The versions are aligned as by [`diff`](crate::diff). Deleted text is
+/// wrapped in a `Span` with the class `deletion`, and inserted text in one
+/// with the class `insertion`, as by Pandoc's docx reader with
+/// `--track-changes=all`, so that the docx writer shows them as tracked
+/// changes. Pandoc has no tracked changes of blocks, so deleted and inserted
+/// blocks and list items are wrapped in a `Div` with the class `deletion` or
+/// `insertion`, and their text is also wrapped in spans. A paragraph, plain
+/// block, header or line block whose text changed is wrapped in a `Div` with
+/// the class `modification`. A block which changed in some other way, such
+/// as a code block or a table, appears twice: once deleted, and once
+/// inserted. Blocks which moved are shown as deleted from their old place and
+/// inserted in their new one.
+///
+/// The document's metadata is that of `new`.
+pub fn redline_with<A>(old: &Pandoc<A>, new: &Pandoc<A>, options: &RedlineOptions) -> Pandoc<A>
+where
+    A: DecorationScheme,
+    A::MetaValue: PartialEq,
+    A::Blocks: PartialEq,
+    A::Block: PartialEq,
+    A::Inlines: PartialEq,
+    A::Inline: PartialEq,
+    A::Caption: PartialEq,
+    A::Citation: PartialEq,
+    A::Table: PartialEq,
+    A::TableHead: PartialEq,
+    A::TableBody: PartialEq,
+    A::TableFoot: PartialEq,
+    A::Row: PartialEq,
+    A::Cell: PartialEq,
+{
+    let merger = Merger { options };
+    Pandoc {
+        decoration: new.decoration.clone(),
+        meta: new.meta.clone(),
+        blocks: merger.merge_blocks(&old.blocks, &new.blocks),
+    }
+}
+
+struct Merger<'a> {
+    options: &'a RedlineOptions,
+}
+
+impl Merger<'_> {
+    fn attr(&self, class: &str) -> Attr {
+        let mut attr = Attr::default();
+        attr.add_class(class);
+        if let Some(author) = &self.options.author {
+            attr.set("author", author);
+        }
+        if let Some(date) = &self.options.date {
+            attr.set("date", date);
+        }
+        attr
+    }
+
+    fn deleted_blocks<A>(&self, blocks: Blocks<A>) -> Block<A>
+    where
+        A: DecorationScheme,
+    {
+        let mut marker = TextMarker {
+            merger: self,
+            deletion: true,
+        };
+        Block::div(self.attr("deletion"), blocks.walk(&mut marker))
+    }
+
+    fn inserted_blocks<A>(&self, blocks: Blocks<A>) -> Block<A>
+    where
+        A: DecorationScheme,
+    {
+        let mut marker = TextMarker {
+            merger: self,
+            deletion: false,
+        };
+        Block::div(self.attr("insertion"), blocks.walk(&mut marker))
+    }
+
+    /// Mark a block which was merged from two different ones, if its own text
+    /// changed.
+    fn modified_block<A>(&self, block: Block<A>) -> Block<A>
+    where
+        A: DecorationScheme,
+    {
+        match block.content {
+            BlockContent::Plain(_)
+            | BlockContent::Para(_)
+            | BlockContent::Header(_, _, _)
+            | BlockContent::LineBlock(_) => Block::div(self.attr("modification"), block),
+            _ => block,
+        }
+    }
+
+    fn deleted_inlines<A>(&self, inlines: Vec<Inline<A, ExpandedLeaf>>) -> Inline<A, ExpandedLeaf>
+    where
+        A: DecorationScheme,
+    {
+        let inlines = Inlines::from_iter(inlines);
+        match self.options.strikeout {
+            true => Inline::span(self.attr("deletion"), Inline::strikeout(inlines)),
+            false => Inline::span(self.attr("deletion"), inlines),
+        }
+    }
+
+    fn inserted_inlines<A>(&self, inlines: Vec<Inline<A, ExpandedLeaf>>) -> Inline<A, ExpandedLeaf>
+    where
+        A: DecorationScheme,
+    {
+        Inline::span(self.attr("insertion"), Inlines::from_iter(inlines))
+    }
+
+    fn merge_blocks<A>(&self, old: &Blocks<A>, new: &Blocks<A>) -> Blocks<A>
+    where
+        A: DecorationScheme,
+        A::MetaValue: PartialEq,
+        A::Blocks: PartialEq,
+        A::Block: PartialEq,
+        A::Inlines: PartialEq,
+        A::Inline: PartialEq,
+        A::Caption: PartialEq,
+        A::Citation: PartialEq,
+        A::Table: PartialEq,
+        A::TableHead: PartialEq,
+        A::TableBody: PartialEq,
+        A::TableFoot: PartialEq,
+        A::Row: PartialEq,
+        A::Cell: PartialEq,
+    {
+        if old.content.ptr_eq(&new.content) {
+            return new.clone();
+        }
+
+        let (o, n) = (&old.content, &new.content);
+        let mut merged = Vector::new();
+        for chunk in align(o.len(), n.len(), |i, j| same_block(&o[i], &n[j])) {
+            let (o, n) = match chunk {
+                Chunk::Same(_, j) => {
+                    merged.push_back(n[j].clone());
+                    continue;
+                }
+                Chunk::Changed(i, j) => (o.clone().slice(i), n.clone().slice(j)),
+            };
+            let paired = o.len().min(n.len());
+            let mut deleted = Vector::new();
+            let mut inserted = Vector::new();
+            for k in 0..paired {
+                match self.merge_block(&o[k], &n[k]) {
+                    Some(block) => {
+                        self.flush_blocks(&mut merged, &mut deleted, &mut inserted);
+                        merged.push_back(self.modified_block(block));
+                    }
+                    None => {
+                        deleted.push_back(o[k].clone());
+                        inserted.push_back(n[k].clone());
+                    }
+                }
+            }
+            deleted.extend(o.iter().skip(paired).cloned());
+            inserted.extend(n.iter().skip(paired).cloned());
+            self.flush_blocks(&mut merged, &mut deleted, &mut inserted);
+        }
+
+        Blocks {
+            decoration: new.decoration.clone(),
+            content: merged,
+        }
+    }
+
+    fn flush_blocks<A>(
+        &self,
+        merged: &mut Vector<Block<A>>,
+        deleted: &mut Vector<Block<A>>,
+        inserted: &mut Vector<Block<A>>,
+    ) where
+        A: DecorationScheme,
+    {
+        if !deleted.is_empty() {
+            merged.push_back(self.deleted_blocks(std::mem::take(deleted).into()));
+        }
+        if !inserted.is_empty() {
+            merged.push_back(self.inserted_blocks(std::mem::take(inserted).into()));
+        }
+    }
+
+    /// Merge two blocks of the same kind, or return `None` if they must be
+    /// shown as deleted and inserted.
+    fn merge_block<A>(&self, old: &Block<A>, new: &Block<A>) -> Option<Block<A>>
+    where
+        A: DecorationScheme,
+        A::MetaValue: PartialEq,
+        A::Blocks: PartialEq,
+        A::Block: PartialEq,
+        A::Inlines: PartialEq,
+        A::Inline: PartialEq,
+        A::Caption: PartialEq,
+        A::Citation: PartialEq,
+        A::Table: PartialEq,
+        A::TableHead: PartialEq,
+        A::TableBody: PartialEq,
+        A::TableFoot: PartialEq,
+        A::Row: PartialEq,
+        A::Cell: PartialEq,
+    {
+        if old.decoration != new.decoration {
+            return None;
+        }
+        let content = match (&old.content, &new.content) {
+            (BlockContent::Plain(a), BlockContent::Plain(b)) => {
+                BlockContent::Plain(self.merge_inlines(a, b))
+            }
+            (BlockContent::Para(a), BlockContent::Para(b)) => {
+                BlockContent::Para(self.merge_inlines(a, b))
+            }
+            (BlockContent::Header(l1, a1, i1), BlockContent::Header(l2, a2, i2))
+                if l1 == l2 && a1 == a2 =>
+            {
+                BlockContent::Header(*l2, a2.clone(), self.merge_inlines(i1, i2))
+            }
+            (BlockContent::LineBlock(a), BlockContent::LineBlock(b)) if a.len() == b.len() => {
+                let lines = a
+                    .iter()
+                    .zip(b.iter())
+                    .map(|(a, b)| self.merge_inlines(a, b));
+                BlockContent::LineBlock(Vector::from_iter(lines))
+            }
+            (BlockContent::BlockQuote(a), BlockContent::BlockQuote(b)) => {
+                BlockContent::BlockQuote(self.merge_blocks(a, b))
+            }
+            (BlockContent::OrderedList(l1, a), BlockContent::OrderedList(l2, b)) if l1 == l2 => {
+                BlockContent::OrderedList(l2.clone(), self.merge_items(a, b))
+            }
+            (BlockContent::BulletList(a), BlockContent::BulletList(b)) => {
+                BlockContent::BulletList(self.merge_items(a, b))
+            }
+            (BlockContent::Div(a1, b1), BlockContent::Div(a2, b2)) if a1 == a2 => {
+                BlockContent::Div(a2.clone(), self.merge_blocks(b1, b2))
+            }
+            (BlockContent::Figure(a1, c1, b1), BlockContent::Figure(a2, c2, b2))
+                if a1 == a2 && c1 == c2 =>
+            {
+                BlockContent::Figure(a2.clone(), c2.clone(), self.merge_blocks(b1, b2))
+            }
+            _ => return None,
+        };
+        Some(Block {
+            decoration: new.decoration.clone(),
+            content,
+        })
+    }
+
+    /// Merge the items of two lists. Deleted and inserted items have their
+    /// content wrapped in a `Div`.
+    fn merge_items<A>(&self, old: &Vector<Blocks<A>>, new: &Vector<Blocks<A>>) -> Vector<Blocks<A>>
+    where
+        A: DecorationScheme,
+        A::MetaValue: PartialEq,
+        A::Blocks: PartialEq,
+        A::Block: PartialEq,
+        A::Inlines: PartialEq,
+        A::Inline: PartialEq,
+        A::Caption: PartialEq,
+        A::Citation: PartialEq,
+        A::Table: PartialEq,
+        A::TableHead: PartialEq,
+        A::TableBody: PartialEq,
+        A::TableFoot: PartialEq,
+        A::Row: PartialEq,
+        A::Cell: PartialEq,
+    {
+        let mut merged = Vector::new();
+        for chunk in align(old.len(), new.len(), |i, j| old[i] == new[j]) {
+            match chunk {
+                Chunk::Same(_, j) => merged.push_back(new[j].clone()),
+                Chunk::Changed(i, j) => {
+                    let paired = i.len().min(j.len());
+                    for k in 0..paired {
+                        let item = self.merge_blocks(&old[i.start + k], &new[j.start + k]);
+                        merged.push_back(item);
+                    }
+                    for i in i.start + paired..i.end {
+                        merged.push_back(self.deleted_blocks(old[i].clone()).into());
+                    }
+                    for j in j.start + paired..j.end {
+                        merged.push_back(self.inserted_blocks(new[j].clone()).into());
+                    }
+                }
+            }
+        }
+        merged
+    }
+
+    fn merge_inlines<A>(&self, old: &Inlines<A>, new: &Inlines<A>) -> Inlines<A>
+    where
+        A: DecorationScheme,
+        A::MetaValue: PartialEq,
+        A::Blocks: PartialEq,
+        A::Block: PartialEq,
+        A::Inlines: PartialEq,
+        A::Inline: PartialEq,
+        A::Caption: PartialEq,
+        A::Citation: PartialEq,
+        A::Table: PartialEq,
+        A::TableHead: PartialEq,
+        A::TableBody: PartialEq,
+        A::TableFoot: PartialEq,
+        A::Row: PartialEq,
+        A::Cell: PartialEq,
+    {
+        if old == new {
+            return new.clone();
+        }
+
+        let o = Vec::from_iter(old.expanded());
+        let n = Vec::from_iter(new.expanded());
+        let mut merged = Vec::new();
+        let mut deleted = Vec::new();
+        let mut inserted = Vec::new();
+        for chunk in align(o.len(), n.len(), |i, j| same_inline(&o[i], &n[j])) {
+            match chunk {
+                Chunk::Same(_, j) => {
+                    self.flush_inlines(&mut merged, &mut deleted, &mut inserted);
+                    merged.push(n[j].clone());
+                }
+                Chunk::Changed(i, j) => {
+                    let paired = i.len().min(j.len());
+                    for k in 0..paired {
+                        let (a, b) = (&o[i.start + k], &n[j.start + k]);
+                        match self.merge_inline(a, b) {
+                            Some(inline) => {
+                                self.flush_inlines(&mut merged, &mut deleted, &mut inserted);
+                                merged.push(inline);
+                            }
+                            None => {
+                                deleted.push(a.clone());
+                                inserted.push(b.clone());
+                            }
+                        }
+                    }
+                    deleted.extend(o[i.start + paired..i.end].iter().cloned());
+                    inserted.extend(n[j.start + paired..j.end].iter().cloned());
+                }
+            }
+        }
+        self.flush_inlines(&mut merged, &mut deleted, &mut inserted);
+
+        Inlines {
+            decoration: new.decoration.clone(),
+            content: InlinesContent::from_iter(merged),
+        }
+    }
+
+    fn flush_inlines<A>(
+        &self,
+        merged: &mut Vec<Inline<A, ExpandedLeaf>>,
+        deleted: &mut Vec<Inline<A, ExpandedLeaf>>,
+        inserted: &mut Vec<Inline<A, ExpandedLeaf>>,
+    ) where
+        A: DecorationScheme,
+    {
+        if !deleted.is_empty() {
+            merged.push(self.deleted_inlines(std::mem::take(deleted)));
+        }
+        if !inserted.is_empty() {
+            merged.push(self.inserted_inlines(std::mem::take(inserted)));
+        }
+    }
+
+    /// Merge two inline containers of the same kind, or return `None` if they
+    /// must be shown as deleted and inserted.
+    fn merge_inline<A>(
+        &self,
+        old: &Inline<A, ExpandedLeaf>,
+        new: &Inline<A, ExpandedLeaf>,
+    ) -> Option<Inline<A, ExpandedLeaf>>
+    where
+        A: DecorationScheme,
+        A::MetaValue: PartialEq,
+        A::Blocks: PartialEq,
+        A::Block: PartialEq,
+        A::Inlines: PartialEq,
+        A::Inline: PartialEq,
+        A::Caption: PartialEq,
+        A::Citation: PartialEq,
+        A::Table: PartialEq,
+        A::TableHead: PartialEq,
+        A::TableBody: PartialEq,
+        A::TableFoot: PartialEq,
+        A::Row: PartialEq,
+        A::Cell: PartialEq,
+    {
+        if old.decoration != new.decoration {
+            return None;
+        }
+        let content = match (&old.content, &new.content) {
+            (InlineContent::Emph(a), InlineContent::Emph(b)) => {
+                InlineContent::Emph(self.merge_inlines(a, b))
+            }
+            (InlineContent::Underline(a), InlineContent::Underline(b)) => {
+                InlineContent::Underline(self.merge_inlines(a, b))
+            }
+            (InlineContent::Strong(a), InlineContent::Strong(b)) => {
+                InlineContent::Strong(self.merge_inlines(a, b))
+            }
+            (InlineContent::Strikeout(a), InlineContent::Strikeout(b)) => {
+                InlineContent::Strikeout(self.merge_inlines(a, b))
+            }
+            (InlineContent::Superscript(a), InlineContent::Superscript(b)) => {
+                InlineContent::Superscript(self.merge_inlines(a, b))
+            }
+            (InlineContent::Subscript(a), InlineContent::Subscript(b)) => {
+                InlineContent::Subscript(self.merge_inlines(a, b))
+            }
+            (InlineContent::SmallCaps(a), InlineContent::SmallCaps(b)) => {
+                InlineContent::SmallCaps(self.merge_inlines(a, b))
+            }
+            (InlineContent::Quoted(q1, a), InlineContent::Quoted(q2, b)) if q1 == q2 => {
+                InlineContent::Quoted(q2.clone(), self.merge_inlines(a, b))
+            }
+            (InlineContent::Cite(c1, a), InlineContent::Cite(c2, b)) if c1 == c2 => {
+                InlineContent::Cite(c2.clone(), self.merge_inlines(a, b))
+            }
+            (InlineContent::Link(a1, i1, t1), InlineContent::Link(a2, i2, t2))
+                if a1 == a2 && t1 == t2 =>
+            {
+                InlineContent::Link(a2.clone(), self.merge_inlines(i1, i2), t2.clone())
+            }
+            (InlineContent::Note(a), InlineContent::Note(b)) => {
+                InlineContent::Note(self.merge_blocks(a, b))
+            }
+            (InlineContent::Span(a1, i1), InlineContent::Span(a2, i2)) if a1 == a2 => {
+                InlineContent::Span(a2.clone(), self.merge_inlines(i1, i2))
+            }
+            _ => return None,
+        };
+        Some(Inline {
+            decoration: new.decoration.clone(),
+            content,
+        })
+    }
+}
+
+/// Wraps the text of the blocks it walks in deletion or insertion spans.
+struct TextMarker<'a> {
+    merger: &'a Merger<'a>,
+    deletion: bool,
+}
+
+impl TextMarker<'_> {
+    fn mark<A>(&self, inlines: &mut Inlines<A>)
+    where
+        A: DecorationScheme,
+    {
+        let content = inlines.expand();
+        if content.is_empty() {
+            return;
+        }
+        let text = Vec::from_iter(std::mem::take(content));
+        content.push_back(match self.deletion {
+            true => self.merger.deleted_inlines(text),
+            false => self.merger.inserted_inlines(text),
+        });
+    }
+}
+
+impl<A> Map<A> for TextMarker<'_>
+where
+    A: DecorationScheme,
+{
+    fn map_block(&mut self, mut block: Block<A>) -> Block<A> {
+        match &mut block.content {
+            BlockContent::Plain(i) | BlockContent::Para(i) | BlockContent::Header(_, _, i) => {
+                self.mark(i)
+            }
+            BlockContent::LineBlock(lines) => lines.iter_mut().for_each(|i| self.mark(i)),
+            BlockContent::DefinitionList(items) => {
+                items.iter_mut().for_each(|(term, _)| self.mark(term))
+            }
+            _ => {}
+        }
+        block
+    }
+}
+
+#[test]
+fn test_redline() {
+    let old: Pandoc<NullScheme> = Pandoc {
+        decoration: None,
+        meta: imbl::HashMap::new(),
+        blocks: crate::blocks![
+            Block::para(crate::inlines![
+                "The buyer pays ",
+                Inline::emph("the seller"),
+                "."
+            ]),
+            Block::code_block(Attr::default(), "a"),
+            Block::bullet_list([crate::blocks![Block::plain("one")]]),
+        ],
+    };
+    let new: Pandoc<NullScheme> = Pandoc {
+        decoration: None,
+        meta: imbl::HashMap::new(),
+        blocks: crate::blocks![
+            Block::para(crate::inlines![
+                "The buyer pays ",
+                Inline::emph("the agent"),
+                "."
+            ]),
+            Block::code_block(Attr::default(), "b"),
+            Block::bullet_list([
+                crate::blocks![Block::plain("one")],
+                crate::blocks![Block::plain("two")]
+            ]),
+        ],
+    };
+
+    let merged = redline_with(
+        &old,
+        &new,
+        &RedlineOptions {
+            author: Some("Legal".into()),
+            ..RedlineOptions::default()
+        },
+    );
+    let change = |class: &str| ::serde_json::json!([["", [class], [["author", "Legal"]]]]);
+    let span = |class: &str, word: &str| ::serde_json::json!({"t":"Span","c":[change(class)[0], [{"t":"Str","c":word}]]});
+    ::std::assert_eq!(
+        ::serde_json::to_value(&merged.blocks.content[0]).unwrap(),
+        ::serde_json::json!({"t":"Div","c":[change("modification")[0], [{"t":"Para","c":[
+            {"t":"Str","c":"The"},{"t":"Space"},{"t":"Str","c":"buyer"},{"t":"Space"},
+            {"t":"Str","c":"pays"},{"t":"Space"},
+            {"t":"Emph","c":[
+                {"t":"Str","c":"the"},{"t":"Space"},
+                span("deletion", "seller"),span("insertion", "agent")
+            ]},
+            {"t":"Str","c":"."}
+        ]}]]})
+    );
+
+    let classes = Vec::from_iter(
+        merged
+            .blocks
+            .content
+            .iter()
+            .skip(1)
+            .map(|b| match &b.content {
+                BlockContent::Div(attr, _) => attr.classes[0].to_string(),
+                BlockContent::BulletList(items) => format!("{} items", items.len()),
+                _ => String::new(),
+            }),
+    );
+    ::std::assert_eq!(classes, ["deletion", "insertion", "2 items"]);
+
+    let BlockContent::BulletList(items) = &merged.blocks.content[3].content else {
+        unreachable!()
+    };
+    ::std::assert_eq!(
+        ::serde_json::to_value(&items[1]).unwrap(),
+        ::serde_json::json!([{"t":"Div","c":[
+            change("insertion")[0],
+            [{"t":"Plain","c":[span("insertion", "two")]}]
+        ]}])
+    );
+}
@@ -0,0 +1,263 @@
+//! Hierarchical views of a document's sections
+
+use imbl::Vector;
+use std::fmt::Debug;
+use std::iter::Peekable;
+
+use crate::ast::*;
+use crate::interned::InternedString;
+
+/// A section of a document: a header, the content which follows it, and the
+/// sections of greater level nested within it.
+///
+/// The header is held as the parts of a `Header` block.
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct Section<A>
+where
+    A: DecorationScheme,
+{
+    /// The decoration of the header block.
+    pub decoration: Option<A::Block>,
+    /// The level of the header.
+    pub level: i32,
+    /// The attributes of the header.
+    pub attr: Attr,
+    /// The text of the header.
+    pub title: Inlines<A>,
+    /// The blocks between the header and the first child section.
+    pub blocks: Vector<Block<A>>,
+    pub children: Vector<Section<A>>,
+}
+
+/// A document's blocks grouped into sections, in the manner of Pandoc's
+/// `makeSections`.
+///
+/// Only headers which appear directly in the top-level sequence of blocks
+/// begin sections. Headers nested within divs, block quotes, lists, and so on
+/// are treated as ordinary content. A section continues until the next header
+/// of the same or lesser level, and a header of greater level begins a child
+/// section even if intermediate levels are skipped.
+///
+/// Converting blocks into a `SectionTree` and back is lossless.
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::MetaValue : Debug, A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::MetaValue : PartialEq, A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct SectionTree<A>
+where
+    A: DecorationScheme,
+{
+    /// The decoration of the [`Blocks`] this tree was built from.
+    pub decoration: Option<A::Blocks>,
+    /// The blocks before the first header.
+    pub preamble: Vector<Block<A>>,
+    pub sections: Vector<Section<A>>,
+}
+
+impl<A> Section<A>
+where
+    A: DecorationScheme,
+{
+    /// The section's header block.
+    pub fn header(&self) -> Block<A> {
+        Block {
+            decoration: self.decoration.clone(),
+            content: BlockContent::Header(self.level, self.attr.clone(), self.title.clone()),
+        }
+    }
+
+    fn flatten_into(self, out: &mut Vector<Block<A>>) {
+        out.push_back(Block {
+            decoration: self.decoration,
+            content: BlockContent::Header(self.level, self.attr, self.title),
+        });
+        out.append(self.blocks);
+        for child in self.children {
+            child.flatten_into(out);
+        }
+    }
+
+    /// Wrap this section in a `Div` with class `section`, as Pandoc's
+    /// `makeSections` does. The header's identifier moves to the div, and its
+    /// classes are copied there.
+    fn into_div(self) -> Block<A> {
+        let mut attr = self.attr;
+
+        let mut classes = attr.classes.clone();
+        classes.push_front(InternedString::from("section"));
+        let div_attr = Attr {
+            identifier: std::mem::take(&mut attr.identifier),
+            classes,
+            attrs: Vector::new(),
+        };
+
+        let mut content = Vector::unit(Block {
+            decoration: self.decoration,
+            content: BlockContent::Header(self.level, attr, self.title),
+        });
+        content.append(self.blocks);
+        content.extend(self.children.into_iter().map(Section::into_div));
+
+        BlockContent::Div(div_attr, content.into()).into()
+    }
+}
+
+impl<A> SectionTree<A>
+where
+    A: DecorationScheme,
+{
+    pub fn from_blocks(blocks: Blocks<A>) -> Self {
+        let mut iter = blocks.content.into_iter().peekable();
+
+        let mut preamble = Vector::new();
+        while let Some(block) = iter.next_if(|b| header_level(b).is_none()) {
+            preamble.push_back(block);
+        }
+
+        SectionTree {
+            decoration: blocks.decoration,
+            preamble,
+            sections: take_sections(&mut iter, None),
+        }
+    }
+
+    /// Flatten the tree back into the blocks it was built from.
+    pub fn into_blocks(self) -> Blocks<A> {
+        let mut content = self.preamble;
+        for section in self.sections {
+            section.flatten_into(&mut content);
+        }
+
+        Blocks {
+            decoration: self.decoration,
+            content,
+        }
+    }
+
+    /// Flatten the tree, wrapping each section in a `Div` with class
+    /// `section` which takes the header's identifier.
+    pub fn into_section_divs(self) -> Blocks<A> {
+        let mut content = self.preamble;
+        content.extend(self.sections.into_iter().map(Section::into_div));
+
+        Blocks {
+            decoration: self.decoration,
+            content,
+        }
+    }
+}
+
+impl<A> From<Blocks<A>> for SectionTree<A>
+where
+    A: DecorationScheme,
+{
+    fn from(blocks: Blocks<A>) -> Self {
+        SectionTree::from_blocks(blocks)
+    }
+}
+
+impl<A> From<SectionTree<A>> for Blocks<A>
+where
+    A: DecorationScheme,
+{
+    fn from(tree: SectionTree<A>) -> Self {
+        tree.into_blocks()
+    }
+}
+
+fn header_level<A>(block: &Block<A>) -> Option<i32>
+where
+    A: DecorationScheme,
+{
+    match &block.content {
+        BlockContent::Header(level, _, _) => Some(*level),
+        _ => None,
+    }
+}
+
+/// Take sections from `iter` for as long as their headers are of greater
+/// level than `parent`.
+fn take_sections<A, I>(iter: &mut Peekable<I>, parent: Option<i32>) -> Vector<Section<A>>
+where
+    A: DecorationScheme,
+    I: Iterator<Item = Block<A>>,
+{
+    let mut sections = Vector::new();
+
+    while let Some(header) =
+        iter.next_if(|b| header_level(b).is_some_and(|l| parent.is_none_or(|p| l > p)))
+    {
+        let BlockContent::Header(level, attr, title) = header.content else {
+            unreachable!("section headers are Header blocks")
+        };
+
+        let mut blocks = Vector::new();
+        while let Some(block) = iter.next_if(|b| header_level(b).is_none()) {
+            blocks.push_back(block);
+        }
+
+        sections.push_back(Section {
+            decoration: header.decoration,
+            level,
+            attr,
+            title,
+            blocks,
+            children: take_sections(iter, Some(level)),
+        });
+    }
+
+    sections
+}
+
+#[test]
+fn test_section_tree() {
+    let input = r#"[
+        {"t":"Para","c":[{"t":"Str","c":"preamble"}]},
+        {"t":"Header","c":[1,["one",[],[]],[{"t":"Str","c":"One"}]]},
+        {"t":"Para","c":[{"t":"Str","c":"a"}]},
+        {"t":"Header","c":[3,["deep",[],[]],[{"t":"Str","c":"Deep"}]]},
+        {"t":"Header","c":[2,["two",[],[]],[{"t":"Str","c":"Two"}]]},
+        {"t":"Para","c":[{"t":"Str","c":"b"}]},
+        {"t":"Header","c":[1,["three",["appendix"],[]],[{"t":"Str","c":"Three"}]]}
+    ]"#;
+    let blocks: Blocks<NullScheme> = ::serde_json::from_str(input).unwrap();
+    let tree = SectionTree::from_blocks(blocks.clone());
+
+    ::std::assert_eq!(tree.preamble.len(), 1);
+    ::std::assert_eq!(tree.sections.len(), 2);
+    let one = &tree.sections[0];
+    ::std::assert_eq!(one.blocks.len(), 1);
+    ::std::assert_eq!(
+        Vec::from_iter(
+            one.children
+                .iter()
+                .map(|s| (s.level, s.attr.identifier.to_string()))
+        ),
+        vec![(3, "deep".to_string()), (2, "two".to_string())]
+    );
+    ::std::assert_eq!(tree.clone().into_blocks(), blocks);
+
+    let divs = tree.into_section_divs();
+    let output = ::serde_json::to_value(&divs.content[2]).unwrap();
+    ::std::assert_eq!(
+        output,
+        ::serde_json::json!({"t":"Div","c":[["three",["section","appendix"],[]],[
+            {"t":"Header","c":[1,["",["appendix"],[]],[{"t":"Str","c":"Three"}]]}
+        ]]})
+    );
+}
@@ -0,0 +1,279 @@
+//! Conversion of AST nodes into plain text
+
+use crate::ast::*;
+
+/// Conversion of an AST node into plain text, in the manner of Pandoc's
+/// `stringify`.
+///
+/// Markup is discarded and only text is kept. Spaces and line breaks become
+/// single spaces; quoted text is wrapped in curly quotes; code and math
+/// contribute their source; notes and raw content are dropped. Unlike Pandoc,
+/// consecutive blocks are separated by a blank line, and lines of a line block,
+/// list items, and table rows by a newline.
+pub trait Stringify {
+    /// Append the plain text of this node to `out`.
+    fn stringify_into(&self, out: &mut String);
+
+    /// Return the plain text of this node.
+    fn stringify(&self) -> String {
+        let mut out = String::new();
+        self.stringify_into(&mut out);
+        out
+    }
+}
+
+impl<A> Stringify for Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    fn stringify_into(&self, out: &mut String) {
+        self.blocks.stringify_into(out)
+    }
+}
+
+impl<A> Stringify for MetaValue<A>
+where
+    A: DecorationScheme,
+{
+    /// Lists and maps contribute the concatenated text of their values, maps in
+    /// key order. Booleans become `true` or `false`.
+    fn stringify_into(&self, out: &mut String) {
+        match &self.content {
+            MetaValueContent::Map(m) => {
+                let mut entries = Vec::from_iter(m.iter());
+                entries.sort_by_key(|(k, _)| *k);
+                for (_, v) in entries {
+                    v.stringify_into(out);
+                }
+            }
+            MetaValueContent::List(l) => {
+                for v in l {
+                    v.stringify_into(out);
+                }
+            }
+            MetaValueContent::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            MetaValueContent::String(s) => out.extend(s.chars()),
+            MetaValueContent::Inlines(i) => i.stringify_into(out),
+            MetaValueContent::Blocks(b) => b.stringify_into(out),
+        }
+    }
+}
+
+impl<A> Stringify for Blocks<A>
+where
+    A: DecorationScheme,
+{
+    fn stringify_into(&self, out: &mut String) {
+        join_into(out, "\n\n", self.content.iter())
+    }
+}
+
+impl<A> Stringify for Block<A>
+where
+    A: DecorationScheme,
+{
+    fn stringify_into(&self, out: &mut String) {
+        match &self.content {
+            BlockContent::Plain(i) | BlockContent::Para(i) | BlockContent::Header(_, _, i) => {
+                i.stringify_into(out)
+            }
+            BlockContent::LineBlock(lines) => join_into(out, "\n", lines.iter()),
+            BlockContent::CodeBlock(_, code) => out.extend(code.chars()),
+            BlockContent::RawBlock(_, _) | BlockContent::HorizontalRule => {}
+            BlockContent::BlockQuote(b) | BlockContent::Div(_, b) => b.stringify_into(out),
+            BlockContent::OrderedList(_, items) | BlockContent::BulletList(items) => {
+                join_into(out, "\n", items.iter())
+            }
+            BlockContent::DefinitionList(items) => {
+                let mut first = true;
+                for (term, definitions) in items {
+                    for part in std::iter::once(term.stringify())
+                        .chain(definitions.iter().map(Stringify::stringify))
+                    {
+                        push_separated(out, "\n", &mut first, &part);
+                    }
+                }
+            }
+            BlockContent::Table(t) => t.stringify_into(out),
+            BlockContent::Figure(_, caption, b) => {
+                let mut first = true;
+                push_separated(out, "\n\n", &mut first, &caption.stringify());
+                push_separated(out, "\n\n", &mut first, &b.stringify());
+            }
+        }
+    }
+}
+
+impl<A> Stringify for Inlines<A>
+where
+    A: DecorationScheme,
+{
+    fn stringify_into(&self, out: &mut String) {
+        match &self.content {
+            InlinesContent::Condensed(v) => {
+                for inline in v {
+                    inline.stringify_into(out);
+                }
+            }
+            InlinesContent::Expanded(v) => {
+                for inline in v {
+                    inline.stringify_into(out);
+                }
+            }
+        }
+    }
+}
+
+impl<A> Stringify for Inline<A, CondensedLeaf>
+where
+    A: DecorationScheme,
+{
+    fn stringify_into(&self, out: &mut String) {
+        inline_into(&self.content, out, |leaf, out| {
+            out.extend(leaf.chars().map(|ch| match ch {
+                ' ' | '\r' | '\n' | CONDENSED_LITERAL_SPACE => ' ',
+                CONDENSED_LITERAL_CR => '\r',
+                CONDENSED_LITERAL_NEWLINE => '\n',
+                _ => ch,
+            }))
+        })
+    }
+}
+
+impl<A> Stringify for Inline<A, ExpandedLeaf>
+where
+    A: DecorationScheme,
+{
+    fn stringify_into(&self, out: &mut String) {
+        inline_into(&self.content, out, |leaf, out| match leaf {
+            ExpandedLeaf::Str(s) => out.extend(s.chars()),
+            ExpandedLeaf::Space | ExpandedLeaf::SoftBreak | ExpandedLeaf::LineBreak => {
+                out.push(' ')
+            }
+        })
+    }
+}
+
+impl<A> Stringify for Caption<A>
+where
+    A: DecorationScheme,
+{
+    /// A caption's text is that of its full form; the short form is ignored.
+    fn stringify_into(&self, out: &mut String) {
+        self.full.stringify_into(out)
+    }
+}
+
+impl<A> Stringify for Table<A>
+where
+    A: DecorationScheme,
+{
+    fn stringify_into(&self, out: &mut String) {
+        let mut first = true;
+        push_separated(out, "\n\n", &mut first, &self.caption.stringify());
+
+        let rows = self
+            .head
+            .rows
+            .iter()
+            .chain(
+                self.body
+                    .iter()
+                    .flat_map(|b| b.intermediate_head.iter().chain(b.rows.iter())),
+            )
+            .chain(self.foot.rows.iter());
+        let mut rows_text = String::new();
+        join_into(&mut rows_text, "\n", rows);
+        push_separated(out, "\n\n", &mut first, &rows_text);
+    }
+}
+
+impl<A> Stringify for Row<A>
+where
+    A: DecorationScheme,
+{
+    /// Cells are separated by a single space.
+    fn stringify_into(&self, out: &mut String) {
+        join_into(out, " ", self.cells.iter())
+    }
+}
+
+impl<A> Stringify for Cell<A>
+where
+    A: DecorationScheme,
+{
+    fn stringify_into(&self, out: &mut String) {
+        self.blocks.stringify_into(out)
+    }
+}
+
+fn inline_into<A, L, F>(content: &InlineContent<A, L>, out: &mut String, leaf: F)
+where
+    A: DecorationScheme,
+    F: FnOnce(&L, &mut String),
+{
+    match content {
+        InlineContent::Leaf(l) => leaf(l, out),
+        InlineContent::Emph(i)
+        | InlineContent::Underline(i)
+        | InlineContent::Strong(i)
+        | InlineContent::Strikeout(i)
+        | InlineContent::Superscript(i)
+        | InlineContent::Subscript(i)
+        | InlineContent::SmallCaps(i)
+        | InlineContent::Cite(_, i)
+        | InlineContent::Link(_, i, _)
+        | InlineContent::Image(_, i, _)
+        | InlineContent::Span(_, i) => i.stringify_into(out),
+        InlineContent::Quoted(QuoteType::Single, i) => {
+            out.push('\u{2018}');
+            i.stringify_into(out);
+            out.push('\u{2019}');
+        }
+        InlineContent::Quoted(QuoteType::Double, i) => {
+            out.push('\u{201c}');
+            i.stringify_into(out);
+            out.push('\u{201d}');
+        }
+        InlineContent::Code(_, r) | InlineContent::Math(_, r) => out.extend(r.chars()),
+        InlineContent::RawInline(_, _) | InlineContent::Note(_) => {}
+    }
+}
+
+/// Append the text of each item to `out`, separating non-empty items with
+/// `separator`.
+fn join_into<'a, T, I>(out: &mut String, separator: &str, items: I)
+where
+    T: Stringify + 'a,
+    I: Iterator<Item = &'a T>,
+{
+    let mut first = true;
+    for item in items {
+        push_separated(out, separator, &mut first, &item.stringify());
+    }
+}
+
+fn push_separated(out: &mut String, separator: &str, first: &mut bool, text: &str) {
+    if !text.is_empty() {
+        if !*first {
+            out.push_str(separator);
+        }
+        out.push_str(text);
+        *first = false;
+    }
+}
+
+#[test]
+fn test_stringify() {
+    let input = r#"[
+        {"t":"Header","c":[1,["",[],[]],[{"t":"Str","c":"A"},{"t":"Space"},{"t":"Quoted","c":[{"t":"DoubleQuote"},[{"t":"Str","c":"quoted"}]]}]]},
+        {"t":"Para","c":[{"t":"Code","c":[["",[],[]],"x + y"]},{"t":"Note","c":[{"t":"Para","c":[{"t":"Str","c":"gone"}]}]},{"t":"LineBreak"},{"t":"Str","c":"end."}]},
+        {"t":"HorizontalRule"},
+        {"t":"BulletList","c":[[{"t":"Plain","c":[{"t":"Str","c":"one"}]}],[{"t":"Plain","c":[{"t":"Str","c":"two"}]}]]}
+    ]"#;
+    let blocks: Blocks<NullScheme> = ::serde_json::from_str(input).unwrap();
+    ::std::assert_eq!(
+        blocks.stringify(),
+        "A \u{201c}quoted\u{201d}\n\nx + y end.\n\none\ntwo"
+    );
+}
@@ -0,0 +1,361 @@
+use imbl::Vector;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+
+use super::{Slot, TableGrid};
+use crate::ast::*;
+use crate::stringify::Stringify;
+
+/// Whether the first row of a CSV file is a header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvHeader {
+    Always,
+    Never,
+    /// Guess from the content. The first row is taken to be a header if, in
+    /// most columns, it differs from the other rows: it is text where they
+    /// are all numbers, or its length differs where theirs are all the same.
+    /// Empty fields are ignored.
+    #[default]
+    Detect,
+}
+
+/// Options for [`Table::from_csv`] and [`Table::to_csv_with`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    /// The character used to quote fields, or `None` if fields are never
+    /// quoted, as in TSV.
+    pub quote: Option<char>,
+    pub header: CsvHeader,
+    /// Right-align columns whose body cells are all numbers.
+    pub infer_alignment: bool,
+}
+
+impl CsvOptions {
+    /// Options for tab-separated values, which are never quoted.
+    pub fn tsv() -> Self {
+        CsvOptions {
+            delimiter: '\t',
+            quote: None,
+            ..CsvOptions::default()
+        }
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            quote: Some('"'),
+            header: CsvHeader::Detect,
+            infer_alignment: true,
+        }
+    }
+}
+
+/// Error returned by [`Table::from_csv`].
+#[derive(Debug)]
+pub enum CsvError {
+    Io(io::Error),
+    /// A quoted field which begins on the given line is not closed.
+    UnterminatedQuote {
+        line: usize,
+    },
+}
+
+impl Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Io(e) => write!(f, "{}", e),
+            CsvError::UnterminatedQuote { line } => {
+                write!(f, "unterminated quoted field starting on line {}", line)
+            }
+        }
+    }
+}
+
+impl Error for CsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CsvError::Io(e) => Some(e),
+            CsvError::UnterminatedQuote { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for CsvError {
+    fn from(e: io::Error) -> Self {
+        CsvError::Io(e)
+    }
+}
+
+impl<A> Table<A>
+where
+    A: DecorationScheme,
+{
+    /// Read a table from comma-separated values, or other delimited text.
+    ///
+    /// Quoted fields follow RFC 4180: they may contain delimiters and line
+    /// breaks, and a doubled quote stands for a quote. Blank lines are
+    /// skipped, and short rows are padded with empty cells. Each non-empty
+    /// field becomes a cell holding a `Plain` block, as given by
+    /// [`Inlines::from_text`].
+    pub fn from_csv<R>(mut reader: R, options: &CsvOptions) -> Result<Self, CsvError>
+    where
+        R: Read,
+    {
+        let mut input = String::new();
+        reader.read_to_string(&mut input)?;
+        let mut records = parse_records(&input, options)?;
+
+        let width = records.iter().map(Vec::len).max().unwrap_or(0);
+        for record in records.iter_mut() {
+            record.resize(width, String::new());
+        }
+
+        let header = match options.header {
+            CsvHeader::Always => !records.is_empty(),
+            CsvHeader::Never => false,
+            CsvHeader::Detect => detect_header(&records),
+        };
+        let body = records.split_off(usize::from(header));
+
+        let colspecs = Vector::from_iter((0..width).map(|col| {
+            let numeric = body.iter().any(|r| !r[col].trim().is_empty())
+                && body
+                    .iter()
+                    .all(|r| r[col].trim().is_empty() || is_number(&r[col]));
+            ColSpec {
+                alignment: match options.infer_alignment && numeric {
+                    true => Alignment::Right,
+                    false => Alignment::Default,
+                },
+                col_width: ColWidth::Default,
+            }
+        }));
+
+        let row = |record: Vec<String>| Row {
+            decoration: None,
+            attrs: Attr::default(),
+            cells: Vector::from_iter(record.into_iter().map(|field| Cell {
+                decoration: None,
+                attrs: Attr::default(),
+                alignment: Alignment::Default,
+                row_span: 1,
+                col_span: 1,
+                blocks: match field.is_empty() {
+                    true => Blocks::default(),
+                    false => Block::plain(Inlines::from_text(&field)).into(),
+                },
+            })),
+        };
+
+        Ok(Table {
+            decoration: None,
+            attrs: Attr::default(),
+            caption: Caption::new(None, Blocks::default()),
+            colspecs,
+            head: TableHead {
+                decoration: None,
+                attrs: Attr::default(),
+                rows: Vector::from_iter(records.into_iter().map(row)),
+            },
+            body: Vector::unit(TableBody {
+                decoration: None,
+                attrs: Attr::default(),
+                row_head_cols: 0,
+                intermediate_head: Vector::new(),
+                rows: Vector::from_iter(body.into_iter().map(row)),
+            }),
+            foot: TableFoot {
+                decoration: None,
+                attrs: Attr::default(),
+                rows: Vector::new(),
+            },
+        })
+    }
+
+    /// Write the table as comma-separated values. See
+    /// [`Table::to_csv_with`].
+    pub fn to_csv<W>(&self, writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        self.to_csv_with(writer, &CsvOptions::default())
+    }
+
+    /// Write the table as delimited text, with one record for each row of
+    /// its head, bodies and foot, and one field for each column.
+    ///
+    /// Each cell is written as its plain text, as given by [`Stringify`],
+    /// and the columns a cell spans after its first are left empty. Fields
+    /// are quoted if they contain the delimiter, the quote or a line break.
+    /// If `options.quote` is `None`, these characters are replaced by spaces
+    /// instead. Records end with a line feed.
+    pub fn to_csv_with<W>(&self, mut writer: W, options: &CsvOptions) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let grid = TableGrid::from_table(self.clone());
+        for row in grid.rows.iter() {
+            let fields = row.slots.iter().map(|slot| match slot {
+                Slot::Origin(id) => quote_field(&grid.cells[id.0].blocks.stringify(), options),
+                Slot::Covered(_) | Slot::Empty => String::new(),
+            });
+            let mut record = Vec::from_iter(fields).join(&options.delimiter.to_string());
+            record.push('\n');
+            writer.write_all(record.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_records(input: &str, options: &CsvOptions) -> Result<Vec<Vec<String>>, CsvError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if Some(c) == options.quote && field.is_empty() && !quoted => {
+                let start = line;
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some(c) if Some(c) == options.quote => {
+                            if chars.next_if_eq(&c).is_some() {
+                                field.push(c);
+                            } else {
+                                break;
+                            }
+                        }
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => return Err(CsvError::UnterminatedQuote { line: start }),
+                    }
+                }
+            }
+            c if c == options.delimiter => {
+                record.push(std::mem::take(&mut field));
+                quoted = false;
+            }
+            '\r' | '\n' => {
+                if c == '\r' {
+                    chars.next_if_eq(&'\n');
+                }
+                line += 1;
+                if !record.is_empty() || !field.is_empty() || quoted {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                quoted = false;
+            }
+            c => field.push(c),
+        }
+    }
+    if !record.is_empty() || !field.is_empty() || quoted {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+fn detect_header(records: &[Vec<String>]) -> bool {
+    let Some((header, body)) = records.split_first() else {
+        return false;
+    };
+    if body.is_empty() {
+        return false;
+    }
+
+    let mut votes = 0;
+    for (col, name) in header.iter().enumerate() {
+        let values = Vec::from_iter(body.iter().map(|r| r[col].trim()).filter(|v| !v.is_empty()));
+        let Some(len) = values.first().map(|v| v.chars().count()) else {
+            continue;
+        };
+        if values.iter().all(|v| is_number(v)) {
+            votes += if is_number(name) { -1 } else { 1 };
+        } else if values.iter().all(|v| v.chars().count() == len) {
+            votes += if name.trim().chars().count() == len {
+                -1
+            } else {
+                1
+            };
+        }
+    }
+    votes > 0
+}
+
+fn is_number(field: &str) -> bool {
+    parse_number(field).is_some()
+}
+
+/// Parse a field as a number, such as `-1.5`, `2e3`, `1,000` or `50%`.
+pub(super) fn parse_number(field: &str) -> Option<f64> {
+    let field = field.trim();
+    let field = field.strip_suffix('%').unwrap_or(field).replace(',', "");
+    let digits = field
+        .trim_start_matches(['+', '-'])
+        .starts_with(|c: char| c.is_ascii_digit() || c == '.');
+    digits.then(|| field.parse().ok()).flatten()
+}
+
+fn quote_field(field: &str, options: &CsvOptions) -> String {
+    let special =
+        |c: char| c == options.delimiter || c == '\n' || c == '\r' || Some(c) == options.quote;
+    match options.quote {
+        Some(q) if field.contains(special) => {
+            let escaped = field.replace(q, &format!("{}{}", q, q));
+            format!("{}{}{}", q, escaped, q)
+        }
+        Some(_) => field.to_string(),
+        None => field.replace(special, " "),
+    }
+}
+
+#[test]
+fn test_csv() {
+    let input =
+        "name,price,note\r\nApple,1.50,\"red, \"\"crisp\"\"\"\n\nPear,2,\"two\nlines\"\nFig,,\n";
+    let table: Table<NullScheme> =
+        Table::from_csv(input.as_bytes(), &CsvOptions::default()).unwrap();
+
+    ::std::assert_eq!(table.head.rows.len(), 1);
+    ::std::assert_eq!(table.body[0].rows.len(), 3);
+    ::std::assert_eq!(
+        Vec::from_iter(table.colspecs.iter().map(|c| c.alignment.clone())),
+        vec![Alignment::Default, Alignment::Right, Alignment::Default]
+    );
+    ::std::assert_eq!(
+        table.body[0].rows[0].cells[2].blocks.stringify(),
+        "red, \"crisp\""
+    );
+    ::std::assert!(table.body[0].rows[2].cells[1].blocks.content.is_empty());
+
+    let mut output = Vec::new();
+    table.to_csv(&mut output).unwrap();
+    ::std::assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "name,price,note\nApple,1.50,\"red, \"\"crisp\"\"\"\nPear,2,two lines\nFig,,\n"
+    );
+
+    let tsv = "a\tb\n1\t2\n";
+    let table: Table<NullScheme> = Table::from_csv(tsv.as_bytes(), &CsvOptions::tsv()).unwrap();
+    ::std::assert_eq!(table.head.rows.len(), 1);
+
+    let error = Table::<NullScheme>::from_csv("a,\"b\n".as_bytes(), &CsvOptions::default());
+    ::std::assert_eq!(
+        error.unwrap_err().to_string(),
+        "unterminated quoted field starting on line 1"
+    );
+}
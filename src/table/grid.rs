@@ -0,0 +1,501 @@
+use imbl::Vector;
+use std::collections::HashSet;
+use std::fmt::Debug;
+
+use super::{CellPosition, TableDiagnostic};
+use crate::ast::*;
+#[cfg(test)]
+use crate::stringify::Stringify;
+
+/// The part of a table a row belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TablePart {
+    Head,
+    /// The intermediate head of the body with the given index.
+    BodyHead(usize),
+    /// The body with the given index.
+    Body(usize),
+    Foot,
+}
+
+/// An index into [`TableGrid::cells`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CellId(pub usize);
+
+/// A slot of a [`TableGrid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Slot {
+    /// The top left slot of a cell.
+    Origin(CellId),
+    /// A slot covered by a cell which spans several rows or columns.
+    Covered(CellId),
+    /// A slot not covered by any cell, at the end of a short row.
+    Empty,
+}
+
+impl Slot {
+    /// The cell occupying this slot, if any.
+    pub fn cell(self) -> Option<CellId> {
+        match self {
+            Slot::Origin(id) | Slot::Covered(id) => Some(id),
+            Slot::Empty => None,
+        }
+    }
+}
+
+/// A row of a [`TableGrid`].
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct GridRow<A>
+where
+    A: DecorationScheme,
+{
+    pub part: TablePart,
+    pub decoration: Option<A::Row>,
+    pub attrs: Attr,
+    /// One slot for each column of the grid.
+    pub slots: Vec<Slot>,
+}
+
+/// A view of a table as a dense matrix, in which each slot refers to the
+/// cell which occupies it.
+///
+/// Each cell of the table is stored once in `cells`. Its top left slot is a
+/// [`Slot::Origin`], and the other slots it spans are [`Slot::Covered`]. As
+/// in Pandoc, spans are confined to the part of the table which contains the
+/// cell, and a span which would overlap another cell or extend past the end
+/// of its part is shortened.
+///
+/// The grid may be edited freely, and should have one slot in each row for
+/// each column. When it is converted back into a [`Table`], rows which are
+/// shorter than the longest are padded with empty slots, the spans of each
+/// cell are recomputed from the rectangle of slots it covers, and slots which
+/// are not covered by a cell get an empty one. The `row_span` and `col_span`
+/// of the cells in `cells` are ignored.
+///
+/// Converting a table into a `TableGrid` and back is lossless if the table's
+/// spans are valid and its rows are all full.
+#[derive(Educe)]
+#[educe(
+    Debug(
+        bound = "A::Blocks : Debug, A::Block : Debug, A::Inlines : Debug, A::Inline : Debug, A::Caption : Debug, A::Citation : Debug, A::Table : Debug, A::TableHead : Debug, A::TableBody : Debug, A::TableFoot : Debug, A::Row : Debug, A:: Cell : Debug"
+    ),
+    Clone,
+    PartialEq(
+        bound = "A::Blocks : PartialEq, A::Block : PartialEq, A::Inlines : PartialEq, A::Inline : PartialEq, A::Caption : PartialEq, A::Citation : PartialEq, A::Table : PartialEq, A::TableHead : PartialEq, A::TableBody : PartialEq, A::TableFoot : PartialEq, A::Row : PartialEq, A:: Cell : PartialEq"
+    )
+)]
+pub struct TableGrid<A>
+where
+    A: DecorationScheme,
+{
+    pub decoration: Option<A::Table>,
+    pub attrs: Attr,
+    pub caption: Caption<A>,
+    /// The table's column specifications. If the grid is wider than this,
+    /// default specifications are added when it is converted back into a
+    /// table.
+    pub colspecs: Vector<ColSpec>,
+    /// The table's head, without its rows.
+    pub head: TableHead<A>,
+    /// The table's bodies, without their rows. Each keeps its
+    /// `row_head_cols`.
+    pub bodies: Vector<TableBody<A>>,
+    /// The table's foot, without its rows.
+    pub foot: TableFoot<A>,
+    pub rows: Vec<GridRow<A>>,
+    pub cells: Vec<Cell<A>>,
+}
+
+impl<A> TableGrid<A>
+where
+    A: DecorationScheme,
+{
+    pub fn from_table(table: Table<A>) -> Self {
+        TableGrid::build(table, &mut Vec::new())
+    }
+
+    /// Build the grid of a table, recording the problems found with its
+    /// spans in `diagnostics`.
+    pub(super) fn build(table: Table<A>, diagnostics: &mut Vec<TableDiagnostic>) -> Self {
+        let Table {
+            decoration,
+            attrs,
+            caption,
+            colspecs,
+            mut head,
+            body,
+            mut foot,
+        } = table;
+
+        let mut parts = Vec::new();
+        parts.push((TablePart::Head, std::mem::take(&mut head.rows)));
+        let bodies = Vector::from_iter(body.into_iter().enumerate().map(|(i, mut body)| {
+            parts.push((
+                TablePart::BodyHead(i),
+                std::mem::take(&mut body.intermediate_head),
+            ));
+            parts.push((TablePart::Body(i), std::mem::take(&mut body.rows)));
+            body
+        }));
+        parts.push((TablePart::Foot, std::mem::take(&mut foot.rows)));
+
+        let mut grid = TableGrid {
+            decoration,
+            attrs,
+            caption,
+            colspecs,
+            head,
+            bodies,
+            foot,
+            rows: Vec::new(),
+            cells: Vec::new(),
+        };
+        for (part, rows) in parts {
+            grid.place_rows(part, rows, diagnostics);
+        }
+
+        let width = grid.rows.iter().map(|r| r.slots.len()).max().unwrap_or(0);
+        let width = width.max(grid.colspecs.len());
+        for row in grid.rows.iter_mut() {
+            row.slots.resize(width, Slot::Empty);
+        }
+        grid
+    }
+
+    /// Place the cells of the rows of one part of a table.
+    fn place_rows(
+        &mut self,
+        part: TablePart,
+        rows: Vector<Row<A>>,
+        diagnostics: &mut Vec<TableDiagnostic>,
+    ) {
+        let start = self.rows.len();
+        let count = rows.len();
+        for row in rows.iter() {
+            self.rows.push(GridRow {
+                part,
+                decoration: row.decoration.clone(),
+                attrs: row.attrs.clone(),
+                slots: Vec::new(),
+            });
+        }
+
+        for (i, row) in rows.into_iter().enumerate() {
+            let r = start + i;
+            let mut col = 0;
+            for (cell_index, mut cell) in row.cells.into_iter().enumerate() {
+                let at = CellPosition {
+                    part,
+                    row: i,
+                    cell: cell_index,
+                };
+                if cell.row_span < 1 || cell.col_span < 1 {
+                    diagnostics.push(TableDiagnostic::InvalidSpan(at));
+                }
+
+                while self.rows[r]
+                    .slots
+                    .get(col)
+                    .is_some_and(|s| *s != Slot::Empty)
+                {
+                    col += 1;
+                }
+
+                let free = self.rows[r].slots[col.min(self.rows[r].slots.len())..]
+                    .iter()
+                    .take_while(|s| **s == Slot::Empty)
+                    .count();
+                let col_span = usize::try_from(cell.col_span).unwrap_or(0).max(1);
+                let col_span = match col + free < self.rows[r].slots.len() {
+                    true if col_span > free => {
+                        diagnostics.push(TableDiagnostic::OverlappingSpan(at));
+                        free
+                    }
+                    _ => col_span,
+                };
+                let row_span = usize::try_from(cell.row_span).unwrap_or(0).max(1);
+                let row_span = match row_span > count - i {
+                    true => {
+                        diagnostics.push(TableDiagnostic::SpanPastEnd(at));
+                        count - i
+                    }
+                    false => row_span,
+                };
+                cell.col_span = col_span as i32;
+                cell.row_span = row_span as i32;
+
+                let id = CellId(self.cells.len());
+                self.cells.push(cell);
+                for (dr, grid_row) in self.rows[r..r + row_span].iter_mut().enumerate() {
+                    if grid_row.slots.len() < col + col_span {
+                        grid_row.slots.resize(col + col_span, Slot::Empty);
+                    }
+                    for (dc, slot) in grid_row.slots[col..col + col_span].iter_mut().enumerate() {
+                        *slot = match (dr, dc) {
+                            (0, 0) => Slot::Origin(id),
+                            _ => Slot::Covered(id),
+                        };
+                    }
+                }
+                col += col_span;
+            }
+        }
+    }
+
+    /// The number of columns of the grid: the length of its longest row, or
+    /// the number of column specifications if it has no rows.
+    pub fn width(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|r| r.slots.len())
+            .max()
+            .unwrap_or(self.colspecs.len())
+    }
+
+    pub fn slot(&self, row: usize, col: usize) -> Option<Slot> {
+        self.rows.get(row)?.slots.get(col).copied()
+    }
+
+    /// The cell occupying a slot, whether as its origin or by spanning it.
+    pub fn get(&self, row: usize, col: usize) -> Option<&Cell<A>> {
+        let id = self.slot(row, col)?.cell()?;
+        self.cells.get(id.0)
+    }
+
+    pub fn get_mut(&mut self, row: usize, col: usize) -> Option<&mut Cell<A>> {
+        let id = self.slot(row, col)?.cell()?;
+        self.cells.get_mut(id.0)
+    }
+
+    /// The row and column of the origin of a cell.
+    pub fn origin(&self, id: CellId) -> Option<(usize, usize)> {
+        self.rows.iter().enumerate().find_map(|(r, row)| {
+            let c = row.slots.iter().position(|s| *s == Slot::Origin(id))?;
+            Some((r, c))
+        })
+    }
+
+    /// The indices of the rows belonging to a part of the table.
+    pub fn part_rows(&self, part: TablePart) -> impl Iterator<Item = usize> + '_ {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter(move |(_, r)| r.part == part)
+            .map(|(i, _)| i)
+    }
+
+    /// Add a cell to `cells`, returning its identifier.
+    pub fn push_cell(&mut self, cell: Cell<A>) -> CellId {
+        self.cells.push(cell);
+        CellId(self.cells.len() - 1)
+    }
+
+    /// Make the first slot of each cell, in reading order, its origin, and
+    /// its other slots covered. This should be called after slots are moved
+    /// or removed, so that each cell keeps exactly one origin.
+    pub fn fix_origins(&mut self) {
+        let mut seen = HashSet::new();
+        for row in self.rows.iter_mut() {
+            for slot in row.slots.iter_mut() {
+                if let Some(id) = slot.cell() {
+                    *slot = match seen.insert(id) {
+                        true => Slot::Origin(id),
+                        false => Slot::Covered(id),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Convert the grid back into a table.
+    pub fn into_table(self) -> Table<A> {
+        let width = self.width();
+        let mut colspecs = self.colspecs;
+        while colspecs.len() < width {
+            colspecs.push_back(ColSpec {
+                alignment: Alignment::Default,
+                col_width: ColWidth::Default,
+            });
+        }
+
+        let mut grid_rows = self.rows;
+        for row in grid_rows.iter_mut() {
+            row.slots.resize(width, Slot::Empty);
+        }
+
+        let mut covered = vec![vec![false; width]; grid_rows.len()];
+        let mut rows = Vec::with_capacity(grid_rows.len());
+        for (r, row) in grid_rows.iter().enumerate() {
+            let mut cells = Vector::new();
+            for c in 0..width {
+                if covered[r][c] {
+                    continue;
+                }
+
+                let cell = match row.slots[c] {
+                    Slot::Origin(id) => {
+                        let inside = |slot: Slot| slot == Slot::Covered(id);
+                        let col_span = 1 + row.slots[c + 1..]
+                            .iter()
+                            .take_while(|s| inside(**s))
+                            .count();
+                        let row_span = 1 + grid_rows[r + 1..]
+                            .iter()
+                            .take_while(|below| {
+                                below.part == row.part
+                                    && below.slots[c..c + col_span].iter().all(|s| inside(*s))
+                            })
+                            .count();
+                        for covered_row in &mut covered[r..r + row_span] {
+                            covered_row[c..c + col_span].fill(true);
+                        }
+
+                        let mut cell = self.cells[id.0].clone();
+                        cell.row_span = row_span as i32;
+                        cell.col_span = col_span as i32;
+                        cell
+                    }
+                    Slot::Covered(_) | Slot::Empty => empty_cell(),
+                };
+                cells.push_back(cell);
+            }
+            rows.push((
+                row.part,
+                Row {
+                    decoration: row.decoration.clone(),
+                    attrs: row.attrs.clone(),
+                    cells,
+                },
+            ));
+        }
+
+        let mut head = self.head;
+        let mut bodies = self.bodies;
+        let mut foot = self.foot;
+        for (part, row) in rows {
+            match part {
+                TablePart::Head => head.rows.push_back(row),
+                TablePart::BodyHead(i) => {
+                    if let Some(body) = bodies.get_mut(i) {
+                        body.intermediate_head.push_back(row);
+                    }
+                }
+                TablePart::Body(i) => {
+                    if let Some(body) = bodies.get_mut(i) {
+                        body.rows.push_back(row);
+                    }
+                }
+                TablePart::Foot => foot.rows.push_back(row),
+            }
+        }
+
+        Table {
+            decoration: self.decoration,
+            attrs: self.attrs,
+            caption: self.caption,
+            colspecs,
+            head,
+            body: bodies,
+            foot,
+        }
+    }
+}
+
+pub(crate) fn empty_cell<A>() -> Cell<A>
+where
+    A: DecorationScheme,
+{
+    Cell {
+        decoration: None,
+        attrs: Attr::default(),
+        alignment: Alignment::Default,
+        row_span: 1,
+        col_span: 1,
+        blocks: Blocks::default(),
+    }
+}
+
+impl<A> From<Table<A>> for TableGrid<A>
+where
+    A: DecorationScheme,
+{
+    fn from(table: Table<A>) -> Self {
+        TableGrid::from_table(table)
+    }
+}
+
+impl<A> From<TableGrid<A>> for Table<A>
+where
+    A: DecorationScheme,
+{
+    fn from(grid: TableGrid<A>) -> Self {
+        grid.into_table()
+    }
+}
+
+#[test]
+fn test_table_grid() {
+    let cell = |text: &str, rows: i32, cols: i32| ::serde_json::json!([["",[],[]],{"t":"AlignDefault"},rows,cols,[{"t":"Plain","c":[{"t":"Str","c":text}]}]]);
+    let row = |cells: Vec<::serde_json::Value>| ::serde_json::json!([["", [], []], cells]);
+    let colspec = ::serde_json::json!([{"t":"AlignDefault"},{"t":"ColWidthDefault"}]);
+    let input = ::serde_json::json!({"t":"Table","c":[
+        ["",[],[]],
+        [null,[]],
+        [colspec, colspec, colspec],
+        [["",[],[]],[row(vec![cell("h", 1, 3)])]],
+        [[["",[],[]],1,[],[
+            row(vec![cell("a", 2, 1), cell("b", 1, 2)]),
+            row(vec![cell("c", 1, 1), cell("d", 3, 1)])
+        ]]],
+        [["",[],[]],[]]
+    ]});
+    let block: Block<NullScheme> = ::serde_json::from_str(&input.to_string()).unwrap();
+    let BlockContent::Table(table) = block.content else {
+        unreachable!()
+    };
+
+    let grid = TableGrid::from_table(table.clone());
+    ::std::assert_eq!(grid.width(), 3);
+    ::std::assert_eq!(
+        Vec::from_iter(grid.rows.iter().map(|r| r.part)),
+        vec![TablePart::Head, TablePart::Body(0), TablePart::Body(0)]
+    );
+    ::std::assert_eq!(
+        grid.rows[2].slots,
+        vec![
+            Slot::Covered(CellId(1)),
+            Slot::Origin(CellId(3)),
+            Slot::Origin(CellId(4))
+        ]
+    );
+    let text = |row, col| grid.get(row, col).map(|c| c.blocks.stringify());
+    ::std::assert_eq!(text(0, 2), Some("h".to_string()));
+    ::std::assert_eq!(text(2, 0), Some("a".to_string()));
+    ::std::assert_eq!(grid.origin(CellId(2)), Some((1, 1)));
+    ::std::assert_eq!(grid.cells[4].row_span, 1);
+
+    // Ragged rows are padded with empty slots.
+    let mut ragged = grid.clone();
+    ragged.rows[0].slots.truncate(1);
+    ragged.rows[1].slots.push(Slot::Empty);
+    ::std::assert_eq!(ragged.width(), 4);
+    let ragged = ragged.into_table();
+    ::std::assert_eq!(
+        Vec::from_iter(ragged.head.rows[0].cells.iter().map(|c| c.col_span)),
+        vec![1, 1, 1, 1]
+    );
+    ::std::assert_eq!(ragged.colspecs.len(), 4);
+
+    let mut expected = table;
+    expected.body[0].rows[1].cells[1].row_span = 1;
+    ::std::assert_eq!(grid.into_table(), expected);
+}
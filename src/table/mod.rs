@@ -0,0 +1,11 @@
+//! Utilities for working with tables
+
+mod csv;
+mod grid;
+mod transform;
+mod validate;
+
+pub use csv::*;
+pub use grid::*;
+pub use transform::*;
+pub use validate::*;
@@ -0,0 +1,408 @@
+use imbl::Vector;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use super::csv::parse_number;
+use super::{GridRow, TableGrid, TablePart};
+use crate::ast::*;
+use crate::stringify::Stringify;
+
+/// Options for [`Table::sort_rows`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SortOptions {
+    /// Compare cells as numbers rather than as text. Cells which are not
+    /// numbers sort after those which are, in ascending text order even if
+    /// `descending` is set.
+    pub numeric: bool,
+    pub descending: bool,
+}
+
+impl<A> Table<A>
+where
+    A: DecorationScheme,
+{
+    /// Sort the rows of each body by the plain text of their cells in the
+    /// given column. The sort is stable.
+    ///
+    /// Rows joined by a cell which spans them are kept together, and sorted
+    /// by the first of them. Heads, intermediate heads and the foot are not
+    /// changed.
+    pub fn sort_rows(&mut self, column: usize, options: SortOptions) {
+        let mut grid = TableGrid::from_table(self.clone());
+
+        let mut rows = Vec::new();
+        for range in part_ranges(&grid) {
+            let part = grid.rows[range.start].part;
+            let mut groups = row_groups(&grid, range.clone());
+            if let TablePart::Body(_) = part {
+                let key = |group: &Range<usize>| {
+                    grid.get(group.start, column)
+                        .map(|c| c.blocks.stringify())
+                        .unwrap_or_default()
+                };
+                let mut keyed = Vec::from_iter(groups.into_iter().map(|g| (key(&g), g)));
+                keyed.sort_by(|(a, _), (b, _)| compare(a, b, options));
+                groups = Vec::from_iter(keyed.into_iter().map(|(_, g)| g));
+            }
+            rows.extend(
+                groups
+                    .into_iter()
+                    .flat_map(|g| g.map(|r| grid.rows[r].clone())),
+            );
+        }
+
+        grid.rows = rows;
+        *self = grid.into_table();
+    }
+
+    /// Remove the body rows for which `f` returns `false`. The function is
+    /// given the cell occupying each column of the row, whether as its
+    /// origin or by spanning it.
+    ///
+    /// Heads, intermediate heads and the foot are not changed. A cell which
+    /// spans several rows is kept as long as one of them is.
+    pub fn retain_rows<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&[Option<&Cell<A>>]) -> bool,
+    {
+        let mut grid = TableGrid::from_table(self.clone());
+
+        let keep = Vec::from_iter((0..grid.rows.len()).map(|r| match grid.rows[r].part {
+            TablePart::Body(_) => {
+                let cells = Vec::from_iter((0..grid.width()).map(|c| grid.get(r, c)));
+                f(&cells)
+            }
+            _ => true,
+        }));
+        let mut keep = keep.into_iter();
+        grid.rows.retain(|_| keep.next().unwrap_or(true));
+        grid.fix_origins();
+
+        *self = grid.into_table();
+    }
+
+    /// Rearrange the table's columns, so that column `i` of the result is
+    /// column `columns[i]` of the original. Columns which are not listed are
+    /// removed.
+    ///
+    /// Column specifications move with their columns, and cells keep their
+    /// alignment. A cell which spans several columns keeps those which remain
+    /// next to its first; the others become empty cells. The number of row
+    /// head columns of each body is clamped to the number of columns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a column is out of range.
+    pub fn select_columns(&mut self, columns: &[usize]) {
+        let mut grid = TableGrid::from_table(self.clone());
+
+        let width = grid.width();
+        if let Some(c) = columns.iter().find(|c| **c >= width) {
+            panic!("column {} out of range for table with {} columns", c, width);
+        }
+
+        let colspecs = grid.colspecs.clone();
+        grid.colspecs = Vector::from_iter(columns.iter().filter_map(|c| colspecs.get(*c).cloned()));
+        for row in grid.rows.iter_mut() {
+            row.slots = Vec::from_iter(columns.iter().map(|c| row.slots[*c]));
+        }
+        grid.fix_origins();
+        for body in grid.bodies.iter_mut() {
+            body.row_head_cols = body.row_head_cols.min(columns.len() as i32);
+        }
+
+        *self = grid.into_table();
+    }
+
+    /// Remove a column. See [`Table::select_columns`].
+    pub fn remove_column(&mut self, column: usize) {
+        let width = TableGrid::from_table(self.clone()).width();
+        let columns = Vec::from_iter((0..width).filter(|c| *c != column));
+        self.select_columns(&columns);
+    }
+
+    /// Swap the table's rows and columns.
+    ///
+    /// The result has a single body, whose rows are the original columns.
+    /// The original head rows become its row head columns, and the spans of
+    /// each cell are swapped. The original column specifications are lost,
+    /// and the result's are the default.
+    pub fn transpose(&mut self) {
+        let grid = TableGrid::from_table(self.clone());
+
+        let head_rows = grid.part_rows(TablePart::Head).count();
+        let mut body = grid.bodies.front().cloned().unwrap_or(TableBody {
+            decoration: None,
+            attrs: Attr::default(),
+            row_head_cols: 0,
+            intermediate_head: Vector::new(),
+            rows: Vector::new(),
+        });
+        body.row_head_cols = head_rows as i32;
+
+        let rows = Vec::from_iter((0..grid.width()).map(|c| GridRow {
+            part: TablePart::Body(0),
+            decoration: None,
+            attrs: Attr::default(),
+            slots: Vec::from_iter(grid.rows.iter().map(|r| r.slots[c])),
+        }));
+        let colspecs = Vector::from_iter(grid.rows.iter().map(|_| ColSpec {
+            alignment: Alignment::Default,
+            col_width: ColWidth::Default,
+        }));
+
+        let mut grid = TableGrid {
+            colspecs,
+            bodies: Vector::unit(body),
+            rows,
+            ..grid
+        };
+        grid.fix_origins();
+
+        *self = grid.into_table();
+    }
+
+    /// Split the table into several, each with at most `max_rows` body rows,
+    /// counting intermediate heads. Each table repeats the head, the foot and
+    /// the caption; only the first keeps the table's identifier. Each keeps
+    /// only the bodies it has rows of.
+    ///
+    /// Rows joined by a cell which spans them are not split, and neither is
+    /// an intermediate head from the first rows of its body, so a table may
+    /// have more rows than `max_rows` if such a group is larger.
+    pub fn split_rows(&self, max_rows: usize) -> Vec<Table<A>> {
+        let grid = TableGrid::from_table(self.clone());
+
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut after_head = false;
+        for range in part_ranges(&grid) {
+            let part = grid.rows[range.start].part;
+            match part {
+                TablePart::BodyHead(_) => groups.push(Vec::from_iter(range)),
+                TablePart::Body(_) => {
+                    for (g, group) in row_groups(&grid, range).into_iter().enumerate() {
+                        match groups.last_mut() {
+                            Some(head) if g == 0 && after_head => head.extend(group),
+                            _ => groups.push(Vec::from_iter(group)),
+                        }
+                    }
+                }
+                TablePart::Head | TablePart::Foot => {}
+            }
+            after_head = matches!(part, TablePart::BodyHead(_));
+        }
+
+        let mut chunks: Vec<Vec<usize>> = Vec::new();
+        for group in groups {
+            match chunks.last_mut() {
+                Some(chunk) if chunk.len() + group.len() <= max_rows => chunk.extend(group),
+                _ => chunks.push(group),
+            }
+        }
+        if chunks.is_empty() {
+            return vec![self.clone()];
+        }
+
+        let outer = |part| grid.rows.iter().filter(move |r| r.part == part).cloned();
+        Vec::from_iter(chunks.into_iter().enumerate().map(|(i, chunk)| {
+            let mut bodies: Vec<usize> = Vec::new();
+            let mut rows = Vec::from_iter(outer(TablePart::Head));
+            for r in chunk {
+                let mut row = grid.rows[r].clone();
+                let (TablePart::BodyHead(b) | TablePart::Body(b)) = row.part else {
+                    unreachable!("chunks hold body rows")
+                };
+                if bodies.last() != Some(&b) {
+                    bodies.push(b);
+                }
+                let index = bodies.len() - 1;
+                row.part = match row.part {
+                    TablePart::BodyHead(_) => TablePart::BodyHead(index),
+                    _ => TablePart::Body(index),
+                };
+                rows.push(row);
+            }
+            rows.extend(outer(TablePart::Foot));
+
+            let mut table = grid.clone();
+            if i > 0 {
+                table.attrs.identifier = Default::default();
+            }
+            table.bodies = Vector::from_iter(bodies.into_iter().map(|b| grid.bodies[b].clone()));
+            table.rows = rows;
+            table.fix_origins();
+            table.into_table()
+        }))
+    }
+}
+
+/// Compare the text of two cells, lexically or numerically.
+fn compare(a: &str, b: &str, options: SortOptions) -> Ordering {
+    let order = match options.numeric {
+        false => a.cmp(b),
+        true => match (parse_number(a), parse_number(b)) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => return a.cmp(b),
+        },
+    };
+    match options.descending {
+        true => order.reverse(),
+        false => order,
+    }
+}
+
+/// The ranges of consecutive grid rows in the same part of the table.
+fn part_ranges<A>(grid: &TableGrid<A>) -> Vec<Range<usize>>
+where
+    A: DecorationScheme,
+{
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (r, row) in grid.rows.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(range) if grid.rows[range.start].part == row.part => range.end = r + 1,
+            _ => ranges.push(r..r + 1),
+        }
+    }
+    ranges
+}
+
+/// Split a range of rows into groups joined by cells which span them.
+fn row_groups<A>(grid: &TableGrid<A>, rows: Range<usize>) -> Vec<Range<usize>>
+where
+    A: DecorationScheme,
+{
+    let mut groups: Vec<Range<usize>> = Vec::new();
+    for r in rows {
+        let joined = r > 0
+            && grid.rows[r].slots.iter().enumerate().any(|(c, slot)| {
+                slot.cell().is_some() && grid.rows[r - 1].slots[c].cell() == slot.cell()
+            });
+        match groups.last_mut() {
+            Some(group) if joined => group.end = r + 1,
+            _ => groups.push(r..r + 1),
+        }
+    }
+    groups
+}
+
+#[test]
+fn test_transform() {
+    let input = "name,price\nPear,2\nApple,\"1,000\"\nFig,n/a\nDate,3\n";
+    let mut table: Table<NullScheme> = Table::from_csv(
+        input.as_bytes(),
+        &super::CsvOptions {
+            header: super::CsvHeader::Always,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let column = |table: &Table<NullScheme>, c: usize| {
+        Vec::from_iter(
+            table.body[0]
+                .rows
+                .iter()
+                .map(|r| r.cells[c].blocks.stringify()),
+        )
+    };
+
+    table.sort_rows(1, SortOptions::default());
+    ::std::assert_eq!(column(&table, 0), ["Apple", "Pear", "Date", "Fig"]);
+    table.sort_rows(
+        1,
+        SortOptions {
+            numeric: true,
+            descending: false,
+        },
+    );
+    ::std::assert_eq!(column(&table, 0), ["Pear", "Date", "Apple", "Fig"]);
+    table.sort_rows(
+        1,
+        SortOptions {
+            numeric: true,
+            descending: true,
+        },
+    );
+    ::std::assert_eq!(column(&table, 0), ["Apple", "Date", "Pear", "Fig"]);
+
+    table.retain_rows(|cells| cells[1].is_some_and(|c| c.blocks.stringify() != "n/a"));
+    ::std::assert_eq!(column(&table, 0), ["Apple", "Date", "Pear"]);
+
+    table.select_columns(&[1, 0]);
+    ::std::assert_eq!(table.colspecs[0].alignment, Alignment::Default);
+    ::std::assert_eq!(table.head.rows[0].cells[0].blocks.stringify(), "price");
+
+    let tables = table.split_rows(2);
+    ::std::assert_eq!(tables.len(), 2);
+    ::std::assert_eq!(tables[1].head, table.head);
+    ::std::assert_eq!(column(&tables[1], 1), ["Pear"]);
+
+    table.remove_column(0);
+    table.transpose();
+    ::std::assert!(table.head.rows.is_empty());
+    ::std::assert_eq!(table.body[0].row_head_cols, 1);
+    ::std::assert_eq!(table.body[0].rows.len(), 1);
+    ::std::assert_eq!(
+        Vec::from_iter(
+            table.body[0].rows[0]
+                .cells
+                .iter()
+                .map(|c| c.blocks.stringify())
+        ),
+        ["name", "Apple", "Date", "Pear"]
+    );
+    ::std::assert_eq!(table.validate(), vec![]);
+}
+
+#[test]
+fn test_split_rows() {
+    let row = |text: &str| ::serde_json::json!([["",[],[]],[[["",[],[]],{"t":"AlignDefault"},1,1,[{"t":"Plain","c":[{"t":"Str","c":text}]}]]]]);
+    let input = ::serde_json::json!({"t":"Table","c":[
+        ["",[],[]],
+        [null,[]],
+        [[{"t":"AlignDefault"},{"t":"ColWidthDefault"}]],
+        [["",[],[]],[row("h")]],
+        [
+            [["b0",[],[]],0,[row("H")],[row("a"), row("b")]],
+            [["b1",[],[]],0,[],[row("c"), row("d"), row("e")]]
+        ],
+        [["",[],[]],[]]
+    ]});
+    let block: Block<NullScheme> = ::serde_json::from_str(&input.to_string()).unwrap();
+    let BlockContent::Table(table) = block.content else {
+        unreachable!()
+    };
+    let text = |rows: &Vector<Row<NullScheme>>| {
+        Vec::from_iter(rows.iter().map(|r| r.cells[0].blocks.stringify()))
+    };
+    let bodies = |table: &Table<NullScheme>| {
+        Vec::from_iter(table.body.iter().map(|b| {
+            (
+                b.attrs.identifier.to_string(),
+                text(&b.intermediate_head),
+                text(&b.rows),
+            )
+        }))
+    };
+    let body = |id: &str, head: &[&str], rows: &[&str]| {
+        let strings = |v: &[&str]| Vec::from_iter(v.iter().map(|s| s.to_string()));
+        (id.to_string(), strings(head), strings(rows))
+    };
+
+    let tables = table.split_rows(2);
+    ::std::assert_eq!(tables.len(), 3);
+    ::std::assert_eq!(bodies(&tables[0]), [body("b0", &["H"], &["a"])]);
+    ::std::assert_eq!(
+        bodies(&tables[1]),
+        [body("b0", &[], &["b"]), body("b1", &[], &["c"])]
+    );
+    ::std::assert_eq!(bodies(&tables[2]), [body("b1", &[], &["d", "e"])]);
+    ::std::assert!(tables.iter().all(|t| text(&t.head.rows) == ["h"]));
+    ::std::assert!(tables.iter().all(|t| t.validate().is_empty()));
+
+    // An intermediate head is not split from the first row of its body.
+    let tables = table.split_rows(1);
+    ::std::assert_eq!(tables.len(), 5);
+    ::std::assert_eq!(bodies(&tables[0]), [body("b0", &["H"], &["a"])]);
+}
@@ -0,0 +1,274 @@
+use std::fmt::{self, Display};
+
+use super::{Slot, TableGrid, TablePart};
+use crate::ast::*;
+
+/// The position of a cell in a [`Table`], as written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CellPosition {
+    pub part: TablePart,
+    /// The index of the row within its part.
+    pub row: usize,
+    /// The index of the cell within its row.
+    pub cell: usize,
+}
+
+/// A problem found by [`Table::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableDiagnostic {
+    /// The table has no column specifications.
+    NoColumns,
+    /// A cell has a row or column span less than 1.
+    InvalidSpan(CellPosition),
+    /// A cell spans columns already occupied by another cell.
+    OverlappingSpan(CellPosition),
+    /// A cell spans rows past the end of its part of the table.
+    SpanPastEnd(CellPosition),
+    /// A row's cells extend past the last column.
+    RowTooLong {
+        part: TablePart,
+        row: usize,
+        columns: usize,
+    },
+    /// A row's cells do not reach the last column.
+    RowTooShort {
+        part: TablePart,
+        row: usize,
+        columns: usize,
+    },
+    /// A body has more row head columns than the table has columns, or a
+    /// negative number of them.
+    RowHeadColumns { body: usize, row_head_cols: i32 },
+    /// A column's width is negative or not finite.
+    InvalidWidth { column: usize },
+    /// The column widths add up to more than the whole width of the page.
+    WidthsExceedOne { total: f64 },
+}
+
+impl Display for CellPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cell {} of row {} of {}", self.cell, self.row, self.part)
+    }
+}
+
+impl Display for TablePart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TablePart::Head => write!(f, "the head"),
+            TablePart::BodyHead(i) => write!(f, "the intermediate head of body {}", i),
+            TablePart::Body(i) => write!(f, "body {}", i),
+            TablePart::Foot => write!(f, "the foot"),
+        }
+    }
+}
+
+impl Display for TableDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableDiagnostic::NoColumns => write!(f, "table has no columns"),
+            TableDiagnostic::InvalidSpan(at) => write!(f, "{} has a span less than 1", at),
+            TableDiagnostic::OverlappingSpan(at) => write!(f, "{} overlaps another cell", at),
+            TableDiagnostic::SpanPastEnd(at) => {
+                write!(f, "{} spans past the end of {}", at, at.part)
+            }
+            TableDiagnostic::RowTooLong { part, row, columns } => {
+                write!(f, "row {} of {} has {} columns", row, part, columns)
+            }
+            TableDiagnostic::RowTooShort { part, row, columns } => {
+                write!(f, "row {} of {} has only {} columns", row, part, columns)
+            }
+            TableDiagnostic::RowHeadColumns {
+                body,
+                row_head_cols,
+            } => write!(f, "body {} has {} row head columns", body, row_head_cols),
+            TableDiagnostic::InvalidWidth { column } => {
+                write!(f, "column {} has an invalid width", column)
+            }
+            TableDiagnostic::WidthsExceedOne { total } => {
+                write!(f, "column widths add up to {}", total)
+            }
+        }
+    }
+}
+
+impl<A> Table<A>
+where
+    A: DecorationScheme,
+{
+    /// Check the table for structures which Pandoc rejects or renders badly.
+    /// See [`Table::normalize`].
+    pub fn validate(&self) -> Vec<TableDiagnostic> {
+        let mut diagnostics = Vec::new();
+        let columns = self.colspecs.len();
+        if columns == 0 {
+            diagnostics.push(TableDiagnostic::NoColumns);
+        }
+
+        let grid = TableGrid::build(self.clone(), &mut diagnostics);
+        let mut row = 0;
+        for (r, grid_row) in grid.rows.iter().enumerate() {
+            if r > 0 && grid.rows[r - 1].part != grid_row.part {
+                row = 0;
+            }
+            let extent = grid_row
+                .slots
+                .iter()
+                .rposition(|s| *s != Slot::Empty)
+                .map_or(0, |c| c + 1);
+            if extent > columns && columns > 0 {
+                diagnostics.push(TableDiagnostic::RowTooLong {
+                    part: grid_row.part,
+                    row,
+                    columns: extent,
+                });
+            } else if grid_row.slots[..columns].contains(&Slot::Empty) {
+                diagnostics.push(TableDiagnostic::RowTooShort {
+                    part: grid_row.part,
+                    row,
+                    columns: extent,
+                });
+            }
+            row += 1;
+        }
+
+        for (body, b) in self.body.iter().enumerate() {
+            if b.row_head_cols < 0 || b.row_head_cols as usize > columns {
+                diagnostics.push(TableDiagnostic::RowHeadColumns {
+                    body,
+                    row_head_cols: b.row_head_cols,
+                });
+            }
+        }
+
+        let mut total = 0.0;
+        for (column, colspec) in self.colspecs.iter().enumerate() {
+            if let ColWidth::Percent(width) = colspec.col_width {
+                if width.is_finite() && width >= 0.0 {
+                    total += width;
+                } else {
+                    diagnostics.push(TableDiagnostic::InvalidWidth { column });
+                }
+            }
+        }
+        // Allow for rounding in widths which were scaled to add up to 1.
+        if total > 1.0 + 1e-9 {
+            diagnostics.push(TableDiagnostic::WidthsExceedOne { total });
+        }
+
+        diagnostics
+    }
+
+    /// Repair the problems reported by [`Table::validate`], in the manner of
+    /// Pandoc's table normalization:
+    ///
+    /// * Spans less than 1 become 1, and spans which overlap another cell or
+    ///   extend past the end of their part of the table are shortened.
+    /// * Rows are padded with empty cells to the number of columns, and cells
+    ///   past the last column are dropped.
+    /// * The number of row head columns of each body is clamped to the number
+    ///   of columns.
+    /// * Invalid column widths become `ColWidthDefault`, and widths which add
+    ///   up to more than 1 are scaled down proportionally.
+    ///
+    /// A table without column specifications gets default ones for as many
+    /// columns as its widest row.
+    pub fn normalize(&mut self) {
+        let mut grid = TableGrid::from_table(self.clone());
+
+        let columns = grid.colspecs.len();
+        if columns > 0 {
+            for row in grid.rows.iter_mut() {
+                row.slots.truncate(columns);
+            }
+        }
+        let columns = grid.width();
+
+        for body in grid.bodies.iter_mut() {
+            body.row_head_cols = body.row_head_cols.clamp(0, columns as i32);
+        }
+
+        let mut total = 0.0;
+        for colspec in grid.colspecs.iter_mut() {
+            if let ColWidth::Percent(width) = colspec.col_width {
+                if width.is_finite() && width >= 0.0 {
+                    total += width;
+                } else {
+                    colspec.col_width = ColWidth::Default;
+                }
+            }
+        }
+        if total > 1.0 {
+            for colspec in grid.colspecs.iter_mut() {
+                if let ColWidth::Percent(width) = &mut colspec.col_width {
+                    *width /= total;
+                }
+            }
+        }
+
+        *self = grid.into_table();
+    }
+}
+
+#[test]
+fn test_validate() {
+    let cell =
+        |rows: i32, cols: i32| ::serde_json::json!([["",[],[]],{"t":"AlignDefault"},rows,cols,[]]);
+    let row = |cells: Vec<::serde_json::Value>| ::serde_json::json!([["", [], []], cells]);
+    let colspec =
+        |width: f64| ::serde_json::json!([{"t":"AlignDefault"},{"t":"ColWidth","c":width}]);
+    let input = ::serde_json::json!({"t":"Table","c":[
+        ["",[],[]],
+        [null,[]],
+        [colspec(0.8), colspec(0.4)],
+        [["",[],[]],[row(vec![cell(1, 1), cell(1, 1), cell(1, 1)])]],
+        [[["",[],[]],5,[],[
+            row(vec![cell(3, 1), cell(1, 0)]),
+            row(vec![])
+        ]]],
+        [["",[],[]],[]]
+    ]});
+    let block: Block<NullScheme> = ::serde_json::from_str(&input.to_string()).unwrap();
+    let BlockContent::Table(mut table) = block.content else {
+        unreachable!()
+    };
+
+    let at = |row, cell| CellPosition {
+        part: TablePart::Body(0),
+        row,
+        cell,
+    };
+    let diagnostics = table.validate();
+    ::std::assert_eq!(
+        diagnostics,
+        vec![
+            TableDiagnostic::SpanPastEnd(at(0, 0)),
+            TableDiagnostic::InvalidSpan(at(0, 1)),
+            TableDiagnostic::RowTooLong {
+                part: TablePart::Head,
+                row: 0,
+                columns: 3
+            },
+            TableDiagnostic::RowTooShort {
+                part: TablePart::Body(0),
+                row: 1,
+                columns: 1
+            },
+            TableDiagnostic::RowHeadColumns {
+                body: 0,
+                row_head_cols: 5
+            },
+            TableDiagnostic::WidthsExceedOne { total: 0.8 + 0.4 },
+        ]
+    );
+    ::std::assert_eq!(
+        diagnostics[0].to_string(),
+        "cell 0 of row 0 of body 0 spans past the end of body 0"
+    );
+
+    table.normalize();
+    ::std::assert_eq!(table.validate(), vec![]);
+    ::std::assert_eq!(table.head.rows[0].cells.len(), 2);
+    ::std::assert_eq!(table.body[0].rows[0].cells[0].row_span, 2);
+    ::std::assert_eq!(table.body[0].rows[1].cells.len(), 1);
+    ::std::assert_eq!(table.body[0].row_head_cols, 2);
+}
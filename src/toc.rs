@@ -0,0 +1,183 @@
+//! Tables of contents
+
+use imbl::Vector;
+
+use crate::ast::*;
+use crate::section::{Section, SectionTree};
+use crate::visit::Map;
+
+/// Build a table of contents for a document, as Pandoc's `--toc` does.
+///
+/// The result is a nested `BulletList` in which each item links to a
+/// header's identifier. Headers deeper than `depth`, and headers with the
+/// class `unlisted`, are omitted along with their subsections. If a header
+/// has a `number` attribute and is not `unnumbered`, the number precedes its
+/// text in a `Span` with class `toc-section-number`. Notes and links are
+/// removed from the text of each entry.
+///
+/// As with Pandoc, only headers at the top level of the document are
+/// included; see [`SectionTree`]. Returns `None` if there are no entries.
+pub fn toc<A>(doc: &Pandoc<A>, depth: i32) -> Option<Block<A>>
+where
+    A: DecorationScheme,
+{
+    toc_list(
+        &SectionTree::from_blocks(doc.blocks.clone()).sections,
+        depth,
+    )
+}
+
+impl<A> Pandoc<A>
+where
+    A: DecorationScheme,
+{
+    /// Replace the content of the placeholder `Div` with identifier `toc` by
+    /// the document's table of contents. See [`toc`].
+    ///
+    /// Returns whether a placeholder was found.
+    pub fn insert_toc(&mut self, depth: i32) -> bool {
+        let mut placeholder = TocPlaceholder {
+            list: toc(self, depth),
+            found: false,
+        };
+        let blocks = std::mem::take(&mut self.blocks);
+        self.blocks = blocks.walk(&mut placeholder);
+        placeholder.found
+    }
+}
+
+/// Fills the first `Div#toc` with a table of contents.
+struct TocPlaceholder<A>
+where
+    A: DecorationScheme,
+{
+    list: Option<Block<A>>,
+    found: bool,
+}
+
+impl<A> Map<A> for TocPlaceholder<A>
+where
+    A: DecorationScheme,
+{
+    fn map_block(&mut self, mut block: Block<A>) -> Block<A> {
+        if let BlockContent::Div(attr, content) = &mut block.content {
+            if !self.found && attr.identifier.as_ref() == "toc" {
+                *content = Blocks::from_iter(self.list.take());
+                self.found = true;
+            }
+        }
+        block
+    }
+}
+
+fn toc_list<A>(sections: &Vector<Section<A>>, depth: i32) -> Option<Block<A>>
+where
+    A: DecorationScheme,
+{
+    let items = Vec::from_iter(sections.iter().filter_map(|s| toc_item(s, depth)));
+    if items.is_empty() {
+        None
+    } else {
+        Some(Block::bullet_list(items))
+    }
+}
+
+fn toc_item<A>(section: &Section<A>, depth: i32) -> Option<Blocks<A>>
+where
+    A: DecorationScheme,
+{
+    let attr = &section.attr;
+    if section.level > depth || attr.has_class("unlisted") {
+        return None;
+    }
+
+    let mut text = toc_text(&section.title);
+    let number = attr
+        .get_str("number")
+        .filter(|n| !n.is_empty() && !attr.has_class("unnumbered"));
+    if let Some(number) = number {
+        let span = Inline::span(
+            Attr::builder().class("toc-section-number").build(),
+            Inline::str(&number),
+        );
+        text = Inlines::from_iter([span.into(), Inline::space().into(), text]);
+    }
+
+    let mut item = Vector::new();
+    if !text.expand().is_empty() {
+        if attr.identifier.is_empty() {
+            item.push_back(Block::plain(text));
+        } else {
+            let url = format!("#{}", attr.identifier);
+            item.push_back(Block::plain(Inline::link(Attr::default(), text, &url, "")));
+        }
+    }
+    item.extend(toc_list(&section.children, depth));
+
+    if item.is_empty() {
+        None
+    } else {
+        Some(item.into())
+    }
+}
+
+/// A header's text with notes removed and links replaced by their content.
+fn toc_text<A>(title: &Inlines<A>) -> Inlines<A>
+where
+    A: DecorationScheme,
+{
+    title.clone().walk(&mut TocText)
+}
+
+/// Removes notes and replaces links by their content.
+struct TocText;
+
+impl<A> Map<A> for TocText
+where
+    A: DecorationScheme,
+{
+    fn map_inlines(&mut self, mut inlines: Inlines<A>) -> Inlines<A> {
+        let content = inlines.expand();
+        content.retain(|i| !matches!(i.content, InlineContent::Note(_)));
+        for inline in content.iter_mut() {
+            if let InlineContent::Link(_, link_text, _) = &mut inline.content {
+                let link_text = std::mem::take(link_text);
+                inline.content = InlineContent::Span(Attr::default(), link_text);
+            }
+        }
+        inlines
+    }
+}
+
+#[test]
+fn test_toc() {
+    let input = r#"{"pandoc-api-version":[1,23,1],"meta":{},"blocks":[
+        {"t":"Div","c":[["toc",[],[]],[]]},
+        {"t":"Header","c":[1,["intro",[],[["number","1"]]],[{"t":"Str","c":"Intro"},{"t":"Note","c":[]}]]},
+        {"t":"Header","c":[2,["details",[],[]],[{"t":"Link","c":[["",[],[]],[{"t":"Str","c":"Details"}],["http://example.com",""]]}]]},
+        {"t":"Header","c":[3,["deep",[],[]],[{"t":"Str","c":"Deep"}]]},
+        {"t":"Header","c":[1,["hidden",["unlisted"],[]],[{"t":"Str","c":"Hidden"}]]}
+    ]}"#;
+    let mut doc: Pandoc<NullScheme> = ::serde_json::from_str(input).unwrap();
+    ::std::assert!(doc.insert_toc(2));
+
+    let output = ::serde_json::to_value(&doc.blocks.content[0]).unwrap();
+    let link = |id: &str, text: ::serde_json::Value| {
+        ::serde_json::json!({"t":"Link","c":[["",[],[]],text,[format!("#{}", id),""]]})
+    };
+    ::std::assert_eq!(
+        output,
+        ::serde_json::json!({"t":"Div","c":[["toc",[],[]],[{"t":"BulletList","c":[[
+            {"t":"Plain","c":[link("intro", ::serde_json::json!([
+                {"t":"Span","c":[["",["toc-section-number"],[]],[{"t":"Str","c":"1"}]]},
+                {"t":"Space"},
+                {"t":"Str","c":"Intro"}
+            ]))]},
+            {"t":"BulletList","c":[[
+                {"t":"Plain","c":[link("details", ::serde_json::json!([
+                    {"t":"Span","c":[["",[],[]],[{"t":"Str","c":"Details"}]]}
+                ]))]}
+            ]]}
+        ]]}]]})
+    );
+}
@@ -1,6 +1,12 @@
 use crate::ast::*;
 use imbl::{HashMap, Vector};
 
+/// A transformation of a document, applied by the `walk` methods.
+///
+/// Walking a node calls its `enter_` method, walks its children, and then
+/// calls its `map_` method on the node rebuilt from the walked children. Nodes
+/// are therefore entered in document order and mapped bottom-up.
+#[allow(unused_variables)]
 pub trait Map<A>
 where
     A: DecorationScheme,
@@ -89,26 +95,46 @@ where
     fn map_caption_decoration(&self, input: A::Caption) -> B::Caption;
 }
 
+/// A read-only traversal of a document, applied by the `query` methods.
+///
+/// Querying a node calls its `enter_` method, queries its children, and then
+/// calls its `query_` method, so nodes are entered in document order and
+/// queried bottom-up. The nodes borrow from the document for `'a`.
 #[allow(unused_variables)]
-pub trait Query<A>
+pub trait Query<'a, A>
 where
     A: DecorationScheme,
 {
-    fn query_pandoc(&mut self, pandoc: &Pandoc<A>) {}
-    fn query_meta_value(&mut self, meta: &MetaValue<A>) {}
-    fn query_blocks(&mut self, blocks: &Blocks<A>) {}
-    fn query_block(&mut self, block: &Block<A>) {}
-    fn query_inlines(&mut self, inlines: &Inlines<A>) {}
-    fn query_inline_condensed(&mut self, inline: &Inline<A, CondensedLeaf>) {}
-    fn query_inline_expanded(&mut self, inline: &Inline<A, ExpandedLeaf>) {}
-    fn query_citation(&mut self, citation: &Citation<A>) {}
-    fn query_table(&mut self, table: &Table<A>) {}
-    fn query_table_head(&mut self, head: &TableHead<A>) {}
-    fn query_table_body(&mut self, body: &TableBody<A>) {}
-    fn query_table_foot(&mut self, foot: &TableFoot<A>) {}
-    fn query_row(&mut self, row: &Row<A>) {}
-    fn query_cell(&mut self, cell: &Cell<A>) {}
-    fn query_caption(&mut self, caption: &Caption<A>) {}
+    fn enter_pandoc(&mut self, pandoc: &'a Pandoc<A>) {}
+    fn enter_meta_value(&mut self, meta: &'a MetaValue<A>) {}
+    fn enter_blocks(&mut self, blocks: &'a Blocks<A>) {}
+    fn enter_block(&mut self, block: &'a Block<A>) {}
+    fn enter_inlines(&mut self, inlines: &'a Inlines<A>) {}
+    fn enter_inline_condensed(&mut self, inline: &'a Inline<A, CondensedLeaf>) {}
+    fn enter_inline_expanded(&mut self, inline: &'a Inline<A, ExpandedLeaf>) {}
+    fn enter_citation(&mut self, citation: &'a Citation<A>) {}
+    fn enter_table(&mut self, table: &'a Table<A>) {}
+    fn enter_table_head(&mut self, head: &'a TableHead<A>) {}
+    fn enter_table_body(&mut self, body: &'a TableBody<A>) {}
+    fn enter_table_foot(&mut self, foot: &'a TableFoot<A>) {}
+    fn enter_row(&mut self, row: &'a Row<A>) {}
+    fn enter_cell(&mut self, cell: &'a Cell<A>) {}
+    fn enter_caption(&mut self, caption: &'a Caption<A>) {}
+    fn query_pandoc(&mut self, pandoc: &'a Pandoc<A>) {}
+    fn query_meta_value(&mut self, meta: &'a MetaValue<A>) {}
+    fn query_blocks(&mut self, blocks: &'a Blocks<A>) {}
+    fn query_block(&mut self, block: &'a Block<A>) {}
+    fn query_inlines(&mut self, inlines: &'a Inlines<A>) {}
+    fn query_inline_condensed(&mut self, inline: &'a Inline<A, CondensedLeaf>) {}
+    fn query_inline_expanded(&mut self, inline: &'a Inline<A, ExpandedLeaf>) {}
+    fn query_citation(&mut self, citation: &'a Citation<A>) {}
+    fn query_table(&mut self, table: &'a Table<A>) {}
+    fn query_table_head(&mut self, head: &'a TableHead<A>) {}
+    fn query_table_body(&mut self, body: &'a TableBody<A>) {}
+    fn query_table_foot(&mut self, foot: &'a TableFoot<A>) {}
+    fn query_row(&mut self, row: &'a Row<A>) {}
+    fn query_cell(&mut self, cell: &'a Cell<A>) {}
+    fn query_caption(&mut self, caption: &'a Caption<A>) {}
 }
 
 impl<A> Pandoc<A>
@@ -119,11 +145,13 @@ where
     where
         M: Map<A>,
     {
-        map.map_pandoc(Pandoc {
+        map.enter_pandoc(&self);
+        let pandoc = Pandoc {
             decoration: self.decoration,
             meta: HashMap::from_iter(self.meta.into_iter().map(|(k, v)| (k, v.walk(map)))),
             blocks: self.blocks.walk(map),
-        })
+        };
+        map.map_pandoc(pandoc)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Pandoc<B>
@@ -142,10 +170,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_pandoc(self);
         for v in self.meta.values() {
             v.query(query);
         }
@@ -159,11 +188,12 @@ impl<A> MetaValue<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_meta_value(MetaValue {
+        map.enter_meta_value(&self);
+        let meta = MetaValue {
             decoration: self.decoration,
             content: match self.content {
                 MetaValueContent::Map(m) => MetaValueContent::Map(HashMap::from_iter(
@@ -177,7 +207,8 @@ where
                 MetaValueContent::Inlines(i) => MetaValueContent::Inlines(i.walk(map)),
                 MetaValueContent::Blocks(b) => MetaValueContent::Blocks(b.walk(map)),
             },
-        })
+        };
+        map.map_meta_value(meta)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> MetaValue<B>
@@ -202,10 +233,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_meta_value(self);
         match &self.content {
             MetaValueContent::Map(m) => {
                 for v in m.values() {
@@ -231,14 +263,16 @@ impl<A> Blocks<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_blocks(Blocks {
+        map.enter_blocks(&self);
+        let blocks = Blocks {
             decoration: self.decoration,
             content: Vector::from_iter(self.content.into_iter().map(|v| v.walk(map))),
-        })
+        };
+        map.map_blocks(blocks)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Blocks<B>
@@ -252,10 +286,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_blocks(self);
         for b in &self.content {
             b.query(query);
         }
@@ -268,11 +303,12 @@ impl<A> Block<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_block(Block {
+        map.enter_block(&self);
+        let block = Block {
             decoration: self.decoration,
             content: match self.content {
                 BlockContent::Plain(i) => BlockContent::Plain(i.walk(map)),
@@ -304,7 +340,8 @@ where
                 BlockContent::Figure(a, c, b) => BlockContent::Figure(a, c.walk(map), b.walk(map)),
                 BlockContent::Div(a, bs) => BlockContent::Div(a, bs.walk(map)),
             },
-        })
+        };
+        map.map_block(block)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Block<B>
@@ -351,10 +388,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_block(self);
         match &self.content {
             BlockContent::Plain(i) => {
                 i.query(query);
@@ -411,11 +449,12 @@ impl<A> Inlines<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_inlines(Inlines {
+        map.enter_inlines(&self);
+        let inlines = Inlines {
             decoration: self.decoration,
             content: match self.content {
                 InlinesContent::Condensed(is) => InlinesContent::Condensed(Vector::from_iter(
@@ -425,7 +464,8 @@ where
                     InlinesContent::Expanded(Vector::from_iter(is.into_iter().map(|i| i.walk(map))))
                 }
             },
-        })
+        };
+        map.map_inlines(inlines)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Inlines<B>
@@ -446,10 +486,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_inlines(self);
         match &self.content {
             InlinesContent::Condensed(is) => {
                 for i in is {
@@ -471,17 +512,20 @@ impl<A> Inline<A, CondensedLeaf>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_inline_condensed(self.walk_general(map))
+        map.enter_inline_condensed(&self);
+        let inline = self.walk_general(map);
+        map.map_inline_condensed(inline)
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_inline_condensed(self);
         self.query_general(query);
         query.query_inline_condensed(self);
     }
@@ -491,17 +535,20 @@ impl<A> Inline<A, ExpandedLeaf>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_inline_expanded(self.walk_general(map))
+        map.enter_inline_expanded(&self);
+        let inline = self.walk_general(map);
+        map.map_inline_expanded(inline)
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_inline_expanded(self);
         self.query_general(query);
         query.query_inline_expanded(self);
     }
@@ -511,7 +558,7 @@ impl<A, L> Inline<A, L>
 where
     A: DecorationScheme,
 {
-    fn walk_general<M>(self, map: &M) -> Self
+    fn walk_general<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
@@ -580,9 +627,9 @@ where
         }
     }
 
-    fn query_general<Q>(&self, query: &mut Q)
+    fn query_general<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
         match &self.content {
             InlineContent::Emph(is) => {
@@ -636,11 +683,12 @@ impl<A> Citation<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_citation(Citation {
+        map.enter_citation(&self);
+        let citation = Citation {
             decoration: self.decoration,
             id: self.id,
             prefix: self.prefix.walk(map),
@@ -648,7 +696,8 @@ where
             mode: self.mode,
             num: self.num,
             hash: self.hash,
-        })
+        };
+        map.map_citation(citation)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Citation<B>
@@ -667,10 +716,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_citation(self);
         self.prefix.query(query);
         self.suffix.query(query);
         query.query_citation(self);
@@ -681,11 +731,12 @@ impl<A> Table<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_table(Table {
+        map.enter_table(&self);
+        let table = Table {
             decoration: self.decoration,
             attrs: self.attrs,
             caption: self.caption.walk(map),
@@ -693,7 +744,8 @@ where
             head: self.head.walk(map),
             body: Vector::from_iter(self.body.into_iter().map(|b| b.walk(map))),
             foot: self.foot.walk(map),
-        })
+        };
+        map.map_table(table)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Table<B>
@@ -712,10 +764,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_table(self);
         self.caption.query(query);
         self.head.query(query);
         for b in &self.body {
@@ -730,15 +783,17 @@ impl<A> TableHead<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_table_head(TableHead {
+        map.enter_table_head(&self);
+        let head = TableHead {
             decoration: self.decoration,
             attrs: self.attrs,
             rows: Vector::from_iter(self.rows.into_iter().map(|row| row.walk(map))),
-        })
+        };
+        map.map_table_head(head)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> TableHead<B>
@@ -753,10 +808,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_table_head(self);
         for row in &self.rows {
             row.query(query);
         }
@@ -768,11 +824,12 @@ impl<A> TableBody<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_table_body(TableBody {
+        map.enter_table_body(&self);
+        let body = TableBody {
             decoration: self.decoration,
             attrs: self.attrs,
             row_head_cols: self.row_head_cols,
@@ -780,7 +837,8 @@ where
                 self.intermediate_head.into_iter().map(|row| row.walk(map)),
             ),
             rows: Vector::from_iter(self.rows.into_iter().map(|row| row.walk(map))),
-        })
+        };
+        map.map_table_body(body)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> TableBody<B>
@@ -801,10 +859,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_table_body(self);
         for row in &self.intermediate_head {
             row.query(query);
         }
@@ -821,15 +880,17 @@ impl<A> TableFoot<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_table_foot(TableFoot {
+        map.enter_table_foot(&self);
+        let foot = TableFoot {
             decoration: self.decoration,
             attrs: self.attrs,
             rows: Vector::from_iter(self.rows.into_iter().map(|row| row.walk(map))),
-        })
+        };
+        map.map_table_foot(foot)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> TableFoot<B>
@@ -844,10 +905,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_table_foot(self);
         for row in &self.rows {
             row.query(query);
         }
@@ -859,15 +921,17 @@ impl<A> Row<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_row(Row {
+        map.enter_row(&self);
+        let row = Row {
             decoration: self.decoration,
             attrs: self.attrs,
             cells: Vector::from_iter(self.cells.into_iter().map(|cell| cell.walk(map))),
-        })
+        };
+        map.map_row(row)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Row<B>
@@ -886,10 +950,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_row(self);
         for cell in &self.cells {
             cell.query(query);
         }
@@ -901,18 +966,20 @@ impl<A> Cell<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_cell(Cell {
+        map.enter_cell(&self);
+        let cell = Cell {
             decoration: self.decoration,
             attrs: self.attrs,
             alignment: self.alignment,
             row_span: self.row_span,
             col_span: self.col_span,
             blocks: self.blocks.walk(map),
-        })
+        };
+        map.map_cell(cell)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Cell<B>
@@ -930,10 +997,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_cell(self);
         self.blocks.query(query);
         query.query_cell(self);
     }
@@ -943,15 +1011,17 @@ impl<A> Caption<A>
 where
     A: DecorationScheme,
 {
-    pub fn walk<M>(self, map: &M) -> Self
+    pub fn walk<M>(self, map: &mut M) -> Self
     where
         M: Map<A>,
     {
-        map.map_caption(Caption {
+        map.enter_caption(&self);
+        let caption = Caption {
             decoration: self.decoration,
             short: self.short.map(|is| is.walk(map)),
             full: self.full.walk(map),
-        })
+        };
+        map.map_caption(caption)
     }
 
     pub fn walk_decorations<M, B>(self, map: &M) -> Caption<B>
@@ -966,10 +1036,11 @@ where
         }
     }
 
-    pub fn query<Q>(&self, query: &mut Q)
+    pub fn query<'a, Q>(&'a self, query: &mut Q)
     where
-        Q: Query<A>,
+        Q: Query<'a, A>,
     {
+        query.enter_caption(self);
         if let Some(is) = &self.short {
             is.query(query);
         }